@@ -0,0 +1,73 @@
+//! Internal cfg aliases for the groups of formats that share helpers. A new format joins
+//! the groups it needs here, rather than dozens of `#[cfg(any(feature = ...))]` lists
+
+use std::env;
+
+/// An alias is set when any of its features is enabled
+const ALIASES: &[(&str, &[&str])] = &[
+    // every format
+    (
+        "any_format",
+        &[
+            "ar", "bz2", "gz", "lha", "rar", "tarball", "warc", "xz", "zip", "zstd",
+        ],
+    ),
+    // formats with several entries
+    ("archive", &["ar", "lha", "rar", "tarball", "warc", "zip"]),
+    // formats whose entries we write out ourselves, rather than through a library
+    ("writes_entries", &["ar", "lha", "tarball", "warc", "zip"]),
+    // formats whose entry names are checked for escapes as they are read
+    ("checks_paths", &["ar", "lha", "rar", "tarball", "zip"]),
+    // formats that report entries to `on_entry` and count them for `max_entries`
+    (
+        "notifies_entries",
+        &["lha", "rar", "tarball", "warc", "zip"],
+    ),
+    // formats that honor `resume_manifest`, `max_path_depth` and `set_all_mtimes`
+    ("records_entries", &["rar", "tarball", "zip"]),
+    // formats holding whole trees: folders, links, modes and times
+    ("extracts_trees", &["tarball", "zip"]),
+    // formats read through to the end by `verify`
+    (
+        "drains",
+        &["ar", "bz2", "gz", "lha", "tarball", "xz", "zip", "zstd"],
+    ),
+    // formats whose entries are read from headers through `io::Read`
+    ("reads_headers", &["ar", "tarball"]),
+    // formats whose entry names may be in a legacy encoding, for `filename_encoding`
+    ("decodes_names", &["lha", "zip"]),
+    // formats with entry times
+    ("has_mtimes", &["ar", "gz", "lha", "tarball", "zip"]),
+    // formats honoring `number_entries`
+    ("numbers_entries", &["ar", "tarball", "warc", "zip"]),
+    // formats with unix modes
+    ("has_modes", &["ar", "tarball", "zip"]),
+    // formats honoring `allowed_extensions`
+    ("filters_extensions", &["tarball", "warc", "zip"]),
+    // formats with compressed sizes for `max_compression_ratio`
+    ("checks_ratio", &["lha", "tarball", "zip"]),
+    // formats read through a decoder
+    (
+        "decodes",
+        &[
+            "bz2", "gz", "xz", "zstd", "targz", "tarbz", "tarxz", "tarzst", "warcgz",
+        ],
+    ),
+    // formats decoding zstd
+    ("zstd_codec", &["tarzst", "zstd"]),
+    // tarballs in a compression format
+    ("compressed_tar", &["targz", "tarbz", "tarxz", "tarzst"]),
+];
+
+fn main() {
+    for (alias, features) in ALIASES {
+        println!("cargo:rustc-check-cfg=cfg({alias})");
+        let enabled = features.iter().any(|feature| {
+            env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some()
+        });
+        if enabled {
+            println!("cargo:rustc-cfg={alias}");
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
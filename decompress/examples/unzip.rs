@@ -7,7 +7,7 @@ use regex::Regex;
 fn main() {
     let matches = command!()
         .arg(arg!(<archive> "Archive to Unzip (attempt any file)"))
-        .arg(arg!(<out> "Output folder"))
+        .arg(arg!(<out> "Output folder, or `-` to write a single-file format (gz, bz2, xz, zst) to stdout"))
         .arg(arg!(
             -s --strip "Strip the first component of the archive"
         ))
@@ -16,10 +16,23 @@ fn main() {
     let archive = matches.get_one::<String>("archive").expect("required");
     let to = matches.get_one::<String>("out").expect("required");
     let strip = usize::from(matches.get_flag("strip"));
+
+    if to == "-" {
+        let res = decompress::Decompress::default().decompress_to_writer(
+            archive,
+            &mut std::io::stdout().lock(),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        );
+        // stdout carries the contents, so the outcome goes to stderr
+        eprintln!("{res:?}");
+        std::process::exit(i32::from(res.is_err()));
+    }
+
     let decompressor = decompress::Decompress::build(vec![decompressors::zip::Zip::build(Some(
         Regex::new(r".*").unwrap(),
     ))]);
 
+    println!("format: {:?}", decompressor.matching_id(archive));
     let res = decompressor.decompress(
         archive,
         to,
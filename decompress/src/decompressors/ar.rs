@@ -1,10 +1,16 @@
-use crate::decompressors::utils::normalize_mode;
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::utils::{
+    check_path, create_dir_all, create_file, drain, entry_error, is_unsafe_path, matches_name,
+    normalize_mode, numbered, resolve_conflict, system_time, write_contents,
+};
+use crate::{
+    DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing,
+    ListingEntry, ListingIter, RelPath, RelPathKind,
+};
 use ar::Archive;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::path::{Component, PathBuf};
-use std::{fs, io};
+use std::fs;
+use std::path::PathBuf;
 use std::{
     fs::File,
     io::{BufReader, Read},
@@ -20,75 +26,130 @@ fn build_archive(archive: &Path) -> Result<Archive<Box<dyn Read>>, DecompressErr
     let out: Archive<Box<dyn Read>> = Archive::new(Box::new(fd));
     Ok(out)
 }
+/// Member headers, with the size and mode stored in them. Members are all files, as
+/// ar has no folders
+fn ar_list(archive: &Path, opts: &ExtractOpts) -> Result<Vec<ListingEntry>, DecompressError> {
+    let mut out = build_archive(archive)?;
+    let mut entries = vec![];
+    while let Some(entry) = out.next_entry() {
+        let entry = entry.map_err(entry_error)?;
+        let header = entry.header();
+
+        let filepath = {
+            #[cfg(windows)]
+            {
+                PathBuf::from(String::from_utf8_lossy(header.identifier()).to_string())
+            }
+            #[cfg(unix)]
+            {
+                use std::ffi::OsStr;
+                use std::os::unix::prelude::OsStrExt;
+                PathBuf::from(OsStr::from_bytes(header.identifier()))
+            }
+        };
+        check_path(&filepath, opts)?;
+        entries.push(ListingEntry {
+            path: RelPath::new(filepath),
+            kind: RelPathKind::File,
+            size: Some(header.size()),
+            compressed_size: None,
+            comment: None,
+            mode: Some(header.mode()),
+            is_name_lossy: std::str::from_utf8(header.identifier()).is_err(),
+        });
+    }
+    Ok(entries)
+}
+
 #[derive(Default)]
 pub struct Ar {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Ar {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Ar {
+    fn id(&self) -> &'static str {
+        "ar"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/x-unix-archive"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
+    }
+
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        let entries = ar_list(archive, opts)?
+            .into_iter()
+            .map(|entry| entry.path.as_path().to_string_lossy().to_string())
+            .collect();
+        Ok(Listing { id: "ar", entries })
+    }
+
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        Ok(Box::new(ar_list(archive, opts)?.into_iter().map(Ok)))
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
         let mut out = build_archive(archive)?;
-        let mut entries = vec![];
         while let Some(entry) = out.next_entry() {
-            let entry = entry?;
-            let header = entry.header();
-
-            let filepath = {
-                #[cfg(windows)]
-                {
-                    PathBuf::from(String::from_utf8_lossy(header.identifier()).to_string())
-                }
-                #[cfg(unix)]
-                {
-                    use std::ffi::OsStr;
-                    use std::os::unix::prelude::OsStrExt;
-                    PathBuf::from(OsStr::from_bytes(header.identifier()))
-                }
-            };
-            entries.push(filepath.to_string_lossy().to_string());
+            let mut entry = entry.map_err(entry_error)?;
+            let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+            drain(&mut entry, &name)?;
         }
-        Ok(Listing { id: "ar", entries })
+        Ok(())
     }
 
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
-        _opts: &ExtractOpts,
+        opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
         let mut out = build_archive(archive)?;
         let mut files = vec![];
 
         if !to.exists() {
-            fs::create_dir_all(to)?;
+            create_dir_all(to, opts)?;
         }
 
         // alternative impl: just unpack, and then mv everything back X levels
+        let mut count = 0;
         while let Some(entry) = out.next_entry() {
-            let entry = entry?;
+            let entry = entry.map_err(entry_error)?;
             let header = entry.header();
+            let index = count;
+            count += 1;
 
             let filepath = {
                 #[cfg(windows)]
@@ -103,10 +164,8 @@ impl Decompressor for Ar {
                 }
             };
 
-            if filepath.components().any(|component| match component {
-                Component::ParentDir | Component::RootDir | Component::Prefix(..) => true,
-                Component::Normal(..) | Component::CurDir => false,
-            }) {
+            check_path(&filepath, opts)?;
+            if is_unsafe_path(&filepath) {
                 continue;
             }
 
@@ -116,17 +175,29 @@ impl Decompressor for Ar {
             // because we potentially stripped a component, we may have an empty path, in which case
             // the joined target will be identical to the target folder
             // we take this approach to avoid hardcoding a check against empty ""
-            let outpath = to.join(filepath);
+            let outpath = to.join(&filepath);
             if to == outpath {
                 continue;
             }
 
             #[cfg(unix)]
             let mode = entry.header().mode();
+            let mtime = entry.header().mtime();
 
-            let mut outfile = fs::File::create(&outpath)?;
-            io::copy(&mut BufReader::new(entry), &mut outfile)?;
-            files.push(outpath.to_string_lossy().to_string());
+            let outpath = numbered(opts, outpath.into(), RelPathKind::File, index);
+            let Some(outpath) = resolve_conflict(opts, outpath, RelPathKind::File)? else {
+                continue;
+            };
+            let mut outfile = create_file(opts, &outpath)?;
+            let (size, hash) =
+                write_contents(opts, &filepath, &mut BufReader::new(entry), &mut outfile)?;
+            outfile.persist()?;
+            files.push(ExtractedFile {
+                path: outpath.to_string_lossy().to_string(),
+                mtime: Some(system_time(mtime)),
+                size,
+                hash,
+            });
 
             #[cfg(unix)]
             {
@@ -135,6 +206,6 @@ impl Decompressor for Ar {
                 fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
             }
         }
-        Ok(Decompression { id: "ar", files })
+        Ok(Decompression::new("ar", to, files))
     }
 }
@@ -1,8 +1,9 @@
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::utils::{create_dir_all, drain, matches_name, DecoderReader};
+use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
 use std::{fs, io};
 
@@ -13,32 +14,47 @@ lazy_static! {
 #[derive(Default)]
 pub struct Bz2 {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Bz2 {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Bz2 {
+    fn id(&self) -> &'static str {
+        "bz2"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/x-bzip2"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         Ok(Listing {
             id: "bz2",
             entries: vec![archive
@@ -49,30 +65,66 @@ impl Decompressor for Bz2 {
         })
     }
 
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        drain(
+            &mut bzip2::bufread::MultiBzDecoder::new(fd),
+            &archive.to_string_lossy(),
+        )
+    }
+
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        _opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        Ok(io::copy(
+            &mut DecoderReader(bzip2::bufread::MultiBzDecoder::new(fd)),
+            writer,
+        )?)
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
-        _opts: &ExtractOpts,
+        opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        let fd = BufReader::new(File::open(archive)?);
-        let dec = bzip2::bufread::BzDecoder::new(fd);
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let fd = BufReader::new(reader);
+        let dec = DecoderReader(bzip2::bufread::MultiBzDecoder::new(fd));
 
         if !Path::new(to).exists() {
-            let _res = fs::create_dir_all(to);
+            let _res = create_dir_all(to, opts);
         }
 
         let target = to.join(
-            archive
-                .file_stem()
+            name.file_stem()
                 .ok_or_else(|| DecompressError::Error("cannot compose a file name".to_string()))?,
         );
         let mut outfile = fs::File::create(&target)?;
 
-        io::copy(&mut BufReader::new(dec), &mut outfile)?;
-        Ok(Decompression {
-            id: "bz2",
-            files: vec![target.to_string_lossy().to_string()],
-        })
+        let size = io::copy(&mut BufReader::new(dec), &mut outfile)?;
+        Ok(Decompression::new(
+            "bz2",
+            to,
+            vec![ExtractedFile {
+                path: target.to_string_lossy().to_string(),
+                mtime: None,
+                size,
+                hash: None,
+            }],
+        ))
     }
 }
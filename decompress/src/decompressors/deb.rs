@@ -0,0 +1,137 @@
+use crate::decompressors::tar_common::{tar_extract, tar_list, tar_verify};
+use crate::decompressors::utils::{entry_error, matches_name, zstd_decoder, DecoderReader};
+use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+lazy_static! {
+    static ref RE: Regex = Regex::new(r"(?i)\.deb$").unwrap();
+}
+
+/// The `data.tar` payload, and the id of the tar decompressor its format goes with
+type Payload<'a> = (tar::Archive<Box<dyn Read + 'a>>, &'static str);
+
+/// A `.deb` is an `ar` archive, with the package payload in a `data.tar.*` member.
+/// Only the payload is listed and extracted, `control.tar.*` is left alone.
+fn build_archive<'a>(
+    deb: &'a mut ar::Archive<File>,
+    opts: &ExtractOpts,
+) -> Result<Payload<'a>, DecompressError> {
+    let mut data = None;
+    let mut index = 0;
+    while let Some(entry) = deb.next_entry() {
+        let entry = entry.map_err(entry_error)?;
+        let name = String::from_utf8_lossy(entry.header().identifier());
+        let name = name.trim_end_matches('/');
+        if name.starts_with("data.tar") {
+            data = Some((index, name.to_string()));
+            break;
+        }
+        index += 1;
+    }
+    let (index, name) = data.ok_or_else(|| {
+        DecompressError::CorruptArchive("no `data.tar` member in deb package".to_string())
+    })?;
+
+    let entry = BufReader::new(deb.jump_to_entry(index)?);
+    let (rdr, id): (Box<dyn Read + 'a>, _) = match name.as_str() {
+        "data.tar" => (Box::new(entry), "tarball"),
+        "data.tar.gz" => (
+            Box::new(DecoderReader(flate2::bufread::GzDecoder::new(entry))),
+            "targz",
+        ),
+        "data.tar.xz" => (
+            Box::new(DecoderReader(xz::bufread::XzDecoder::new_multi_decoder(
+                entry,
+            ))),
+            "tarxz",
+        ),
+        "data.tar.zst" => (
+            Box::new(zstd_decoder(
+                entry,
+                opts.zstd_dictionary.as_deref(),
+                opts.zstd_window_log_max,
+            )?),
+            "tarzst",
+        ),
+        _ => {
+            return Err(DecompressError::Error(format!(
+                "unsupported deb payload: `{name}`"
+            )))
+        }
+    };
+    Ok((tar::Archive::new(rdr), id))
+}
+
+#[derive(Default)]
+pub struct Deb {
+    re: Option<Regex>,
+    extra: Option<Regex>,
+}
+
+impl Deb {
+    #[must_use]
+    pub fn new(re: Option<Regex>) -> Self {
+        Self { re, extra: None }
+    }
+    #[must_use]
+    pub fn build(re: Option<Regex>) -> Box<Self> {
+        Box::new(Self::new(re))
+    }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
+}
+
+impl Decompressor for Deb {
+    fn id(&self) -> &'static str {
+        "deb"
+    }
+
+    fn test_mimetype(&self, archive: &str) -> bool {
+        archive == "application/vnd.debian.binary-package"
+    }
+
+    fn test(&self, archive: &Path) -> bool {
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
+    }
+
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        let mut deb = ar::Archive::new(File::open(archive)?);
+        let (mut data, _) = build_archive(&mut deb, opts)?;
+        let entries = tar_list(&mut data, opts)?;
+        Ok(Listing { id: "deb", entries })
+    }
+
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        let mut deb = ar::Archive::new(File::open(archive)?);
+        let (data, _) = build_archive(&mut deb, opts)?;
+        tar_verify(data)
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let mut deb = ar::Archive::new(File::open(archive)?);
+        let (mut data, payload) = build_archive(&mut deb, opts)?;
+        Ok(tar_extract(&mut data, to, opts, self.id())?.with_format_chain(vec!["ar", payload]))
+    }
+}
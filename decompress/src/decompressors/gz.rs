@@ -1,8 +1,19 @@
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+#[cfg(feature = "tarball")]
+use crate::decompressors::tar_common;
+use crate::decompressors::utils::{
+    create_dir_all, drain, matches_name, system_time, DecoderReader,
+};
+use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{fs, io};
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::BufReader,
+    io::Read,
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"(?i)\.gz$").unwrap();
@@ -11,32 +22,47 @@ lazy_static! {
 #[derive(Default)]
 pub struct Gz {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Gz {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Gz {
+    fn id(&self) -> &'static str {
+        "gz"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/gzip"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         Ok(Listing {
             id: "gz",
             entries: vec![archive
@@ -47,28 +73,123 @@ impl Decompressor for Gz {
         })
     }
 
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        drain(
+            &mut flate2::bufread::MultiGzDecoder::new(fd),
+            &archive.to_string_lossy(),
+        )
+    }
+
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        _opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        Ok(io::copy(
+            &mut DecoderReader(flate2::bufread::MultiGzDecoder::new(fd)),
+            writer,
+        )?)
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
-        _opts: &ExtractOpts,
+        opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        let fd = BufReader::new(File::open(archive)?);
-        let dec = flate2::bufread::GzDecoder::new(fd);
-        if !Path::new(to).exists() {
-            let _res = fs::create_dir_all(to);
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let fd = BufReader::new(reader);
+        let mut dec = DecoderReader(flate2::bufread::MultiGzDecoder::new(fd));
+        let target = output_name(&dec.0, name, opts)?;
+
+        // peek at the first block, and hand off to tar if that's what it holds
+        #[cfg(feature = "tarball")]
+        if opts.auto_untar_gz {
+            let mut block = vec![];
+            (&mut dec).take(512).read_to_end(&mut block)?;
+            let rdr = io::Cursor::new(block).chain(&mut dec);
+            if tar_common::is_tar_header(rdr.get_ref().0.get_ref()) {
+                let mut archive = tar::Archive::new(rdr);
+                return Ok(tar_common::tar_extract(&mut archive, to, opts, self.id())?
+                    .with_format_chain(vec!["gz", "tarball"]));
+            }
+            let file = write_single(rdr, &target, to, opts)?;
+            return Ok(Decompression::new("gz", to, vec![with_mtime(file, &dec.0)]));
         }
-        let target = to.join(
-            archive
-                .file_stem()
-                .ok_or_else(|| DecompressError::Error("cannot compose a file name".to_string()))?,
-        );
-        let mut outfile = fs::File::create(&target)?;
+        let file = write_single(&mut dec, &target, to, opts)?;
+        Ok(Decompression::new("gz", to, vec![with_mtime(file, &dec.0)]))
+    }
+}
 
-        io::copy(&mut BufReader::new(dec), &mut outfile)?;
-        Ok(Decompression {
-            id: "gz",
-            files: vec![target.to_string_lossy().to_string()],
+/// The gzip header's mtime, when it has one (0 means it doesn't)
+fn with_mtime<R>(file: ExtractedFile, dec: &flate2::bufread::MultiGzDecoder<R>) -> ExtractedFile {
+    let mtime = dec
+        .header()
+        .map(flate2::GzHeader::mtime)
+        .filter(|mtime| *mtime != 0)
+        .map(|mtime| system_time(u64::from(mtime)));
+    ExtractedFile { mtime, ..file }
+}
+
+/// Where to write the decompressed file, relative to the output folder: the name stored
+/// in the gzip header under `use_gzip_original_name` (after `strip`, and only its plain
+/// components), or else the archive's name without its `.gz`
+fn output_name<R>(
+    dec: &flate2::bufread::MultiGzDecoder<R>,
+    name: &Path,
+    opts: &ExtractOpts,
+) -> Result<PathBuf, DecompressError> {
+    let original = dec
+        .header()
+        .and_then(flate2::GzHeader::filename)
+        .filter(|_| opts.use_gzip_original_name)
+        .map(|original| {
+            Path::new(String::from_utf8_lossy(original).as_ref())
+                .components()
+                .filter(|part| matches!(part, Component::Normal(_)))
+                .skip(opts.strip)
+                .collect::<PathBuf>()
         })
+        .filter(|original| !original.as_os_str().is_empty());
+    if let Some(original) = original {
+        return Ok(original);
+    }
+    Ok(PathBuf::from(name.file_stem().ok_or_else(|| {
+        DecompressError::Error("cannot compose a file name".to_string())
+    })?))
+}
+
+fn write_single(
+    dec: impl Read,
+    target: &Path,
+    to: &Path,
+    opts: &ExtractOpts,
+) -> Result<ExtractedFile, DecompressError> {
+    let target = to.join(target);
+    if let Some(parent) = target.parent() {
+        if !parent.exists() {
+            let _res = create_dir_all(parent, opts);
+        }
     }
+    let mut outfile = fs::File::create(&target)?;
+
+    let size = io::copy(&mut BufReader::new(dec), &mut outfile)?;
+    Ok(ExtractedFile {
+        path: target.to_string_lossy().to_string(),
+        mtime: None,
+        size,
+        hash: None,
+    })
 }
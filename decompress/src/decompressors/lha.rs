@@ -0,0 +1,677 @@
+use crate::decompressors::utils::{
+    check_entry_limit, check_path, check_ratio, create_dir_all, create_file, drain, is_unsafe_path,
+    map_path, matches_name, notify_entry, rename, resolve_conflict, system_time, write_contents,
+};
+use crate::{
+    DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing, RelPathKind,
+};
+use encoding_rs::Encoding;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    static ref RE: Regex = Regex::new(r"(?i)\.(lzh|lha)$").unwrap();
+}
+
+fn corrupt(msg: &str) -> DecompressError {
+    DecompressError::CorruptArchive(format!("lha: {msg}"))
+}
+
+/// An entry's header, the reader is left at the start of its packed data
+struct Header {
+    method: [u8; 5],
+    packed: u64,
+    original: u64,
+    /// Unix time, level 0 and 1 headers store a DOS time unless they carry an extension
+    mtime: Option<u64>,
+    path: PathBuf,
+    crc: u16,
+}
+
+impl Header {
+    fn kind(&self) -> RelPathKind {
+        if &self.method == b"-lhd-" {
+            RelPathKind::Dir
+        } else {
+            RelPathKind::File
+        }
+    }
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// A timezone-less DOS time, which we take as UTC
+fn dos_time(stamp: u32) -> Option<u64> {
+    let (date, time) = (i64::from(stamp >> 16), i64::from(stamp & 0xffff));
+    let days = days_from_civil((date >> 9) + 1980, (date >> 5) & 0xf, date & 0x1f);
+    let secs = days * 86_400 + (time >> 11) * 3600 + ((time >> 5) & 0x3f) * 60 + (time & 0x1f) * 2;
+    u64::try_from(secs).ok()
+}
+
+/// A stored name as a relative path. `0xff` and `\` both separate folders, `\` only
+/// once decoded since it can be the second byte of a Shift-JIS character
+fn decode_name(raw: &[u8], encoding: Option<&'static Encoding>) -> String {
+    raw.split(|b| *b == 0xff)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            encoding.map_or_else(
+                || String::from_utf8_lossy(part).into_owned(),
+                |encoding| encoding.decode_without_bom_handling(part).0.into_owned(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+        .replace('\\', "/")
+}
+
+/// Read the extended headers that follow a level 1 or 2 header, starting with one of
+/// `size` bytes. Returns the total of their sizes
+fn read_extensions(
+    rdr: &mut impl Read,
+    mut size: usize,
+    header: &mut Header,
+    dir: &mut Vec<u8>,
+    name: &mut Vec<u8>,
+) -> Result<u64, DecompressError> {
+    let mut total = 0;
+    while size != 0 {
+        if size < 3 {
+            return Err(corrupt("extended header too short"));
+        }
+        let mut ext = vec![0; size];
+        rdr.read_exact(&mut ext)?;
+        total += size as u64;
+        let data = &ext[1..size - 2];
+        match ext[0] {
+            0x01 => *name = data.to_vec(),
+            0x02 => *dir = data.to_vec(),
+            0x54 if data.len() >= 4 => header.mtime = Some(u64::from(u32_at(data, 0))),
+            _ => {}
+        }
+        size = usize::from(u16_at(&ext, size - 2));
+    }
+    Ok(total)
+}
+
+/// Read the next entry's header, `None` at the end of the archive
+fn next_header(
+    rdr: &mut impl Read,
+    encoding: Option<&'static Encoding>,
+) -> Result<Option<Header>, DecompressError> {
+    let mut base = vec![0; 21];
+    // archives end with a zero byte, though some just end
+    match rdr.read(&mut base[..1])? {
+        0 => return Ok(None),
+        _ if base[0] == 0 => return Ok(None),
+        _ => {}
+    }
+    rdr.read_exact(&mut base[1..])
+        .map_err(|_| corrupt("truncated header"))?;
+
+    let mut method = [0; 5];
+    method.copy_from_slice(&base[2..7]);
+    let mut header = Header {
+        method,
+        packed: u64::from(u32_at(&base, 7)),
+        original: u64::from(u32_at(&base, 11)),
+        mtime: None,
+        path: PathBuf::new(),
+        crc: 0,
+    };
+    let mut dir = vec![];
+    let mut name = vec![];
+    match base[20] {
+        level @ (0 | 1) => {
+            let size = usize::from(base[0]) + 2;
+            if size < 24 {
+                return Err(corrupt("header too short"));
+            }
+            base.resize(size, 0);
+            rdr.read_exact(&mut base[21..])
+                .map_err(|_| corrupt("truncated header"))?;
+            let sum = base[2..].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            if sum != base[1] {
+                return Err(corrupt("header checksum mismatch"));
+            }
+            header.mtime = dos_time(u32_at(&base, 15));
+            let len = usize::from(base[21]);
+            if 22 + len + 2 > size {
+                return Err(corrupt("name overflows its header"));
+            }
+            name = base[22..22 + len].to_vec();
+            header.crc = u16_at(&base, 22 + len);
+            if level == 1 {
+                if size < 22 + len + 5 {
+                    return Err(corrupt("header too short"));
+                }
+                // level 1 counts its extended headers in the packed size
+                let ext = usize::from(u16_at(&base, size - 2));
+                let ext = read_extensions(rdr, ext, &mut header, &mut dir, &mut name)?;
+                header.packed = header
+                    .packed
+                    .checked_sub(ext)
+                    .ok_or_else(|| corrupt("extended headers overflow the entry"))?;
+            }
+        }
+        2 => {
+            let size = usize::from(u16_at(&base, 0));
+            if size < 26 {
+                return Err(corrupt("header too short"));
+            }
+            base.resize(26, 0);
+            rdr.read_exact(&mut base[21..])
+                .map_err(|_| corrupt("truncated header"))?;
+            header.mtime = Some(u64::from(u32_at(&base, 15)));
+            header.crc = u16_at(&base, 21);
+            let ext = usize::from(u16_at(&base, 24));
+            let ext = read_extensions(rdr, ext, &mut header, &mut dir, &mut name)?;
+            // the total size may have a byte of padding past the extended headers
+            let rest = (size as u64)
+                .checked_sub(26 + ext)
+                .ok_or_else(|| corrupt("extended headers overflow the header"))?;
+            io::copy(&mut rdr.take(rest), &mut io::sink())?;
+        }
+        level => {
+            return Err(DecompressError::UnsupportedFeature(format!(
+                "lha header level {level}"
+            )))
+        }
+    }
+
+    if !dir.is_empty() {
+        dir.push(0xff);
+    }
+    dir.extend(name);
+    header.path = PathBuf::from(decode_name(&dir, encoding));
+    Ok(Some(header))
+}
+
+/// Reads bits most significant first. Past the end of the input it reads zeros, like
+/// LHA does, the entry's original size says when to stop
+struct BitReader<R> {
+    inner: R,
+    buf: u32,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn bits(&mut self, n: u32) -> io::Result<u16> {
+        while self.count < n {
+            let mut byte = [0];
+            let byte = match self.inner.read(&mut byte)? {
+                0 => 0,
+                _ => byte[0],
+            };
+            self.buf = (self.buf << 8) | u32::from(byte);
+            self.count += 8;
+        }
+        self.count -= n;
+        let value = (self.buf >> self.count) & ((1 << n) - 1);
+        self.buf &= (1 << self.count) - 1;
+        u16::try_from(value).map_err(|_| invalid("more than 16 bits at once"))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("lha: {msg}"))
+}
+
+/// A canonical Huffman code, or a single symbol that takes no bits at all
+struct Huffman {
+    counts: [u16; 17],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn single(symbol: u16) -> Self {
+        Self {
+            counts: [0; 17],
+            symbols: vec![symbol],
+        }
+    }
+
+    fn new(lengths: &[u16]) -> Self {
+        let mut counts = [0; 17];
+        let mut symbols = vec![];
+        for len in 1..=16 {
+            for (symbol, _) in (0..).zip(lengths).filter(|(_, l)| **l == len) {
+                counts[usize::from(len)] += 1;
+                symbols.push(symbol);
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode<R: Read>(&self, bits: &mut BitReader<R>) -> io::Result<u16> {
+        if self.counts == [0; 17] {
+            return self
+                .symbols
+                .first()
+                .copied()
+                .ok_or_else(|| invalid("empty code table"));
+        }
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for count in &self.counts[1..] {
+            code |= bits.bits(1)?;
+            if code - first < *count {
+                return Ok(self.symbols[usize::from(index + code - first)]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad huffman code"))
+    }
+}
+
+const NC: usize = 510;
+const NT: usize = 19;
+
+/// The `-lh4-` to `-lh7-` methods: LZSS over a `2^dicbit` window, with Huffman coded
+/// blocks (as in `ar002`)
+struct LhDecoder<R> {
+    bits: BitReader<R>,
+    np: usize,
+    pbit: u32,
+    block: u16,
+    codes: Huffman,
+    positions: Huffman,
+    window: Vec<u8>,
+    pos: usize,
+    copy_from: usize,
+    copy_len: usize,
+}
+
+impl<R: Read> LhDecoder<R> {
+    fn new(inner: R, dicbit: u32, np: usize, pbit: u32) -> Self {
+        Self {
+            bits: BitReader {
+                inner,
+                buf: 0,
+                count: 0,
+            },
+            np,
+            pbit,
+            block: 0,
+            codes: Huffman::single(0),
+            positions: Huffman::single(0),
+            // the window starts out as spaces
+            window: vec![b' '; 1 << dicbit],
+            pos: 0,
+            copy_from: 0,
+            copy_len: 0,
+        }
+    }
+
+    fn read_pt_len(&mut self, nn: usize, nbit: u32, special: Option<usize>) -> io::Result<Huffman> {
+        let n = usize::from(self.bits.bits(nbit)?);
+        if n == 0 {
+            let symbol = self.bits.bits(nbit)?;
+            return if usize::from(symbol) < nn {
+                Ok(Huffman::single(symbol))
+            } else {
+                Err(invalid("bad code table"))
+            };
+        }
+        if n > nn {
+            return Err(invalid("bad code table"));
+        }
+        let mut lengths = vec![0; nn];
+        let mut i = 0;
+        while i < n {
+            let mut len = self.bits.bits(3)?;
+            if len == 7 {
+                while self.bits.bits(1)? == 1 {
+                    len += 1;
+                    if len > 16 {
+                        return Err(invalid("bad code length"));
+                    }
+                }
+            }
+            lengths[i] = len;
+            i += 1;
+            if Some(i) == special {
+                i += usize::from(self.bits.bits(2)?);
+            }
+        }
+        Ok(Huffman::new(&lengths))
+    }
+
+    fn read_c_len(&mut self, lens: &Huffman) -> io::Result<Huffman> {
+        let n = usize::from(self.bits.bits(9)?);
+        if n == 0 {
+            let symbol = self.bits.bits(9)?;
+            return if usize::from(symbol) < NC {
+                Ok(Huffman::single(symbol))
+            } else {
+                Err(invalid("bad code table"))
+            };
+        }
+        if n > NC {
+            return Err(invalid("bad code table"));
+        }
+        let mut lengths = vec![0; NC];
+        let mut i = 0;
+        while i < n {
+            match lens.decode(&mut self.bits)? {
+                0 => i += 1,
+                1 => i += usize::from(self.bits.bits(4)?) + 3,
+                2 => i += usize::from(self.bits.bits(9)?) + 20,
+                len => {
+                    lengths[i] = len - 2;
+                    i += 1;
+                }
+            }
+            if i > NC {
+                return Err(invalid("bad code table"));
+            }
+        }
+        Ok(Huffman::new(&lengths))
+    }
+
+    fn next_code(&mut self) -> io::Result<u16> {
+        if self.block == 0 {
+            self.block = self.bits.bits(16)?;
+            let lens = self.read_pt_len(NT, 5, Some(3))?;
+            self.codes = self.read_c_len(&lens)?;
+            self.positions = self.read_pt_len(self.np, self.pbit, None)?;
+        }
+        self.block = self.block.wrapping_sub(1);
+        self.codes.decode(&mut self.bits)
+    }
+
+    fn next_position(&mut self) -> io::Result<usize> {
+        let bits = self.positions.decode(&mut self.bits)?;
+        Ok(match bits {
+            0 => 0,
+            bits => (1 << (bits - 1)) + usize::from(self.bits.bits(u32::from(bits) - 1)?),
+        })
+    }
+}
+
+impl<R: Read> Read for LhDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mask = self.window.len() - 1;
+        for out in buf.iter_mut() {
+            if self.copy_len == 0 {
+                let code = self.next_code()?;
+                if let Ok(byte) = u8::try_from(code) {
+                    self.window[self.pos] = byte;
+                    self.pos = (self.pos + 1) & mask;
+                    *out = byte;
+                    continue;
+                }
+                self.copy_len = usize::from(code) - 253;
+                let position = self.next_position()?;
+                if position > mask {
+                    return Err(invalid("match reaches past the window"));
+                }
+                self.copy_from = self.pos.wrapping_sub(position + 1) & mask;
+            }
+            let byte = self.window[self.copy_from];
+            self.copy_from = (self.copy_from + 1) & mask;
+            self.copy_len -= 1;
+            self.window[self.pos] = byte;
+            self.pos = (self.pos + 1) & mask;
+            *out = byte;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// An entry's contents, cut at its original size and checked against its CRC-16
+/// once read to the end
+struct EntryReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    remaining: u64,
+    crc: u16,
+    expected: u16,
+}
+
+impl<'a> EntryReader<'a> {
+    fn new(header: &Header, data: &'a mut dyn Read) -> Result<Self, DecompressError> {
+        let inner: Box<dyn Read + 'a> = match &header.method {
+            b"-lh0-" | b"-lhd-" => Box::new(data),
+            b"-lh4-" => Box::new(LhDecoder::new(data, 12, 14, 4)),
+            b"-lh5-" => Box::new(LhDecoder::new(data, 13, 14, 4)),
+            b"-lh6-" => Box::new(LhDecoder::new(data, 15, 16, 5)),
+            b"-lh7-" => Box::new(LhDecoder::new(data, 16, 17, 5)),
+            method => {
+                return Err(DecompressError::UnsupportedFeature(format!(
+                    "lha method `{}`",
+                    String::from_utf8_lossy(method)
+                )))
+            }
+        };
+        Ok(Self {
+            inner,
+            remaining: header.original,
+            crc: 0,
+            expected: header.crc,
+        })
+    }
+}
+
+impl Read for EntryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let want = usize::try_from(self.remaining).map_or(buf.len(), |r| r.min(buf.len()));
+        let n = self.inner.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(invalid("truncated entry"));
+        }
+        for byte in &buf[..n] {
+            self.crc ^= u16::from(*byte);
+            for _ in 0..8 {
+                self.crc = if self.crc & 1 == 1 {
+                    (self.crc >> 1) ^ 0xa001
+                } else {
+                    self.crc >> 1
+                };
+            }
+        }
+        self.remaining -= n as u64;
+        if self.remaining == 0 && self.crc != self.expected {
+            return Err(invalid("crc mismatch"));
+        }
+        Ok(n)
+    }
+}
+
+fn lha_list(mut rdr: impl Read, opts: &ExtractOpts) -> Result<Vec<String>, DecompressError> {
+    let mut entries = vec![];
+    while let Some(header) = next_header(&mut rdr, opts.filename_encoding)? {
+        check_path(&header.path, opts)?;
+        entries.push(header.path.to_string_lossy().to_string());
+        io::copy(&mut (&mut rdr).take(header.packed), &mut io::sink())?;
+    }
+    Ok(entries)
+}
+
+fn lha_verify(mut rdr: impl Read, opts: &ExtractOpts) -> Result<(), DecompressError> {
+    while let Some(header) = next_header(&mut rdr, opts.filename_encoding)? {
+        let mut data = (&mut rdr).take(header.packed);
+        drain(
+            &mut EntryReader::new(&header, &mut data)?,
+            &header.path.to_string_lossy(),
+        )?;
+        io::copy(&mut data, &mut io::sink())?;
+    }
+    Ok(())
+}
+
+fn lha_extract(
+    mut rdr: impl Read,
+    to: &Path,
+    opts: &ExtractOpts,
+) -> Result<Vec<ExtractedFile>, DecompressError> {
+    let mut files = vec![];
+    let mut written = 0;
+    if !to.exists() {
+        create_dir_all(to, opts)?;
+    }
+
+    while let Some(header) = next_header(&mut rdr, opts.filename_encoding)? {
+        let mut data = (&mut rdr).take(header.packed);
+        let entry_path = header.path.clone();
+        let kind = header.kind();
+        check_path(&entry_path, opts)?;
+
+        let filepath = entry_path
+            .components()
+            .skip(opts.strip)
+            .collect::<PathBuf>();
+        let outpath = to.join(rename(opts, &filepath, kind));
+        if outpath == to || is_unsafe_path(&entry_path) {
+            io::copy(&mut data, &mut io::sink())?;
+            continue;
+        }
+        if !(opts.filter)(outpath.as_path()) {
+            notify_entry(opts, &entry_path, kind, Some(header.original), false);
+            io::copy(&mut data, &mut io::sink())?;
+            continue;
+        }
+        let Some(outpath) = map_path(opts, outpath.as_path())
+            .map(|outpath| resolve_conflict(opts, outpath, kind))
+            .transpose()?
+            .flatten()
+        else {
+            notify_entry(opts, &entry_path, kind, Some(header.original), false);
+            io::copy(&mut data, &mut io::sink())?;
+            continue;
+        };
+        written += 1;
+        check_entry_limit(opts, written)?;
+
+        if kind == RelPathKind::Dir {
+            create_dir_all(&outpath, opts)?;
+        } else {
+            check_ratio(opts, &entry_path, header.packed, header.original)?;
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    create_dir_all(p, opts)?;
+                }
+            }
+            let mut outfile = create_file(opts, &outpath)?;
+            let (size, hash) = write_contents(
+                opts,
+                &entry_path,
+                &mut EntryReader::new(&header, &mut data)?,
+                &mut outfile,
+            )?;
+            outfile.persist()?;
+            files.push(ExtractedFile {
+                path: outpath.to_string_lossy().to_string(),
+                mtime: header.mtime.map(system_time),
+                size,
+                hash,
+            });
+        }
+        io::copy(&mut data, &mut io::sink())?;
+        notify_entry(opts, &entry_path, kind, Some(header.original), true);
+    }
+    Ok(files)
+}
+
+/// LHA archives (`.lzh`, `.lha`), with level 0 to 2 headers and the `-lh0-` and
+/// `-lh4-` to `-lh7-` methods. Names are decoded with `filename_encoding` when set
+#[derive(Default)]
+pub struct Lha {
+    re: Option<Regex>,
+    extra: Option<Regex>,
+}
+
+impl Lha {
+    #[must_use]
+    pub fn new(re: Option<Regex>) -> Self {
+        Self { re, extra: None }
+    }
+    #[must_use]
+    pub fn build(re: Option<Regex>) -> Box<Self> {
+        Box::new(Self::new(re))
+    }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
+}
+
+impl Decompressor for Lha {
+    fn id(&self) -> &'static str {
+        "lha"
+    }
+
+    fn test_mimetype(&self, archive: &str) -> bool {
+        archive == "application/x-lzh-compressed"
+    }
+
+    fn test(&self, archive: &Path) -> bool {
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
+    }
+
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        Ok(Listing {
+            id: "lha",
+            entries: lha_list(BufReader::new(File::open(archive)?), opts)?,
+        })
+    }
+
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        lha_verify(BufReader::new(File::open(archive)?), opts)
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        Ok(Decompression::new(
+            "lha",
+            to,
+            lha_extract(BufReader::new(reader), to, opts)?,
+        ))
+    }
+}
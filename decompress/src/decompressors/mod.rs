@@ -1,6 +1,9 @@
 #[cfg(feature = "tarball")]
 mod tar_common;
 
+#[cfg(extracts_trees)]
+mod walker;
+
 #[cfg(feature = "tarball")]
 pub mod tarball;
 
@@ -28,6 +31,9 @@ pub mod unrar;
 #[cfg(feature = "ar")]
 pub mod ar;
 
+#[cfg(feature = "deb")]
+pub mod deb;
+
 #[cfg(feature = "bz2")]
 pub mod bz2;
 
@@ -37,4 +43,14 @@ pub mod xz;
 #[cfg(feature = "zstd")]
 pub mod zstd;
 
-mod utils;
+#[cfg(feature = "warc")]
+pub mod warc;
+
+#[cfg(feature = "lha")]
+pub mod lha;
+
+pub mod passthrough;
+
+pub mod predicate;
+
+pub(crate) mod utils;
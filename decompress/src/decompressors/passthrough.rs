@@ -0,0 +1,103 @@
+use crate::decompressors::utils::{create_dir_all, map_path};
+use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+fn file_name(archive: &Path) -> Result<&std::ffi::OsStr, DecompressError> {
+    archive
+        .file_name()
+        .ok_or_else(|| DecompressError::Error("cannot compose a file name".to_string()))
+}
+
+/// Not a format: matches any file, and "extracts" it by copying it as-is.
+///
+/// The copy lands in the output folder under its own file name (through `map`). It isn't
+/// in the default stack, add it last with `Decompress::default().with(Passthrough::build())`
+/// so that unsupported files come through untouched
+#[derive(Default)]
+pub struct Passthrough;
+
+impl Passthrough {
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+    #[must_use]
+    pub fn build() -> Box<Self> {
+        Box::new(Self::new())
+    }
+}
+
+impl Decompressor for Passthrough {
+    fn id(&self) -> &'static str {
+        "passthrough"
+    }
+
+    fn test_mimetype(&self, _archive: &str) -> bool {
+        true
+    }
+
+    fn test(&self, _archive: &Path) -> bool {
+        true
+    }
+
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        Ok(Listing {
+            id: "passthrough",
+            entries: vec![file_name(archive)?.to_string_lossy().to_string()],
+        })
+    }
+
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
+        File::open(archive)?;
+        Ok(())
+    }
+
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        _opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        Ok(io::copy(&mut File::open(archive)?, writer)?)
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        mut reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let target = to.join(file_name(name)?);
+        let Some(target) = map_path(opts, &target) else {
+            return Ok(Decompression::new("passthrough", to, vec![]));
+        };
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent, opts)?;
+        }
+        let size = io::copy(&mut reader, &mut File::create(&target)?)?;
+        Ok(Decompression::new(
+            "passthrough",
+            to,
+            vec![ExtractedFile {
+                path: target.to_string_lossy().to_string(),
+                mtime: None,
+                size,
+                hash: None,
+            }],
+        ))
+    }
+}
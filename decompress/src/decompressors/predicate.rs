@@ -0,0 +1,143 @@
+use crate::{
+    ArchiveMetadata, DecompressError, Decompression, Decompressor, EntryReaderIter, EntrySink,
+    ExtractOpts, Listing, ListingIter,
+};
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+pub type TestFn = dyn Fn(&Path) -> bool;
+
+/// Wraps a decompressor to pick archives with a closure rather than its `Regex`.
+///
+/// For matching that a regex can't express. Everything else is left to the inner
+/// decompressor, including its id and mimetype test
+pub struct Predicate {
+    inner: Box<dyn Decompressor>,
+    test: Box<TestFn>,
+}
+
+impl Predicate {
+    #[must_use]
+    pub fn new(inner: Box<dyn Decompressor>, test: impl Fn(&Path) -> bool + 'static) -> Self {
+        Self {
+            inner,
+            test: Box::new(test),
+        }
+    }
+    #[must_use]
+    pub fn build(
+        inner: Box<dyn Decompressor>,
+        test: impl Fn(&Path) -> bool + 'static,
+    ) -> Box<Self> {
+        Box::new(Self::new(inner, test))
+    }
+}
+
+impl Decompressor for Predicate {
+    fn id(&self) -> &'static str {
+        self.inner.id()
+    }
+
+    fn test_mimetype(&self, mimetype: &str) -> bool {
+        self.inner.test_mimetype(mimetype)
+    }
+
+    fn test_content(&self, archive: &Path) -> bool {
+        self.inner.test_content(archive)
+    }
+
+    fn test(&self, archive: &Path) -> bool {
+        (self.test)(archive)
+    }
+
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        self.inner.list(archive, opts)
+    }
+
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        self.inner.list_iter(archive, opts)
+    }
+
+    fn count(&self, archive: &Path, opts: &ExtractOpts) -> Result<usize, DecompressError> {
+        self.inner.count(archive, opts)
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.inner.decompress(archive, to, opts)
+    }
+
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        self.inner.verify(archive, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.inner.decompress_reader(reader, name, to, opts)
+    }
+
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        self.inner.decompress_to_writer(archive, writer, opts)
+    }
+
+    fn decompress_to_sink(
+        &self,
+        archive: &Path,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        self.inner.decompress_to_sink(archive, sink, opts)
+    }
+
+    fn decompress_range(
+        &self,
+        archive: &Path,
+        start: u64,
+        len: u64,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        self.inner
+            .decompress_range(archive, start, len, writer, opts)
+    }
+
+    fn entries(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        self.inner.entries(archive, opts)
+    }
+
+    fn archive_comment(&self, archive: &Path) -> Result<Option<String>, DecompressError> {
+        self.inner.archive_comment(archive)
+    }
+
+    fn is_encrypted(&self, archive: &Path) -> Result<bool, DecompressError> {
+        self.inner.is_encrypted(archive)
+    }
+
+    fn metadata(&self, archive: &Path) -> Result<ArchiveMetadata, DecompressError> {
+        self.inner.metadata(archive)
+    }
+}
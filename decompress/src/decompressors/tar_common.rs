@@ -1,83 +1,942 @@
-use std::borrow::Cow;
 use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
     fs::{self},
-    io::{self, BufReader, Read},
-    path::{Path, PathBuf},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::mpsc,
+    thread,
 };
 
-use crate::decompressors::utils::normalize_mode;
-use crate::{DecompressError, ExtractOpts};
-use tar::Archive;
+use crate::decompressors::utils::{
+    apply_fixed_mtime, check_depth, check_entry_limit, check_path, create_dir_all, create_file,
+    drain, entry_error, is_allowed_extension, is_apple_metadata, is_too_deep, is_unsafe_path,
+    is_up_to_date, lossy_name_warning, map_path, normalize_mode, notify_entry, numbered,
+    peek_content, rename, resolve_conflict, sanitize, sink_path, system_time, unix_mtime,
+    write_contents, CaseFolds, DirMtimes, Manifest,
+};
+use crate::decompressors::walker::{walk_entries, WalkedEntry};
+use crate::{
+    AbsoluteSymlinkPolicy, DecompressError, Decompression, EntryReaderIter, EntrySink, ExtractOpts,
+    ExtractedFile, ListingEntry, ListingIter, RelPath, RelPathKind, SymlinkPolicy,
+};
+use tar::{Archive, Entries, Entry};
 
-pub fn tar_list(out: &mut Archive<Box<dyn Read>>) -> Result<Vec<String>, DecompressError> {
-    Ok(out
-        .entries()?
-        .collect::<Result<Vec<_>, _>>()?
-        .iter()
-        .map(tar::Entry::path)
-        .collect::<Result<Vec<_>, _>>()?
-        .iter()
-        .map(|p| p.to_string_lossy().to_string())
-        .collect::<Vec<_>>())
+const BLOCK_SIZE: usize = 512;
+
+/// A PAX (format 1.0) sparse entry, where the entry data starts with a map
+/// of the data regions and the real name and size live in the PAX records.
+struct PaxSparse {
+    name: Option<PathBuf>,
+    realsize: u64,
+}
+
+fn pax_sparse<R: Read>(entry: &mut Entry<'_, R>) -> io::Result<Option<PaxSparse>> {
+    let mut major = None;
+    let mut name = None;
+    let mut realsize = None;
+    if let Some(extensions) = entry.pax_extensions()? {
+        for ext in extensions {
+            let ext = ext?;
+            match ext.key() {
+                Ok("GNU.sparse.major") => major = ext.value().ok().map(str::to_string),
+                Ok("GNU.sparse.name") => name = ext.value().ok().map(PathBuf::from),
+                Ok("GNU.sparse.realsize") => {
+                    realsize = ext.value().ok().and_then(|v| v.parse::<u64>().ok());
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(match (major.as_deref(), realsize) {
+        (Some("1"), Some(realsize)) => Some(PaxSparse { name, realsize }),
+        _ => None,
+    })
+}
+
+/// The full entry path: the name of a PAX sparse file, or else what `Entry::path` resolves
+/// from GNU long-name entries and PAX `path` records, rather than the header's name
+/// field that cuts names off at 100 bytes
+fn entry_path<R: Read>(entry: &Entry<'_, R>, sparse: Option<&PaxSparse>) -> io::Result<PathBuf> {
+    match sparse.and_then(|s| s.name.clone()) {
+        Some(name) => Ok(name),
+        None => Ok(entry.path()?.into_owned()),
+    }
+}
+
+/// Extended attributes stored as `SCHILY.xattr.<name>` PAX records
+fn pax_xattrs<R: Read>(entry: &mut Entry<'_, R>) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut xattrs = vec![];
+    if let Some(extensions) = entry.pax_extensions()? {
+        for ext in extensions {
+            let ext = ext?;
+            if let Some(name) = ext.key().ok().and_then(|k| k.strip_prefix("SCHILY.xattr.")) {
+                xattrs.push((name.to_string(), ext.value_bytes().to_vec()));
+            }
+        }
+    }
+    Ok(xattrs)
+}
+
+#[cfg(all(unix, feature = "xattr"))]
+fn apply_xattrs(path: &Path, xattrs: &[(String, Vec<u8>)]) -> io::Result<()> {
+    for (name, value) in xattrs {
+        xattr::set(path, name, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "xattr")))]
+#[allow(clippy::unnecessary_wraps)]
+fn apply_xattrs(_path: &Path, _xattrs: &[(String, Vec<u8>)]) -> io::Result<()> {
+    Ok(())
+}
+
+/// `chown` an extracted entry to the header's uid/gid. Without privilege this is
+/// skipped, unless `strict`
+#[cfg(unix)]
+fn apply_ownership(path: &Path, header: &tar::Header, strict: bool) -> io::Result<()> {
+    let uid = u32::try_from(header.uid()?).ok();
+    let gid = u32::try_from(header.gid()?).ok();
+    match std::os::unix::fs::chown(path, uid, gid) {
+        Err(err) if !strict && err.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn apply_ownership(_path: &Path, _header: &tar::Header, _strict: bool) -> io::Result<()> {
+    Ok(())
+}
+
+/// Write a PAX 1.0 sparse entry: read the decimal map (a count followed by
+/// offset/size pairs, padded to a block) and seek-write each data region.
+fn write_pax_sparse<R: Read>(
+    entry: &mut Entry<'_, R>,
+    outfile: &mut fs::File,
+    realsize: u64,
+) -> io::Result<()> {
+    let mut rdr = BufReader::new(entry);
+    let mut consumed = 0;
+    let mut next_number = || -> io::Result<u64> {
+        let mut line = String::new();
+        consumed += rdr.read_line(&mut line)?;
+        line.trim_end()
+            .parse::<u64>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid sparse map"))
+    };
+    let count = next_number()?;
+    let mut regions = vec![];
+    for _ in 0..count {
+        regions.push((next_number()?, next_number()?));
+    }
+    let padding = (BLOCK_SIZE - consumed % BLOCK_SIZE) % BLOCK_SIZE;
+    io::copy(&mut (&mut rdr).take(padding as u64), &mut io::sink())?;
+
+    for (offset, size) in regions {
+        outfile.seek(SeekFrom::Start(offset))?;
+        io::copy(&mut (&mut rdr).take(size), outfile)?;
+    }
+    outfile.set_len(realsize)
+}
+
+/// Copy a GNU sparse entry. The tar reader already expands holes into zeros,
+/// so on unix we seek over zeroed blocks instead of writing them to leave
+/// holes in the output file. Elsewhere, we fall back to a dense copy.
+fn write_gnu_sparse<R: Read>(
+    entry: &mut Entry<'_, R>,
+    outfile: &mut fs::File,
+    size: u64,
+) -> io::Result<()> {
+    if cfg!(unix) {
+        let mut buf = [0u8; BLOCK_SIZE * 8];
+        let mut pos = 0;
+        loop {
+            let n = entry.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            if buf[..n].iter().any(|b| *b != 0) {
+                outfile.seek(SeekFrom::Start(pos))?;
+                outfile.write_all(&buf[..n])?;
+            }
+            pos += n as u64;
+        }
+        outfile.set_len(size)
+    } else {
+        io::copy(&mut BufReader::new(entry), outfile).map(|_| ())
+    }
+}
+
+fn entry_kind(entry_type: tar::EntryType) -> RelPathKind {
+    match entry_type {
+        tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+            RelPathKind::File
+        }
+        tar::EntryType::Directory => RelPathKind::Dir,
+        tar::EntryType::Symlink => RelPathKind::Symlink,
+        _ => RelPathKind::Other,
+    }
+}
+
+/// Entry types that we extract. Devices, fifos and unknown types can't be extracted
+/// portably, and are skipped with a warning rather than written as empty files.
+fn is_supported(entry_type: tar::EntryType) -> bool {
+    matches!(
+        entry_type,
+        tar::EntryType::Regular
+            | tar::EntryType::Continuous
+            | tar::EntryType::GNUSparse
+            | tar::EntryType::Link
+            | tar::EntryType::Symlink
+            | tar::EntryType::Directory
+    )
+}
+
+/// The header's mtime. A malformed one only fails the entry when it's applied, with
+/// `preserve_mtime`, and is left out of `ExtractedFile::mtime` otherwise
+fn header_mtime(header: &tar::Header, opts: &ExtractOpts) -> io::Result<Option<u64>> {
+    match header.mtime() {
+        Ok(secs) => Ok(Some(secs)),
+        Err(err) if opts.preserve_mtime => Err(err),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Where a symlink at `link` points to inside `to`, or `None` for an absolute target
+/// or one that would leave `to`
+fn resolve_link(to: &Path, link: &Path, target: &Path) -> Option<PathBuf> {
+    let mut rel = link.strip_prefix(to).ok()?.parent()?.to_path_buf();
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !rel.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(to.join(rel))
 }
 
-pub fn tar_extract(
-    out: &mut Archive<Box<dyn Read>>,
+/// The target a symlink entry is created with, after `rewrite_symlink_target`. A link
+/// that is created has to resolve inside `to`, or be absolute under
+/// `AbsoluteSymlinkPolicy::Create`. A rewritten target is held to that with
+/// `SymlinkPolicy::Dereference` too
+///
+/// # Errors
+///
+/// Returns `DecompressError::PathEscape` for a target that doesn't
+fn link_target(
     to: &Path,
+    outpath: &Path,
+    entry_path: &Path,
+    target: PathBuf,
+    opts: &ExtractOpts,
+) -> Result<PathBuf, DecompressError> {
+    let target = match &opts.rewrite_symlink_target {
+        Some(rewrite) => rewrite(&RelPath::new(entry_path), &target),
+        None if opts.symlinks == SymlinkPolicy::Create => target,
+        None => return Ok(target),
+    };
+    let allowed = if target.has_root() {
+        opts.absolute_symlinks == AbsoluteSymlinkPolicy::Create
+    } else {
+        resolve_link(to, outpath, &target).is_some()
+    };
+    if !allowed {
+        return Err(DecompressError::PathEscape(
+            entry_path.to_string_lossy().to_string(),
+        ));
+    }
+    Ok(target)
+}
+
+/// Write a symlink entry per `SymlinkPolicy::Create` or `Dereference`. Returns the link
+/// target to copy in place of the placeholder once all entries are written, if any
+fn write_symlink(
+    to: &Path,
+    outpath: &Path,
+    target: &Path,
+    policy: SymlinkPolicy,
+) -> io::Result<Option<PathBuf>> {
+    if outpath.symlink_metadata().is_ok() {
+        fs::remove_file(outpath)?;
+    }
+    #[cfg(unix)]
+    if policy == SymlinkPolicy::Create {
+        std::os::unix::fs::symlink(target, outpath)?;
+        return Ok(None);
+    }
+    let _ = policy;
+    fs::write(outpath, target.to_string_lossy().as_bytes())?;
+    Ok(resolve_link(to, outpath, target))
+}
+
+/// Whether `outpath` is, or would be written through, one of the symlinks created for
+/// the entries before it, which may lead anywhere
+fn is_under_link(to: &Path, outpath: &Path, created: &HashSet<PathBuf>) -> bool {
+    outpath
+        .ancestors()
+        .take_while(|dir| *dir != to)
+        .any(|dir| created.contains(dir))
+}
+
+/// An archive path without `.` components, as hard link targets and entry names may
+/// differ in them
+fn normalized(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Where the file a hard link entry points to was extracted, when it was
+fn link_source<R: Read>(
+    entry: &Entry<'_, R>,
+    extracted: &HashMap<PathBuf, PathBuf>,
+) -> io::Result<Option<PathBuf>> {
+    if entry.header().entry_type() != tar::EntryType::Link {
+        return Ok(None);
+    }
+    let Some(target) = entry.link_name()? else {
+        return Ok(None);
+    };
+    Ok(extracted.get(&normalized(&target)).cloned())
+}
+
+/// Write a hard link entry at `outpath`, as a hard link to `source` with
+/// `preserve_hardlinks` and as a copy of it otherwise, or when linking fails.
+/// Returns the size of the file
+fn write_hardlink(source: &Path, outpath: &Path, preserve: bool) -> io::Result<u64> {
+    if outpath.symlink_metadata().is_ok() {
+        fs::remove_file(outpath)?;
+    }
+    if preserve && fs::hard_link(source, outpath).is_ok() {
+        return Ok(fs::metadata(outpath)?.len());
+    }
+    fs::copy(source, outpath)
+}
+
+/// Whether a symlink entry to an absolute path is left out per `absolute_symlinks`
+///
+/// # Errors
+///
+/// Returns `DecompressError::PathEscape` for such a link under
+/// `AbsoluteSymlinkPolicy::Error`
+fn skips_absolute_link<R: Read>(
+    entry: &Entry<'_, R>,
+    entry_path: &Path,
+    opts: &ExtractOpts,
+) -> Result<bool, DecompressError> {
+    if entry.header().entry_type() != tar::EntryType::Symlink
+        || opts.symlinks == SymlinkPolicy::Empty
+        || !entry.link_name()?.map_or(false, |target| target.has_root())
+    {
+        return Ok(false);
+    }
+    match opts.absolute_symlinks {
+        AbsoluteSymlinkPolicy::Skip => Ok(true),
+        AbsoluteSymlinkPolicy::Create => Ok(false),
+        AbsoluteSymlinkPolicy::Error => Err(DecompressError::PathEscape(
+            entry_path.to_string_lossy().to_string(),
+        )),
+    }
+}
+
+/// Follow symlinks a chain may go through before giving up, as `ELOOP` does
+const MAX_SYMLINK_HOPS: usize = 40;
+
+fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::CurDir => {}
+            component => pending.push(component.as_os_str().to_os_string()),
+        }
+    }
+}
+
+/// Resolve `link` the way the OS would, one component at a time, following every
+/// symlink along the way (unlike `canonicalize`, this works for dangling targets)
+fn resolve_symlink_chain(link: &Path) -> Result<PathBuf, DecompressError> {
+    let mut pending = vec![];
+    push_components(&mut pending, &std::env::current_dir()?.join(link));
+    let mut resolved = PathBuf::new();
+    let mut hops = 0;
+    while let Some(part) = pending.pop() {
+        if part == ".." {
+            resolved.pop();
+            continue;
+        }
+        resolved.push(part);
+        if resolved
+            .symlink_metadata()
+            .map_or(false, |meta| meta.file_type().is_symlink())
+        {
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Err(DecompressError::CorruptArchive(format!(
+                    "too many levels of symlinks: {}",
+                    link.display()
+                )));
+            }
+            let target = fs::read_link(&resolved)?;
+            resolved.pop();
+            push_components(&mut pending, &target);
+        }
+    }
+    Ok(resolved)
+}
+
+/// Fail if any of `links` resolves to outside of `to`, see `validate_symlinks_after`
+fn validate_symlinks(to: &Path, links: &[PathBuf]) -> Result<(), DecompressError> {
+    let root = resolve_symlink_chain(to)?;
+    for link in links {
+        if !resolve_symlink_chain(link)?.starts_with(&root) {
+            return Err(DecompressError::PathEscape(
+                link.to_string_lossy().to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// How entries are walked when listing: `Archive::entries` reads past every entry body,
+/// `Archive::entries_with_seek` seeks over them, so listing a plain tar only touches its
+/// headers. A compressed stream has to be decompressed to reach the next header either way
+type EntriesFn<R> = for<'a> fn(&'a mut Archive<R>) -> io::Result<Entries<'a, R>>;
+
+#[cfg(compressed_tar)]
+pub fn tar_list<R: Read>(
+    out: &mut Archive<R>,
+    opts: &ExtractOpts,
+) -> Result<Vec<String>, DecompressError> {
+    list_entries(out, Archive::entries, opts)
+}
+
+/// Like `tar_list`, seeking over entry bodies rather than reading them
+pub fn tar_list_seek<R: Read + Seek>(
+    out: &mut Archive<R>,
     opts: &ExtractOpts,
 ) -> Result<Vec<String>, DecompressError> {
+    list_entries(out, Archive::entries_with_seek, opts)
+}
+
+fn list_entries<R: Read>(
+    out: &mut Archive<R>,
+    entries_fn: EntriesFn<R>,
+    opts: &ExtractOpts,
+) -> Result<Vec<String>, DecompressError> {
+    let mut entries = vec![];
+    for entry in entries_fn(out)? {
+        let mut entry = entry.map_err(entry_error)?;
+        if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+            continue;
+        }
+        let sparse = pax_sparse(&mut entry)?;
+        let filepath = entry_path(&entry, sparse.as_ref())?;
+        check_path(&filepath, opts)?;
+        entries.push(filepath.to_string_lossy().to_string());
+    }
+    Ok(entries)
+}
+
+/// List lazily. `tar::Entries` borrows its archive, so the archive is built and walked
+/// on a separate thread that hands entries over a bounded channel. Dropping the iterator
+/// stops the walk at the next entry.
+#[cfg(compressed_tar)]
+pub fn tar_list_iter(
+    build: impl FnOnce() -> Result<Archive<Box<dyn Read>>, DecompressError> + Send + 'static,
+    opts: &ExtractOpts,
+) -> ListingIter {
+    list_entries_iter(build, Archive::entries, opts)
+}
+
+/// Like `tar_list_iter`, seeking over entry bodies rather than reading them
+pub fn tar_list_iter_seek<R: Read + Seek + 'static>(
+    build: impl FnOnce() -> Result<Archive<R>, DecompressError> + Send + 'static,
+    opts: &ExtractOpts,
+) -> ListingIter {
+    list_entries_iter(build, Archive::entries_with_seek, opts)
+}
+
+fn list_entries_iter<R: Read + 'static>(
+    build: impl FnOnce() -> Result<Archive<R>, DecompressError> + Send + 'static,
+    entries_fn: EntriesFn<R>,
+    opts: &ExtractOpts,
+) -> ListingIter {
+    let reject_unsafe_paths = opts.reject_unsafe_paths;
+    let (tx, rx) = mpsc::sync_channel(16);
+    thread::spawn(move || {
+        let walk = || -> Result<(), DecompressError> {
+            let mut out = build()?;
+            for entry in entries_fn(&mut out)? {
+                let mut entry = entry.map_err(entry_error)?;
+                if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+                    continue;
+                }
+                let sparse = pax_sparse(&mut entry)?;
+                let filepath = entry_path(&entry, sparse.as_ref())?;
+                if reject_unsafe_paths && is_unsafe_path(&filepath) {
+                    return Err(DecompressError::PathEscape(
+                        filepath.to_string_lossy().to_string(),
+                    ));
+                }
+                let is_name_lossy = filepath.to_str().is_none();
+                let listed = ListingEntry {
+                    path: RelPath::new(filepath),
+                    kind: entry_kind(entry.header().entry_type()),
+                    size: Some(sparse.map_or_else(|| entry.size(), |s| s.realsize)),
+                    compressed_size: None,
+                    comment: None,
+                    mode: None,
+                    is_name_lossy,
+                };
+                if tx.send(Ok(listed)).is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(())
+        };
+        if let Err(err) = walk() {
+            let _res = tx.send(Err(err));
+        }
+    });
+    Box::new(rx.into_iter())
+}
+
+/// Read every entry into a sink, then the rest of the stream, so trailing checksums
+/// of the compression format are checked too. A tar stream can't be resynced after an
+/// error, so this always stops at the first one.
+pub fn tar_verify<R: Read>(mut out: Archive<R>) -> Result<(), DecompressError> {
+    for entry in out.entries()? {
+        let mut entry = entry.map_err(entry_error)?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        drain(&mut entry, &path)?;
+    }
+    drain(&mut out.into_inner(), "trailing data")
+}
+
+/// Whether a decompressed stream starts with a tar header. A stream that can't be read
+/// is given the benefit of the doubt
+#[cfg(compressed_tar)]
+pub fn is_tar_stream(rdr: impl Read) -> bool {
+    let mut block = vec![];
+    rdr.take(512)
+        .read_to_end(&mut block)
+        .map_or(true, |_| is_tar_header(&block))
+}
+
+/// Returns `true` if `block` starts with a tar header, going by its checksum (which
+/// old v7 headers have too, unlike the `ustar` magic)
+#[cfg(any(compressed_tar, all(feature = "gz", feature = "tarball")))]
+pub fn is_tar_header(block: &[u8]) -> bool {
+    if block.len() < 512 {
+        return false;
+    }
+    let stored = String::from_utf8_lossy(&block[148..156]);
+    let Ok(stored) = u32::from_str_radix(stored.trim_matches(|c: char| c == ' ' || c == '\0'), 8)
+    else {
+        return false;
+    };
+    // the checksum is computed with its own field taken as spaces
+    let sum = block[..512]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u32::from(*b)
+            }
+        })
+        .sum::<u32>();
+    sum == stored
+}
+
+pub fn tar_extract<R: Read>(
+    out: &mut Archive<R>,
+    to: &Path,
+    opts: &ExtractOpts,
+    id: &'static str,
+) -> Result<Decompression, DecompressError> {
     let mut files = vec![];
+    let mut renamed = vec![];
+    let mut warnings = vec![];
+    let mut dir_mtimes = DirMtimes::default();
+    let mut manifest = Manifest::open(opts.resume_manifest.as_deref())?;
+    let mut written = 0;
+    let mut links = vec![];
+    let mut symlinks = vec![];
+    let mut created_links = HashSet::new();
+    let mut dirs = vec![];
+    let mut extracted = HashMap::new();
+    let mut case_folds = CaseFolds::default();
+    let mut skipped = vec![];
+    let target = format!("decompress::{id}");
+    let target = target.as_str();
     if !to.exists() {
-        fs::create_dir_all(to)?;
+        create_dir_all(to, opts)?;
     }
 
     // alternative impl: just unpack, and then mv everything back X levels
-    for entry in out.entries()? {
-        let entry = entry?;
-        let filepath = entry.path()?;
+    for (index, entry) in out.entries()?.enumerate() {
+        let mut entry = entry.map_err(entry_error)?;
+        let entry_type = entry.header().entry_type();
+
+        // PAX global headers only carry metadata for the entries that follow
+        if entry_type == tar::EntryType::XGlobalHeader {
+            continue;
+        }
+        // the rest of a file begun in the previous volume, which we don't have
+        if entry_type.as_byte() == b'M' {
+            return Err(DecompressError::UnsupportedFeature(
+                "GNU multi-volume tar".to_string(),
+            ));
+        }
+
+        let sparse = pax_sparse(&mut entry)?;
+        let filepath = entry_path(&entry, sparse.as_ref())?;
+        check_path(&filepath, opts)?;
+        check_depth(&filepath, opts)?;
+
+        let entry_path = filepath.clone();
+        let kind = entry_kind(entry_type);
+        let size = sparse.as_ref().map_or_else(|| entry.size(), |s| s.realsize);
+        log::trace!(target: target, "considering {} ({kind:?})", entry_path.display());
 
         // strip prefixed components. this can be 0 parts, in which case strip does not happen.
         // it's done for when archives contain an enclosing folder
         let filepath = filepath.components().skip(opts.strip).collect::<PathBuf>();
+        let filepath = rename(opts, &filepath, kind);
+        let sanitized = sanitize(opts, &filepath);
 
         // because we potentially stripped a component, we may have an empty path, in which case
         // the joined target will be identical to the target folder
         // we take this approach to avoid hardcoding a check against empty ""
-        let outpath = to.join(filepath);
+        let outpath = to.join(&sanitized);
         if to == outpath {
             continue;
         }
 
-        if !(opts.filter)(outpath.as_path()) {
+        if !(opts.filter)(outpath.as_path())
+            || !is_allowed_extension(opts, &entry_path, kind)
+            || is_apple_metadata(opts, &entry_path)
+        {
+            log::debug!(target: target, "filtered out {}", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
             continue;
         }
+        if is_too_deep(opts, to, &sanitized)? {
+            log::debug!(target: target, "skipped {}, it's too deep", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            skipped.push(outpath.to_string_lossy().to_string());
+            continue;
+        }
+        let head = if sparse.is_some() || entry_type.is_gnu_sparse() {
+            Some(vec![])
+        } else {
+            peek_content(opts, &entry_path, kind, &mut entry)?
+        };
+        let Some(head) = head else {
+            log::debug!(target: target, "filtered out {} by content", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        };
 
-        let outpath: Cow<'_, Path> = (opts.map)(outpath.as_path());
+        let Some(outpath) = map_path(opts, outpath.as_path()) else {
+            log::debug!(target: target, "mapped out {}", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        };
+        let outpath = numbered(opts, outpath, kind, index);
+        log::trace!(target: target, "{} maps to {}", entry_path.display(), outpath.display());
+        if manifest.contains(&entry_path) {
+            log::debug!(target: target, "skipped {}, already extracted", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        }
+        let outpath = case_folds.resolve(opts, to, outpath, kind)?;
+        let Some(outpath) = resolve_conflict(opts, outpath, kind)? else {
+            log::debug!(target: target, "skipped {}, it already exists", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        };
+        if is_up_to_date(
+            opts,
+            &outpath,
+            kind,
+            entry.header().mtime().ok().map(system_time),
+        ) {
+            log::debug!(target: target, "skipped {}, it isn't newer", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            skipped.push(outpath.to_string_lossy().to_string());
+            continue;
+        }
+        if skips_absolute_link(&entry, &entry_path, opts)? {
+            log::debug!(target: target, "skipped {}, it links to an absolute path", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        }
+        if is_under_link(to, &outpath, &created_links) {
+            return Err(DecompressError::PathEscape(
+                entry_path.to_string_lossy().to_string(),
+            ));
+        }
+        if !is_supported(entry_type) {
+            if opts.strict_entry_types {
+                return Err(DecompressError::UnsupportedEntryType {
+                    id,
+                    entry_type: format!("{entry_type:?}"),
+                });
+            }
+            log::debug!(target: target, "skipped {}, a {entry_type:?} entry", entry_path.display());
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            warnings.push(format!(
+                "unsupported entry type `{entry_type:?}`, skipped: `{}`",
+                entry_path.display()
+            ));
+            skipped.push(outpath.to_string_lossy().to_string());
+            continue;
+        }
+        written += 1;
+        check_entry_limit(opts, written)?;
+        if entry_path.to_str().is_none() {
+            warnings.push(lossy_name_warning(&entry_path));
+        }
+        if sanitized != filepath {
+            renamed.push((
+                filepath.to_string_lossy().to_string(),
+                sanitized.to_string_lossy().to_string(),
+            ));
+        }
+        let secs = header_mtime(entry.header(), opts)?;
+        let mtime = unix_mtime(i64::try_from(secs.unwrap_or_default()).unwrap_or(i64::MAX));
 
-        if entry.header().entry_type() != tar::EntryType::Directory {
+        if entry_type == tar::EntryType::Directory {
+            create_dir_all(&outpath, opts)?;
+            if opts.preserve_ownership {
+                apply_ownership(&outpath, entry.header(), opts.strict_ownership)?;
+            }
+            if opts.preserve_mtime {
+                dir_mtimes.push(outpath.to_path_buf(), mtime);
+            }
+            dirs.push(outpath.to_path_buf());
+        } else if entry_type == tar::EntryType::Symlink && opts.symlinks != SymlinkPolicy::Empty {
+            let target = entry
+                .link_name()?
+                .ok_or_else(|| {
+                    DecompressError::CorruptArchive(format!(
+                        "symlink without a target: {}",
+                        entry_path.display()
+                    ))
+                })?
+                .into_owned();
+            let target = link_target(to, &outpath, &entry_path, target, opts)?;
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(p)?;
+                    create_dir_all(p, opts)?;
+                }
+            }
+            if let Some(resolved) = write_symlink(to, &outpath, &target, opts.symlinks)? {
+                links.push((outpath.to_path_buf(), resolved));
+            } else if !target.has_root() {
+                symlinks.push(outpath.to_path_buf());
+            }
+            if opts.symlinks == SymlinkPolicy::Create {
+                created_links.insert(outpath.to_path_buf());
+            }
+            files.push(ExtractedFile {
+                path: outpath.to_string_lossy().to_string(),
+                mtime: secs.map(system_time),
+                size,
+                hash: None,
+            });
+        } else if let Some(source) = link_source(&entry, &extracted)? {
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    create_dir_all(p, opts)?;
+                }
+            }
+            let size = write_hardlink(&source, &outpath, opts.preserve_hardlinks)?;
+            files.push(ExtractedFile {
+                path: outpath.to_string_lossy().to_string(),
+                mtime: secs.map(system_time),
+                size,
+                hash: None,
+            });
+        } else {
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    create_dir_all(p, opts)?;
                 }
             }
 
-            let mut outfile = fs::File::create(&outpath)?;
+            let mut outfile = create_file(opts, &outpath)?;
 
-            #[cfg(unix)]
-            let h = entry.header().mode();
+            let header = entry.header().clone();
+            let xattrs = if opts.preserve_xattrs {
+                pax_xattrs(&mut entry)?
+            } else {
+                vec![]
+            };
+
+            // sparse entries are written with holes, the logical size is restored with `set_len`
+            let (size, hash) = if opts.structure_only {
+                (0, None)
+            } else if let Some(sparse) = sparse {
+                write_pax_sparse(&mut entry, &mut outfile, sparse.realsize)?;
+                (size, None)
+            } else if entry_type.is_gnu_sparse() {
+                let stored = entry.size();
+                write_gnu_sparse(&mut entry, &mut outfile, stored)?;
+                (size, None)
+            } else {
+                let mut rdr = head.as_slice().chain(BufReader::new(entry));
+                write_contents(opts, &entry_path, &mut rdr, &mut outfile)?
+            };
+            outfile.persist()?;
+            extracted.insert(normalized(&entry_path), outpath.to_path_buf());
+            files.push(ExtractedFile {
+                path: outpath.to_string_lossy().to_string(),
+                mtime: secs.map(system_time),
+                size,
+                hash,
+            });
 
-            io::copy(&mut BufReader::new(entry), &mut outfile)?;
-            files.push(outpath.to_string_lossy().to_string());
+            // before the mode, as `chown` clears setuid/setgid bits
+            if opts.preserve_ownership {
+                apply_ownership(&outpath, &header, opts.strict_ownership)?;
+            }
 
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
-                if let Ok(mode) = h {
+                if let Ok(mode) = header.mode() {
                     let mode = normalize_mode(mode);
                     fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
                 }
             }
+
+            apply_xattrs(&outpath, &xattrs)?;
+
+            if opts.preserve_mtime {
+                filetime::set_file_mtime(&outpath, mtime)?;
+            }
+        }
+        manifest.record(&entry_path)?;
+        log::debug!(target: target, "extracted {} to {}", entry_path.display(), outpath.display());
+        notify_entry(opts, &entry_path, kind, Some(size), true);
+    }
+    // targets may come after their links, so dereferenced links are copied last
+    for (link, target) in links {
+        if target.is_file() {
+            fs::copy(target, link)?;
         }
     }
-    Ok(files)
+    if opts.validate_symlinks_after {
+        validate_symlinks(to, &symlinks)?;
+    }
+    dir_mtimes.apply()?;
+    apply_fixed_mtime(to, &files, &dirs, opts)?;
+    Ok(Decompression::new(id, to, files)
+        .with_renamed(renamed)
+        .with_warnings(warnings)
+        .with_skipped(skipped))
+}
+
+/// Hand the entries of a tarball to `sink`, named as `tar_extract` would name them.
+/// Hard links and special files are skipped, PAX sparse files aren't supported
+pub fn tar_to_sink<R: Read>(
+    out: &mut Archive<R>,
+    sink: &mut dyn EntrySink,
+    opts: &ExtractOpts,
+) -> Result<(), DecompressError> {
+    let mut written = 0;
+    for (index, entry) in out.entries()?.enumerate() {
+        let mut entry = entry.map_err(entry_error)?;
+        let entry_type = entry.header().entry_type();
+        if entry_type == tar::EntryType::XGlobalHeader {
+            continue;
+        }
+        // the rest of a file begun in the previous volume, which we don't have
+        if entry_type.as_byte() == b'M' {
+            return Err(DecompressError::UnsupportedFeature(
+                "GNU multi-volume tar".to_string(),
+            ));
+        }
+
+        let sparse = pax_sparse(&mut entry)?;
+        let entry_path = entry_path(&entry, sparse.as_ref())?;
+        check_path(&entry_path, opts)?;
+        check_depth(&entry_path, opts)?;
+        let kind = entry_kind(entry_type);
+        let size = sparse.as_ref().map_or_else(|| entry.size(), |s| s.realsize);
+
+        let path = sink_path(opts, &entry_path, kind, index);
+        let Some(path) = path.filter(|_| kind != RelPathKind::Other) else {
+            notify_entry(opts, &entry_path, kind, Some(size), false);
+            continue;
+        };
+        if sparse.is_some() {
+            return Err(DecompressError::UnsupportedFeature(format!(
+                "PAX sparse entry in a sink: {}",
+                entry_path.display()
+            )));
+        }
+        written += 1;
+        check_entry_limit(opts, written)?;
+
+        let path = RelPath::new(path);
+        match kind {
+            RelPathKind::Symlink => {
+                let target = entry.link_name_bytes().unwrap_or_default().into_owned();
+                sink.write_entry(&path, kind, &mut target.as_slice())?;
+            }
+            RelPathKind::Dir => sink.write_entry(&path, kind, &mut io::empty())?,
+            _ => sink.write_entry(&path, kind, &mut entry)?,
+        }
+        notify_entry(opts, &entry_path, kind, Some(size), true);
+    }
+    Ok(())
+}
+
+/// Walk the file entries for `Decompressor::entries`, on a separate thread as
+/// `tar_list_iter` does
+pub fn tar_entries<R: Read>(
+    build: impl FnOnce() -> Result<Archive<R>, DecompressError> + Send + 'static,
+    opts: &ExtractOpts,
+) -> EntryReaderIter {
+    walk_entries(opts, move |walker| {
+        let mut out = build()?;
+        for (index, entry) in out.entries()?.enumerate() {
+            let mut entry = entry.map_err(entry_error)?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.as_byte() == b'M' {
+                return Err(DecompressError::UnsupportedFeature(
+                    "GNU multi-volume tar".to_string(),
+                ));
+            }
+            if entry_kind(entry_type) != RelPathKind::File {
+                continue;
+            }
+            let sparse = pax_sparse(&mut entry)?;
+            let path = entry_path(&entry, sparse.as_ref())?;
+            let walked = WalkedEntry {
+                index,
+                size: sparse.as_ref().map_or_else(|| entry.size(), |s| s.realsize),
+                unsupported: sparse
+                    .map(|_| format!("PAX sparse entry read as a stream: {}", path.display())),
+                path,
+                compressed_size: None,
+            };
+            if !walker.file(walked, &mut entry) {
+                break;
+            }
+        }
+        Ok(())
+    })
 }
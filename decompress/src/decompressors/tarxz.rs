@@ -1,5 +1,9 @@
-use crate::decompressors::tar_common::tar_extract;
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::tar_common::{tar_entries, tar_extract, tar_to_sink};
+use crate::decompressors::utils::{matches_name, DecoderReader};
+use crate::{
+    DecompressError, Decompression, Decompressor, EntryReaderIter, EntrySink, ExtractOpts, Listing,
+    ListingIter,
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
@@ -9,62 +13,133 @@ use std::{
 };
 use tar::Archive;
 
-use super::tar_common::tar_list;
+use super::tar_common::{is_tar_stream, tar_list, tar_list_iter, tar_verify};
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"(?i)\.t(ar\.xz|xz)$").unwrap();
 }
 
-fn build_archive(archive: &Path) -> Result<Archive<Box<dyn Read>>, DecompressError> {
-    let fd = BufReader::new(File::open(archive)?);
-    let out: Archive<Box<dyn Read>> = Archive::new(Box::new(xz::bufread::XzDecoder::new(fd)));
-    Ok(out)
+fn build_archive(rdr: Box<dyn Read>) -> Archive<Box<dyn Read>> {
+    let fd = BufReader::new(rdr);
+    let out: Archive<Box<dyn Read>> = Archive::new(Box::new(DecoderReader(
+        xz::bufread::XzDecoder::new_multi_decoder(fd),
+    )));
+    out
 }
 
 #[derive(Default)]
 pub struct Tarxz {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Tarxz {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Tarxz {
+    fn id(&self) -> &'static str {
+        "tarxz"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/x-xz"
     }
 
+    fn test_content(&self, archive: &Path) -> bool {
+        File::open(archive).map_or(false, |file| {
+            is_tar_stream(build_archive(Box::new(file)).into_inner())
+        })
+    }
+
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         Ok(Listing {
             id: "tarxz",
-            entries: tar_list(&mut build_archive(archive)?)?,
+            entries: tar_list(&mut build_archive(Box::new(File::open(archive)?)), opts)?,
         })
     }
 
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        let archive = archive.to_path_buf();
+        Ok(tar_list_iter(
+            move || Ok(build_archive(Box::new(File::open(archive)?))),
+            opts,
+        ))
+    }
+
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
+        tar_verify(build_archive(Box::new(File::open(archive)?)))
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
         opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        Ok(Decompression {
-            id: "tarxz",
-            files: tar_extract(&mut build_archive(archive)?, to, opts)?,
-        })
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        tar_extract(&mut build_archive(reader), to, opts, self.id())
+    }
+
+    fn decompress_to_sink(
+        &self,
+        archive: &Path,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        tar_to_sink(
+            &mut build_archive(Box::new(File::open(archive)?)),
+            sink,
+            opts,
+        )
+    }
+
+    fn entries(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        let archive = archive.to_path_buf();
+        Ok(tar_entries(
+            move || Ok(build_archive(Box::new(File::open(archive)?))),
+            opts,
+        ))
     }
 }
@@ -1,70 +1,187 @@
-use crate::decompressors::tar_common::tar_extract;
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::tar_common::{tar_entries, tar_extract, tar_to_sink};
+use crate::decompressors::utils::{matches_name, zstd_decoder};
+use crate::{
+    DecompressError, Decompression, Decompressor, EntryReaderIter, EntrySink, ExtractOpts, Listing,
+    ListingIter,
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
     fs::File,
-    io::{BufReader, Read},
+    io::{self, BufReader, Read},
     path::Path,
 };
 use tar::Archive;
 
-use super::tar_common::tar_list;
+use super::tar_common::{is_tar_stream, tar_list, tar_list_iter, tar_verify};
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"(?i)\.t(ar\.zst|zst)$").unwrap();
 }
 
-fn build_archive(archive: &Path) -> Result<Archive<Box<dyn Read>>, DecompressError> {
-    let fd = BufReader::new(File::open(archive)?);
-    let out: Archive<Box<dyn Read>> = Archive::new(Box::new(zstd::stream::read::Decoder::new(fd)?));
+fn build_archive(
+    rdr: Box<dyn Read>,
+    dictionary: Option<&[u8]>,
+    window_log_max: Option<u32>,
+) -> Result<Archive<Box<dyn Read>>, DecompressError> {
+    let fd = BufReader::new(rdr);
+    let out: Archive<Box<dyn Read>> =
+        Archive::new(Box::new(zstd_decoder(fd, dictionary, window_log_max)?));
     Ok(out)
 }
 
 #[derive(Default)]
 pub struct Tarzst {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Tarzst {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Tarzst {
+    fn id(&self) -> &'static str {
+        "tarzst"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
-        archive == "application/x-bzip2"
+        archive == "application/zstd"
+    }
+
+    fn test_content(&self, archive: &Path) -> bool {
+        // without the dictionary, if one is needed, the stream can't be told apart
+        File::open(archive).map_or(false, |file| {
+            build_archive(Box::new(file), None, None)
+                .map_or(true, |archive| is_tar_stream(archive.into_inner()))
+        })
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         Ok(Listing {
             id: "tarzst",
-            entries: tar_list(&mut build_archive(archive)?)?,
+            entries: tar_list(
+                &mut build_archive(
+                    Box::new(File::open(archive)?),
+                    opts.zstd_dictionary.as_deref(),
+                    opts.zstd_window_log_max,
+                )?,
+                opts,
+            )?,
         })
     }
 
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        let archive = archive.to_path_buf();
+        let dictionary = opts.zstd_dictionary.clone();
+        let window_log_max = opts.zstd_window_log_max;
+        Ok(tar_list_iter(
+            move || {
+                build_archive(
+                    Box::new(File::open(archive)?),
+                    dictionary.as_deref(),
+                    window_log_max,
+                )
+            },
+            opts,
+        ))
+    }
+
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        tar_verify(build_archive(
+            Box::new(File::open(archive)?),
+            opts.zstd_dictionary.as_deref(),
+            opts.zstd_window_log_max,
+        )?)
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
         opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        Ok(Decompression {
-            id: "tarzst",
-            files: tar_extract(&mut build_archive(archive)?, to, opts)?,
-        })
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let mut archive = build_archive(
+            reader,
+            opts.zstd_dictionary.as_deref(),
+            opts.zstd_window_log_max,
+        )?;
+        let decompression = tar_extract(&mut archive, to, opts, self.id())?;
+        // the content checksum comes at the end of the frame, past the end-of-archive
+        // blocks where tar stops reading
+        io::copy(&mut archive.into_inner(), &mut io::sink())?;
+        Ok(decompression)
+    }
+
+    fn decompress_to_sink(
+        &self,
+        archive: &Path,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        let mut archive = build_archive(
+            Box::new(File::open(archive)?),
+            opts.zstd_dictionary.as_deref(),
+            opts.zstd_window_log_max,
+        )?;
+        tar_to_sink(&mut archive, sink, opts)
+    }
+
+    fn entries(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        let archive = archive.to_path_buf();
+        let dictionary = opts.zstd_dictionary.clone();
+        let window_log_max = opts.zstd_window_log_max;
+        Ok(tar_entries(
+            move || {
+                build_archive(
+                    Box::new(File::open(archive)?),
+                    dictionary.as_deref(),
+                    window_log_max,
+                )
+            },
+            opts,
+        ))
     }
 }
@@ -1,79 +1,308 @@
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::utils::{
+    apply_fixed_mtime, check_depth, check_entry_limit, check_path, create_dir_all, matches_name,
+    move_into_place, notify_entry, Manifest,
+};
+use crate::{
+    DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing, RelPathKind,
+};
 
 lazy_static! {
-    static ref RE: Regex = Regex::new(r"(?i)\.rar$").unwrap();
+    static ref RE: Regex = Regex::new(r"(?i)\.(rar|r\d{2,})$").unwrap();
+    static ref RE_PART: Regex = Regex::new(r"(?i)\.part(\d+)\.rar$").unwrap();
+    static ref RE_OLD_VOLUME: Regex = Regex::new(r"(?i)\.r\d{2,}$").unwrap();
 }
 
+const LOG_TARGET: &str = "decompress::rar";
+
+/// The volume a split archive starts at: `.part1.rar` for `.partN.rar` sets, and the
+/// `.rar` for old style `.r00`, `.r01`, .. sets. unrar continues into the next volumes
+/// from there. Anything else (or a set missing its first volume) is opened as given
+fn first_volume(archive: &Path) -> PathBuf {
+    let Some(name) = archive.file_name().and_then(std::ffi::OsStr::to_str) else {
+        return archive.to_path_buf();
+    };
+    let first = RE_PART.captures(name).map_or_else(
+        || RE_OLD_VOLUME.replace(name, ".rar"),
+        |caps| RE_PART.replace(name, format!(".part{:01$}.rar", 1, caps[1].len())),
+    );
+    let first = archive.with_file_name(first.as_ref());
+    if first.exists() {
+        first
+    } else {
+        archive.to_path_buf()
+    }
+}
+
+fn rar_archive(archive: &Path) -> unrar::Archive<'static> {
+    unrar::Archive::new(first_volume(archive).to_string_lossy().to_string())
+}
+
+/// Like the entry's `Display`, without the ` (partial)` mark: entries are read across all
+/// volumes, so a split one is whole
+fn entry_name(entry: &unrar::archive::Entry) -> String {
+    if entry.is_directory() {
+        format!("{}/", entry.filename)
+    } else {
+        entry.filename.clone()
+    }
+}
+
+/// unrar errors, as `DecompressError::CorruptArchive` when the archive itself is at
+/// fault (including input that isn't a RAR archive at all)
+fn rar_error<T>(err: &unrar::error::UnrarError<T>) -> DecompressError {
+    use unrar::error::Code;
+    match err.code {
+        Code::BadData | Code::BadArchive | Code::UnknownFormat | Code::EndArchive => {
+            DecompressError::CorruptArchive(err.to_string())
+        }
+        _ => DecompressError::Error(err.to_string()),
+    }
+}
+
+fn extract_entries(
+    archive: &Path,
+    to: &Path,
+) -> Result<Vec<unrar::archive::Entry>, DecompressError> {
+    rar_archive(archive)
+        .extract_to(to.to_string_lossy().to_string())
+        .map_err(|e| rar_error(&e))?
+        .process()
+        .map_err(|e| rar_error(&e))
+}
+
+/// Move the files unrar extracted into `staging` over to `to`, for `atomic_writes`
+fn move_staged(
+    staging: &Path,
+    to: &Path,
+    entries: &[unrar::archive::Entry],
+    opts: &ExtractOpts,
+) -> Result<(), DecompressError> {
+    for entry in entries {
+        let path = to.join(&entry.filename);
+        if entry.is_directory() {
+            create_dir_all(&path, opts)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent, opts)?;
+        }
+        move_into_place(&staging.join(&entry.filename), &path)?;
+    }
+    Ok(())
+}
+
+fn list_entries(archive: &Path) -> Result<Vec<unrar::archive::Entry>, DecompressError> {
+    rar_archive(archive)
+        .list()
+        .map_err(|e| rar_error(&e))?
+        .process()
+        .map_err(|e| rar_error(&e))
+}
+
+/// RAR archives, including split sets (`.part1.rar`, `.part2.rar`, .. or `.rar`, `.r00`,
+/// `.r01`, ..) which are read across all volumes whichever one is passed in
 #[derive(Default)]
 pub struct Unrar {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 impl Unrar {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Unrar {
+    fn id(&self) -> &'static str {
+        "rar"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/vnd.rar"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
-        let res = unrar::Archive::new(archive.to_string_lossy().to_string())
-            .list()
-            .map_err(|e| DecompressError::Error(e.to_string()))?
-            .process()
-            .map_err(|e| DecompressError::Error(e.to_string()))?;
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        let res = list_entries(archive)?;
+
+        for entry in &res {
+            check_path(Path::new(&entry.filename), opts)?;
+        }
 
         Ok(Listing {
             id: "rar",
-            entries: res
-                .iter()
-                .map(std::string::ToString::to_string)
-                .collect::<Vec<_>>(),
+            entries: res.iter().map(entry_name).collect::<Vec<_>>(),
         })
     }
 
+    fn verify(&self, archive: &Path, _opts: &ExtractOpts) -> Result<(), DecompressError> {
+        rar_archive(archive)
+            .test()
+            .map_err(|e| rar_error(&e))?
+            .process()
+            .map_err(|e| rar_error(&e))?;
+        Ok(())
+    }
+
+    fn is_encrypted(&self, archive: &Path) -> Result<bool, DecompressError> {
+        match rar_archive(archive).list() {
+            // with encrypted headers not even the listing can be read
+            Err(err) if err.code == unrar::error::Code::MissingPassword => Ok(true),
+            Err(err) => Err(rar_error(&err)),
+            Ok(mut open) => Ok(open
+                .process()
+                .map_err(|e| rar_error(&e))?
+                .iter()
+                .any(unrar::archive::Entry::is_encrypted)),
+        }
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
-        _opts: &ExtractOpts,
+        opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
         use std::fs;
+
+        // unrar extracts the whole archive in one go, so entries are checked up front, from
+        // a single listing as every pass goes over all volumes
+        let checks_entries = opts.reject_unsafe_paths
+            || opts.max_entries.is_some()
+            || opts.max_path_depth.is_some()
+            || opts.resume_manifest.is_some();
+        let listed = if checks_entries {
+            list_entries(archive)?
+        } else {
+            vec![]
+        };
+        for entry in &listed {
+            check_path(Path::new(&entry.filename), opts)?;
+            check_depth(Path::new(&entry.filename), opts)?;
+        }
+        check_entry_limit(opts, listed.len())?;
+
+        // entries can't be extracted one by one, so resuming only helps once all are done
+        let mut manifest = Manifest::open(opts.resume_manifest.as_deref())?;
+        if opts.resume_manifest.is_some()
+            && listed
+                .iter()
+                .all(|entry| manifest.contains(Path::new(&entry.filename)))
+        {
+            log::debug!(target: LOG_TARGET, "skipped {}, already extracted", archive.display());
+            return Ok(Decompression::new("rar", to, vec![]));
+        }
+
         if !to.exists() {
-            fs::create_dir_all(to)?;
+            create_dir_all(to, opts)?;
         }
 
-        let res = unrar::Archive::new(archive.to_string_lossy().to_string())
-            .extract_to(to.to_string_lossy().to_string())
-            .map_err(|e| DecompressError::Error(e.to_string()))?
-            .process()
-            .map_err(|e| DecompressError::Error(e.to_string()))?;
+        log::trace!(target: LOG_TARGET, "extracting {} to {}", archive.display(), to.display());
+        // unrar writes the files itself, so atomic writes go through a folder of their own
+        let staging = opts
+            .atomic_writes
+            .then(|| to.join(format!(".decompress-{}.tmp", std::process::id())));
+        let res = extract_entries(archive, staging.as_deref().unwrap_or(to));
+        if let Some(staging) = &staging {
+            let moved = res
+                .as_ref()
+                .map_or(Ok(()), |entries| move_staged(staging, to, entries, opts));
+            let _ = fs::remove_dir_all(staging);
+            moved?;
+        }
+        let res = res?;
+
+        // unrar creates the folders itself, so `dir_mode` is set once it's done
+        #[cfg(unix)]
+        if let Some(mode) = opts.dir_mode {
+            use std::os::unix::fs::PermissionsExt;
+            let dirs = res
+                .iter()
+                .flat_map(|entry| {
+                    let path = Path::new(&entry.filename);
+                    let skip = usize::from(!entry.is_directory());
+                    path.ancestors().skip(skip).map(Path::to_path_buf)
+                })
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect::<std::collections::BTreeSet<_>>();
+            // deepest first, a parent without `x` would keep us from reaching its children
+            for dir in dirs.iter().rev() {
+                fs::set_permissions(to.join(dir), fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        for entry in &res {
+            let kind = if entry.is_directory() {
+                RelPathKind::Dir
+            } else {
+                RelPathKind::File
+            };
+            log::debug!(target: LOG_TARGET, "extracted {}", Path::new(&entry.filename).display());
+            if !manifest.contains(Path::new(&entry.filename)) {
+                manifest.record(Path::new(&entry.filename))?;
+            }
+            notify_entry(
+                opts,
+                Path::new(&entry.filename),
+                kind,
+                Some(u64::from(entry.unpacked_size)),
+                true,
+            );
+        }
+
+        // unrar doesn't hand out entry times
+        let file_infos = res
+            .iter()
+            .filter(|entry| entry.is_file())
+            .map(|entry| ExtractedFile {
+                path: to.join(&entry.filename).to_string_lossy().to_string(),
+                mtime: None,
+                size: u64::from(entry.unpacked_size),
+                hash: None,
+            })
+            .collect::<Vec<_>>();
+        let dirs = res
+            .iter()
+            .filter(|entry| entry.is_directory())
+            .map(|entry| to.join(&entry.filename))
+            .collect::<Vec<_>>();
+        apply_fixed_mtime(to, &file_infos, &dirs, opts)?;
 
         Ok(Decompression {
             id: "rar",
-            files: res
-                .iter()
-                .map(std::string::ToString::to_string)
-                .collect::<Vec<_>>(),
+            files: res.iter().map(entry_name).collect::<Vec<_>>(),
+            to: to.to_path_buf(),
+            file_infos,
+            format_chain: vec!["rar"],
+            renamed: vec![],
+            warnings: vec![],
+            skipped: vec![],
+            content_index: vec![],
         })
     }
 }
@@ -1,3 +1,33 @@
+#[cfg(extracts_trees)]
+use crate::CaseCollisionPolicy;
+#[cfg(writes_entries)]
+use crate::ConflictResolution;
+#[cfg(any_format)]
+use crate::DecompressError;
+use crate::ExtractOpts;
+#[cfg(records_entries)]
+use crate::ExtractedFile;
+use crate::RelPathKind;
+#[cfg(notifies_entries)]
+use crate::{EntryInfo, RelPath};
+#[cfg(records_entries)]
+use filetime::FileTime;
+use std::borrow::Cow;
+#[cfg(checks_paths)]
+use std::path::Component;
+use std::path::Path;
+#[cfg(archive)]
+use std::path::PathBuf;
+#[cfg(has_mtimes)]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(records_entries)]
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::Write,
+};
+use std::{fs, io};
+
+#[cfg(has_modes)]
 pub fn normalize_mode(mode: u32) -> u32 {
     if mode == 0 {
         0o644
@@ -5,3 +35,1115 @@ pub fn normalize_mode(mode: u32) -> u32 {
         mode
     }
 }
+
+/// Returns `true` if a path is absolute or has a `..` component, and so may escape
+/// the output folder
+#[cfg(checks_paths)]
+pub fn is_unsafe_path(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::ParentDir | Component::RootDir | Component::Prefix(..) => true,
+        Component::Normal(..) | Component::CurDir => false,
+    })
+}
+
+/// Reject an unsafe entry path when `reject_unsafe_paths` is on
+///
+/// # Errors
+///
+/// Returns `DecompressError::PathEscape` for an unsafe path
+#[cfg(checks_paths)]
+pub fn check_path(path: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+    if opts.reject_unsafe_paths && is_unsafe_path(path) {
+        return Err(DecompressError::PathEscape(
+            path.to_string_lossy().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Report an entry to `on_entry`, if set
+#[cfg(notifies_entries)]
+pub fn notify_entry(
+    opts: &ExtractOpts,
+    path: &Path,
+    kind: RelPathKind,
+    size: Option<u64>,
+    extracted: bool,
+) {
+    if let Some(on_entry) = &opts.on_entry {
+        on_entry(&EntryInfo {
+            path: RelPath::new(path),
+            kind,
+            size,
+            extracted,
+            total_expected: None,
+        });
+    }
+}
+
+/// `fs::create_dir_all`, giving every folder it had to create `dir_mode` on unix
+pub fn create_dir_all(path: &Path, opts: &ExtractOpts) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = opts.dir_mode {
+        use std::os::unix::fs::PermissionsExt;
+        let created = path
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        fs::create_dir_all(path)?;
+        // deepest first, a parent without `x` would keep us from reaching its children
+        for dir in created {
+            fs::set_permissions(dir, fs::Permissions::from_mode(mode))?;
+        }
+        return Ok(());
+    }
+    #[cfg(not(unix))]
+    let _ = opts;
+    fs::create_dir_all(path)
+}
+
+/// The output path for an entry, through `map_opt` when set or else `map`. `None`
+/// means the entry is skipped
+pub fn map_path<'a>(opts: &ExtractOpts, path: &'a Path) -> Option<Cow<'a, Path>> {
+    opts.map_opt
+        .as_ref()
+        .map_or_else(|| Some((opts.map)(path)), |map_opt| map_opt(path))
+}
+
+/// The first `content_filter_prefix` bytes of a file entry, read off `rdr` to hand to
+/// `content_filter`, or `None` when the filter leaves the entry out. Nothing is read
+/// without a filter or for other kinds of entries. The bytes returned need writing
+/// before the rest of `rdr`
+#[cfg(extracts_trees)]
+pub fn peek_content(
+    opts: &ExtractOpts,
+    path: &Path,
+    kind: RelPathKind,
+    rdr: &mut impl io::Read,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut head = vec![];
+    let Some(content_filter) = opts
+        .content_filter
+        .as_ref()
+        .filter(|_| kind == RelPathKind::File)
+    else {
+        return Ok(Some(head));
+    };
+    let prefix = u64::try_from(opts.content_filter_prefix).unwrap_or(u64::MAX);
+    io::Read::read_to_end(&mut io::Read::take(rdr, prefix), &mut head)?;
+    Ok(content_filter(&RelPath::new(path), &head).then_some(head))
+}
+
+/// A regex matching whatever any of `res` matches, and nothing for an empty slice. Each
+/// pattern is grouped, so its inline flags don't carry over to the next
+#[cfg(any_format)]
+pub fn any_of(res: &[regex::Regex]) -> regex::Regex {
+    if res.is_empty() {
+        return regex::Regex::new(r"\b\B").unwrap();
+    }
+    let alternatives = res
+        .iter()
+        .map(|re| format!("(?:{})", re.as_str()))
+        .collect::<Vec<_>>();
+    // every alternative is a valid regex on its own, so their union is too
+    regex::Regex::new(&alternatives.join("|")).unwrap()
+}
+
+/// Whether an archive's file name matches `re`, or `default` when there's none, or
+/// `extra`: the names a decompressor made with `build`, `build_multi` or
+/// `build_extended` picks
+#[cfg(any_format)]
+pub fn matches_name(
+    re: Option<&regex::Regex>,
+    default: &regex::Regex,
+    extra: Option<&regex::Regex>,
+    archive: &Path,
+) -> bool {
+    archive
+        .file_name()
+        .and_then(std::ffi::OsStr::to_str)
+        .map_or(false, |f| {
+            re.unwrap_or(default).is_match(f) || extra.map_or(false, |extra| extra.is_match(f))
+        })
+}
+
+/// The relative path once every matching `rename_rules` rule has been applied to it
+pub fn rename<'a>(opts: &ExtractOpts, path: &'a Path, kind: RelPathKind) -> Cow<'a, Path> {
+    let mut path = Cow::Borrowed(path);
+    for rule in &opts.rename_rules {
+        if let Some(pattern) = &rule.matches {
+            let text = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if !glob_matches(pattern, &text) {
+                continue;
+            }
+        }
+        if let Some((from, to)) = &rule.replace_prefix {
+            if let Ok(rest) = path.strip_prefix(from) {
+                path = Path::new(to).join(rest).into();
+            }
+        }
+        if let Some(suffix) = rule.suffix.as_ref().filter(|_| kind == RelPathKind::File) {
+            if let Some(name) = path.file_name() {
+                let mut name = name.to_os_string();
+                name.push(suffix);
+                path = path.with_file_name(name).into();
+            }
+        }
+    }
+    path
+}
+
+/// Whether a `/`-separated path matches a glob, where `?` and `*` stay within a
+/// folder name and `**` crosses folders. The text is read once, tracking every pattern
+/// position matched so far, so hostile entry names can't make it backtrack
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    // `reached[j]`: the text read so far matches `pattern[..j]`. `within[j]`: it matches
+    // `pattern[..j]` and then some of a `**/` there, which still needs its `/`
+    let mut reached = vec![false; pattern.len() + 1];
+    let mut within = reached.clone();
+    let (mut next, mut next_within) = (reached.clone(), reached.clone());
+    reached[0] = true;
+    skip_stars(pattern, &mut reached);
+    for c in text.chars() {
+        next.fill(false);
+        next_within.fill(false);
+        for j in (0..pattern.len()).filter(|&j| reached[j] || within[j]) {
+            let rest = &pattern[j..];
+            if rest.starts_with("**/") {
+                next_within[j] = true;
+                next[j + 3] |= c == '/';
+            } else if rest.starts_with("**") {
+                next[j] = true;
+            } else if rest.starts_with('*') {
+                next[j] |= c != '/';
+            } else if let Some(p) = rest.chars().next() {
+                next[j + p.len_utf8()] |= p == c || (p == '?' && c != '/');
+            }
+        }
+        skip_stars(pattern, &mut next);
+        std::mem::swap(&mut reached, &mut next);
+        std::mem::swap(&mut within, &mut next_within);
+    }
+    reached[pattern.len()]
+}
+
+/// Also mark the pattern positions past the stars that can match nothing. `**/` matches
+/// nothing or up to a `/`, `**` anything, and `*` anything but a `/`
+fn skip_stars(pattern: &str, reached: &mut [bool]) {
+    // stars only skip forward, so a single pass follows chains of them
+    for (j, _) in pattern.char_indices() {
+        let rest = &pattern[j..];
+        let skip = if rest.starts_with("**/") {
+            3
+        } else if rest.starts_with("**") {
+            2
+        } else {
+            usize::from(rest.starts_with('*'))
+        };
+        if reached[j] && skip > 0 {
+            reached[j + skip] = true;
+        }
+    }
+}
+
+/// The output path for a file entry with its name prefixed by the entry's zero-padded
+/// position in the archive, when `number_entries` is set. Folders keep their name
+#[cfg(numbers_entries)]
+pub fn numbered<'a>(
+    opts: &ExtractOpts,
+    path: Cow<'a, Path>,
+    kind: RelPathKind,
+    index: usize,
+) -> Cow<'a, Path> {
+    if !opts.number_entries || kind == RelPathKind::Dir {
+        return path;
+    }
+    let Some(name) = path.file_name() else {
+        return path;
+    };
+    let mut numbered = std::ffi::OsString::from(format!("{index:05}_"));
+    numbered.push(name);
+    path.with_file_name(numbered).into()
+}
+
+/// The output path for a file entry once `on_conflict` had its say about an existing
+/// file there. `None` means the entry is skipped
+///
+/// # Errors
+///
+/// Returns `DecompressError::Aborted` when `on_conflict` says so
+#[cfg(writes_entries)]
+pub fn resolve_conflict<'a>(
+    opts: &ExtractOpts,
+    path: Cow<'a, Path>,
+    kind: RelPathKind,
+) -> Result<Option<Cow<'a, Path>>, DecompressError> {
+    let Some(on_conflict) = &opts.on_conflict else {
+        return Ok(Some(path));
+    };
+    if kind == RelPathKind::Dir || path.symlink_metadata().is_err() {
+        return Ok(Some(path));
+    }
+    match on_conflict(&path) {
+        ConflictResolution::Overwrite => Ok(Some(path)),
+        ConflictResolution::Skip => Ok(None),
+        ConflictResolution::Abort => {
+            Err(DecompressError::Aborted(path.to_string_lossy().to_string()))
+        }
+        ConflictResolution::Rename => Ok(Some(Cow::Owned(free_name(&path)))),
+    }
+}
+
+/// `path` as `name (1).ext`, or with the first number after that no file has
+#[cfg(writes_entries)]
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut n = 1;
+    loop {
+        let renamed = path.with_file_name(format!("{stem} ({n}){ext}"));
+        if renamed.symlink_metadata().is_err() {
+            return renamed;
+        }
+        n += 1;
+    }
+}
+
+/// Whether `dir` is on a case-insensitive filesystem, by writing a scratch file there
+/// and looking for it under another case. `false` when that can't be told
+#[cfg(extracts_trees)]
+fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(format!(".Decompress-Case-Probe-{}", std::process::id()));
+    if fs::File::create(&probe).is_err() {
+        return false;
+    }
+    let folded = dir.join(format!(".decompress-case-probe-{}", std::process::id()));
+    let insensitive = folded.symlink_metadata().is_ok();
+    let _ = fs::remove_file(&probe);
+    insensitive
+}
+
+/// The files extracted so far by their lowercase paths, to catch entries that would
+/// land on the same file of a case-insensitive filesystem, per `case_collision`
+#[cfg(extracts_trees)]
+#[derive(Default)]
+pub struct CaseFolds {
+    seen: std::collections::HashMap<String, PathBuf>,
+    insensitive: Option<bool>,
+}
+
+#[cfg(extracts_trees)]
+impl CaseFolds {
+    /// Where a file entry goes, once checked against the files before it
+    ///
+    /// # Errors
+    ///
+    /// Returns `DecompressError::CaseCollision` for a collision under
+    /// `CaseCollisionPolicy::Error`
+    pub fn resolve<'a>(
+        &mut self,
+        opts: &ExtractOpts,
+        to: &Path,
+        path: Cow<'a, Path>,
+        kind: RelPathKind,
+    ) -> Result<Cow<'a, Path>, DecompressError> {
+        if kind == RelPathKind::Dir || opts.case_collision == CaseCollisionPolicy::Overwrite {
+            return Ok(path);
+        }
+        let folded = path.to_string_lossy().to_lowercase();
+        let seen = self.seen.get(&folded).filter(|seen| **seen != *path);
+        let path = match seen {
+            Some(seen)
+                if *self.insensitive.get_or_insert_with(|| {
+                    opts.assume_case_insensitive || is_case_insensitive(to)
+                }) =>
+            {
+                match opts.case_collision {
+                    CaseCollisionPolicy::Error => {
+                        return Err(DecompressError::CaseCollision {
+                            a: seen.to_string_lossy().to_string(),
+                            b: path.to_string_lossy().to_string(),
+                        })
+                    }
+                    CaseCollisionPolicy::Rename => Cow::Owned(free_name(&path)),
+                    CaseCollisionPolicy::Overwrite => path,
+                }
+            }
+            _ => path,
+        };
+        self.seen
+            .insert(path.to_string_lossy().to_lowercase(), path.to_path_buf());
+        Ok(path)
+    }
+}
+
+/// Copy an entry's contents from `rdr` to `outfile`, retrying writes that fail
+/// transiently up to `io_retries` times, and hashing the contents on the way when
+/// `write_manifest` or `content_addressed` is set. Returns the number of bytes written
+/// and the hex digest
+#[cfg(writes_entries)]
+pub fn write_contents(
+    opts: &ExtractOpts,
+    entry: &Path,
+    rdr: &mut impl io::Read,
+    outfile: &mut impl io::Write,
+) -> io::Result<(u64, Option<String>)> {
+    let mut outfile = RetryingWriter {
+        inner: outfile,
+        retries: opts.io_retries,
+    };
+    #[cfg(feature = "checksums")]
+    if let Some((_, algo)) = opts
+        .write_manifest
+        .as_ref()
+        .or(opts.content_addressed.as_ref())
+    {
+        let mut hashing = HashingWriter {
+            inner: &mut outfile,
+            digest: Digest::new(*algo),
+        };
+        let size = copy_contents(opts, entry, rdr, &mut hashing)?;
+        return Ok((size, Some(hashing.digest.finish())));
+    }
+    copy_contents(opts, entry, rdr, &mut outfile).map(|size| (size, None))
+}
+
+/// Copy an entry's contents from `rdr` to `outfile`, through `transform` when set.
+/// Entries smaller than `buffer_threshold` are read whole and written at once.
+/// Returns the number of bytes written
+#[cfg(writes_entries)]
+fn copy_contents(
+    opts: &ExtractOpts,
+    entry: &Path,
+    rdr: &mut impl io::Read,
+    outfile: &mut impl io::Write,
+) -> io::Result<u64> {
+    use io::Read;
+
+    if let Some(transform) = &opts.transform {
+        let mut buf = vec![];
+        rdr.read_to_end(&mut buf)?;
+        transform(&crate::RelPath::new(entry), &mut buf);
+        outfile.write_all(&buf)?;
+        return Ok(buf.len() as u64);
+    }
+    let Some(threshold) = opts.buffer_threshold else {
+        return io::copy(rdr, outfile);
+    };
+    let mut buf = vec![];
+    rdr.by_ref().take(threshold).read_to_end(&mut buf)?;
+    outfile.write_all(&buf)?;
+    // an entry that fills the buffer may go on, the rest of it streams
+    let rest = if buf.len() as u64 == threshold {
+        io::copy(rdr, outfile)?
+    } else {
+        0
+    };
+    Ok(buf.len() as u64 + rest)
+}
+
+/// Create an entry's output file, retrying up to `io_retries` times when that fails
+/// transiently
+///
+/// # Errors
+///
+/// Returns the error of the last attempt, or the first one that isn't transient
+#[cfg(writes_entries)]
+pub fn create_file(opts: &ExtractOpts, path: &Path) -> io::Result<OutFile> {
+    let tmp = opts.atomic_writes.then(|| atomic_tmp_path(path));
+    // an entry may have left a link where the file is written aside, don't write through it
+    if let Some(tmp) = tmp.as_deref().filter(|tmp| tmp.symlink_metadata().is_ok()) {
+        fs::remove_file(tmp)?;
+    }
+    let file = retry(opts.io_retries, || {
+        fs::File::create(tmp.as_deref().unwrap_or(path))
+    })?;
+    Ok(OutFile {
+        file: Some(file),
+        tmp: tmp.map(|tmp| (tmp, path.to_path_buf())),
+    })
+}
+
+/// Where `atomic_writes` writes `path` before moving it into place: `.<name>.tmp`
+/// next to it
+#[cfg(writes_entries)]
+pub fn atomic_tmp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Move a file written aside by `atomic_writes` into place, by copying it over when it
+/// can't be renamed (such as across filesystems)
+#[cfg(archive)]
+pub fn move_into_place(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// An entry's output file, from `create_file`. With `atomic_writes` it's written aside
+/// and only moved into place by `persist`, and removed if dropped before that
+#[cfg(writes_entries)]
+pub struct OutFile {
+    file: Option<fs::File>,
+    tmp: Option<(PathBuf, PathBuf)>,
+}
+
+#[cfg(writes_entries)]
+impl OutFile {
+    /// Close the file, and with `atomic_writes` move it to its path
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file couldn't be moved into place
+    pub fn persist(mut self) -> io::Result<()> {
+        // closed first, an open file can't be renamed everywhere
+        self.file = None;
+        // on error, dropping it removes the temporary file
+        if let Some((tmp, path)) = &self.tmp {
+            move_into_place(tmp, path)?;
+            self.tmp = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(writes_entries)]
+impl Drop for OutFile {
+    fn drop(&mut self) {
+        self.file = None;
+        if let Some((tmp, _)) = self.tmp.take() {
+            let _ = fs::remove_file(tmp);
+        }
+    }
+}
+
+#[cfg(writes_entries)]
+impl std::ops::Deref for OutFile {
+    type Target = fs::File;
+
+    fn deref(&self) -> &fs::File {
+        self.file.as_ref().expect("open until persisted")
+    }
+}
+
+#[cfg(writes_entries)]
+impl std::ops::DerefMut for OutFile {
+    fn deref_mut(&mut self) -> &mut fs::File {
+        self.file.as_mut().expect("open until persisted")
+    }
+}
+
+#[cfg(writes_entries)]
+impl io::Write for OutFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Run `op` until it succeeds, fails with an error that isn't transient, or has been
+/// retried `retries` times, backing off a little longer each time
+#[cfg(writes_entries)]
+fn retry<T>(retries: u8, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(err) if attempt < retries && is_transient(&err) => {
+                std::thread::sleep(std::time::Duration::from_millis(10 << attempt.min(6)));
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Errors a flaky (network) filesystem may not repeat on a second try
+#[cfg(writes_entries)]
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Passes writes through, retrying the ones that fail transiently
+#[cfg(writes_entries)]
+struct RetryingWriter<'a, W> {
+    inner: &'a mut W,
+    retries: u8,
+}
+
+#[cfg(writes_entries)]
+impl<W: io::Write> io::Write for RetryingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        retry(self.retries, || self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        retry(self.retries, || self.inner.flush())
+    }
+}
+
+/// A hash in progress, of the algorithm `write_manifest` asks for
+#[cfg(all(writes_entries, feature = "checksums"))]
+enum Digest {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    Blake3(Box<blake3::Hasher>),
+}
+
+#[cfg(all(writes_entries, feature = "checksums"))]
+impl Digest {
+    fn new(algo: crate::HashAlgo) -> Self {
+        use sha2::Digest as _;
+        match algo {
+            crate::HashAlgo::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            crate::HashAlgo::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            crate::HashAlgo::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        use sha2::Digest as _;
+        match self {
+            Self::Sha256(hasher) => hasher.update(buf),
+            Self::Sha1(hasher) => hasher.update(buf),
+            Self::Blake3(hasher) => {
+                hasher.update(buf);
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        use sha2::Digest as _;
+        use std::fmt::Write as _;
+        let bytes = match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Blake3(hasher) => return hasher.finalize().to_hex().to_string(),
+        };
+        bytes.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+    }
+}
+
+/// Passes writes through, hashing what was written
+#[cfg(all(writes_entries, feature = "checksums"))]
+struct HashingWriter<'a, W> {
+    inner: &'a mut W,
+    digest: Digest,
+}
+
+#[cfg(all(writes_entries, feature = "checksums"))]
+impl<W: io::Write> io::Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Returns `true` unless `allowed_extensions` is set and a file's extension isn't in it
+#[cfg(filters_extensions)]
+pub fn is_allowed_extension(opts: &ExtractOpts, path: &Path, kind: RelPathKind) -> bool {
+    let Some(allowed) = opts.allowed_extensions.as_ref() else {
+        return true;
+    };
+    if kind == RelPathKind::Dir {
+        return true;
+    }
+    path.extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map_or(false, |ext| {
+            allowed
+                .iter()
+                .any(|a| a.trim_start_matches('.').eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Returns `true` for macOS metadata when `skip_apple_metadata` is set: anything under
+/// `__MACOSX/`, and the resource forks macOS stores as `._*` files
+#[cfg(extracts_trees)]
+pub fn is_apple_metadata(opts: &ExtractOpts, path: &Path) -> bool {
+    opts.skip_apple_metadata
+        && (path.components().any(|c| c.as_os_str() == "__MACOSX")
+            || path
+                .file_name()
+                .and_then(std::ffi::OsStr::to_str)
+                .map_or(false, |name| name.starts_with("._")))
+}
+
+/// Returns `true` for a character this platform doesn't allow in a file name
+#[cfg(extracts_trees)]
+fn is_illegal_char(c: char) -> bool {
+    if cfg!(windows) {
+        c.is_control() || r#"<>:"|?*"#.contains(c)
+    } else {
+        c == '\0'
+    }
+}
+
+/// The relative path with illegal characters replaced by `_` in every component, when
+/// `sanitize_names` is set
+#[cfg(extracts_trees)]
+pub fn sanitize<'a>(opts: &ExtractOpts, path: &'a Path) -> Cow<'a, Path> {
+    if !opts.sanitize_names || !path.to_string_lossy().contains(is_illegal_char) {
+        return path.into();
+    }
+    path.components()
+        .map(|c| match c {
+            Component::Normal(name) if name.to_string_lossy().contains(is_illegal_char) => {
+                name.to_string_lossy().replace(is_illegal_char, "_").into()
+            }
+            c => c.as_os_str().to_os_string(),
+        })
+        .collect::<PathBuf>()
+        .into()
+}
+
+/// The warning for an extracted entry whose name isn't valid UTF-8
+#[cfg(extracts_trees)]
+pub fn lossy_name_warning(path: &Path) -> String {
+    format!(
+        "entry name isn't valid UTF-8, see `filename_encoding`: `{}`",
+        path.display()
+    )
+}
+
+/// The path an entry is handed to an `EntrySink` under, relative to the sink, with the
+/// options that decide names and which entries to keep applied as when extracting.
+/// `None` skips the entry, as do paths that could escape a folder
+#[cfg(extracts_trees)]
+pub fn sink_path(
+    opts: &ExtractOpts,
+    path: &Path,
+    kind: RelPathKind,
+    index: usize,
+) -> Option<PathBuf> {
+    if is_unsafe_path(path) {
+        return None;
+    }
+    let filepath = path.components().skip(opts.strip).collect::<PathBuf>();
+    if filepath.as_os_str().is_empty() {
+        return None;
+    }
+    let filepath = rename(opts, &filepath, kind);
+    let filepath = sanitize(opts, &filepath);
+    let filepath = opts.wrap_in.as_ref().map_or_else(
+        || filepath.to_path_buf(),
+        |wrap_in| Path::new(wrap_in).join(&filepath),
+    );
+    if !(opts.filter)(&filepath)
+        || !is_allowed_extension(opts, path, kind)
+        || is_apple_metadata(opts, path)
+    {
+        return None;
+    }
+    let mapped = map_path(opts, &filepath)?;
+    Some(numbered(opts, mapped, kind, index).into_owned())
+}
+
+/// Fails on an entry path nested deeper than `max_path_depth`
+///
+/// # Errors
+///
+/// Returns `DecompressError::PathTooDeep` over the limit
+#[cfg(records_entries)]
+pub fn check_depth(path: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+    let Some(limit) = opts.max_path_depth else {
+        return Ok(());
+    };
+    let depth = RelPath::new(path).parts().len();
+    if depth > limit {
+        return Err(DecompressError::PathTooDeep { limit, depth });
+    }
+    Ok(())
+}
+
+/// Fails once `count` entries to write are more than `max_entries`
+///
+/// # Errors
+///
+/// Returns `DecompressError::TooManyEntries` over the limit
+#[cfg(notifies_entries)]
+pub fn check_entry_limit(opts: &ExtractOpts, count: usize) -> Result<(), DecompressError> {
+    match opts.max_entries {
+        Some(limit) if count > limit => Err(DecompressError::TooManyEntries { limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Fails on an entry that declares a size over `max_compression_ratio` times the
+/// size it takes in the archive
+///
+/// # Errors
+///
+/// Returns `DecompressError::SuspiciousRatio` over the limit
+#[cfg(checks_ratio)]
+pub fn check_ratio(
+    opts: &ExtractOpts,
+    entry: &Path,
+    compressed: u64,
+    size: u64,
+) -> Result<(), DecompressError> {
+    let Some(limit) = opts.max_compression_ratio else {
+        return Ok(());
+    };
+    if size == 0 {
+        return Ok(());
+    }
+    // sizes beyond 2^53 lose precision, which doesn't matter for a ratio
+    #[allow(clippy::cast_precision_loss)]
+    let ratio = size as f64 / compressed as f64;
+    if ratio > limit {
+        return Err(DecompressError::SuspiciousRatio {
+            entry: entry.to_string_lossy().to_string(),
+            ratio,
+        });
+    }
+    Ok(())
+}
+
+/// Decode a whole stream into a sink, reporting decoding errors as corruption
+///
+/// # Errors
+///
+/// Returns `DecompressError::CorruptArchive` if reading fails
+#[cfg(drains)]
+pub fn drain(rdr: &mut impl io::Read, what: &str) -> Result<(), DecompressError> {
+    io::copy(rdr, &mut io::sink())
+        .map(|_| ())
+        .map_err(|err| match DecompressError::from(err) {
+            DecompressError::IO(err) => DecompressError::CorruptArchive(format!("{what}: {err}")),
+            DecompressError::CorruptArchive(msg) => {
+                DecompressError::CorruptArchive(format!("{what}: {msg}"))
+            }
+            err => err,
+        })
+}
+
+/// The outcome of a run that may have collected errors under `continue_on_error`
+///
+/// # Errors
+///
+/// Returns the single error, or `DecompressError::Multiple`
+#[cfg(feature = "zip")]
+pub fn collected(mut errors: Vec<DecompressError>) -> Result<(), DecompressError> {
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(DecompressError::Multiple(errors)),
+    }
+}
+
+/// Whether `max_extract_depth` leaves out the entry at `path`, relative to `to`. The
+/// folders above it are created down to the limit all the same
+#[cfg(extracts_trees)]
+pub fn is_too_deep(opts: &ExtractOpts, to: &Path, path: &Path) -> io::Result<bool> {
+    let Some(limit) = opts.max_extract_depth else {
+        return Ok(false);
+    };
+    let parts = RelPath::new(path).parts();
+    if parts.len() <= limit {
+        return Ok(false);
+    }
+    let dir = to.join(parts[..limit].iter().collect::<PathBuf>());
+    if !dir.exists() {
+        create_dir_all(&dir, opts)?;
+    }
+    Ok(true)
+}
+
+/// Whether `only_if_newer` leaves the file at `path` as it is, being at least as new
+/// as its entry from `mtime`
+#[cfg(extracts_trees)]
+pub fn is_up_to_date(
+    opts: &ExtractOpts,
+    path: &Path,
+    kind: RelPathKind,
+    mtime: Option<SystemTime>,
+) -> bool {
+    if !opts.only_if_newer || kind == RelPathKind::Dir {
+        return false;
+    }
+    let Some(mtime) = mtime else {
+        return false;
+    };
+    fs::symlink_metadata(path)
+        .and_then(|meta| meta.modified())
+        .map_or(false, |existing| existing >= mtime)
+}
+
+/// A unix timestamp from an archive as a `SystemTime`
+#[cfg(has_mtimes)]
+pub fn system_time(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[cfg(extracts_trees)]
+pub fn unix_mtime(secs: i64) -> FileTime {
+    FileTime::from_unix_time(secs, 0)
+}
+
+/// Directory mtimes are recorded during extraction and applied once all entries
+/// are written, deepest first, so writing children doesn't clobber them.
+#[cfg(extracts_trees)]
+#[derive(Default)]
+pub struct DirMtimes(Vec<(PathBuf, FileTime)>);
+
+#[cfg(extracts_trees)]
+impl DirMtimes {
+    pub fn push(&mut self, dir: PathBuf, mtime: FileTime) {
+        self.0.push((dir, mtime));
+    }
+
+    pub fn apply(mut self) -> io::Result<()> {
+        self.0
+            .sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+        for (dir, mtime) in self.0 {
+            filetime::set_file_mtime(dir, mtime)?;
+        }
+        Ok(())
+    }
+}
+
+/// With `set_all_mtimes`, give every extracted file, the given folders, and the folders
+/// between them and `to` that fixed mtime. Links get it themselves, not their targets
+#[cfg(records_entries)]
+pub fn apply_fixed_mtime(
+    to: &Path,
+    files: &[ExtractedFile],
+    dirs: &[PathBuf],
+    opts: &ExtractOpts,
+) -> io::Result<()> {
+    let Some(mtime) = opts.set_all_mtimes else {
+        return Ok(());
+    };
+    let mtime = FileTime::from_system_time(mtime);
+    let mut folders = BTreeSet::new();
+    for file in files {
+        let path = Path::new(&file.path);
+        filetime::set_symlink_file_times(path, mtime, mtime)?;
+        folders.extend(path.ancestors().skip(1).take_while(|dir| *dir != to));
+    }
+    for dir in dirs.iter().filter(|dir| dir.exists()) {
+        folders.extend(dir.ancestors().take_while(|dir| *dir != to));
+    }
+    // children sort after their parents, so going backwards is deepest first
+    for dir in folders.iter().rev() {
+        filetime::set_file_mtime(dir, mtime)?;
+    }
+    Ok(())
+}
+
+/// The error reading an archive's next entry: an error the decoder underneath already
+/// classified is kept, truncated or malformed headers are `CorruptArchive` and anything
+/// else stays IO. The header parsers give their own errors as `Other`, and keep the kind
+/// of the read errors they wrap
+#[cfg(reads_headers)]
+pub fn entry_error(err: io::Error) -> DecompressError {
+    match DecompressError::from(err) {
+        DecompressError::IO(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof | io::ErrorKind::Other
+            ) =>
+        {
+            DecompressError::CorruptArchive(err.to_string())
+        }
+        err => err,
+    }
+}
+
+/// Errors decoders give for truncated or malformed input, as
+/// `DecompressError::CorruptArchive` carried in the `io::Error`
+#[cfg(decodes)]
+fn corrupt_input(err: io::Error) -> io::Error {
+    let classified = err
+        .get_ref()
+        .map_or(false, |inner| inner.is::<DecompressError>());
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput
+            if !classified =>
+        {
+            io::Error::new(err.kind(), DecompressError::CorruptArchive(err.to_string()))
+        }
+        _ => err,
+    }
+}
+
+/// A decoder's reader, telling its corrupt input apart from errors of the filesystem
+/// and of writers, which don't go through it
+#[cfg(decodes)]
+pub struct DecoderReader<R>(pub R);
+
+#[cfg(decodes)]
+impl<R: io::Read> io::Read for DecoderReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(corrupt_input)
+    }
+}
+
+/// `ZSTD_error_checksum_wrong`. zstd keeps its error codes stable, and its reader
+/// reports them by name
+#[cfg(zstd_codec)]
+const ZSTD_ERROR_CHECKSUM_WRONG: usize = 22;
+
+/// A zstd decoder that reports a content checksum that doesn't match as
+/// `DecompressError::ChecksumMismatch`, carried in the `io::Error`
+#[cfg(zstd_codec)]
+pub struct ZstdReader<R: std::io::BufRead>(zstd::stream::read::Decoder<'static, R>);
+
+#[cfg(zstd_codec)]
+impl<R: std::io::BufRead> io::Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|err| {
+            let checksum_wrong =
+                zstd::zstd_safe::get_error_name(0usize.wrapping_sub(ZSTD_ERROR_CHECKSUM_WRONG));
+            if err.to_string() == checksum_wrong {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    DecompressError::ChecksumMismatch(err.to_string()),
+                )
+            } else {
+                err
+            }
+        })
+    }
+}
+
+/// Build a zstd decoder, using a dictionary when one is given. A frame that requires
+/// a dictionary we weren't given is reported as corrupt instead of failing opaquely.
+/// Content checksums are verified as frames end
+#[cfg(zstd_codec)]
+pub fn zstd_decoder<R: std::io::BufRead>(
+    mut rdr: R,
+    dictionary: Option<&[u8]>,
+    window_log_max: Option<u32>,
+) -> Result<DecoderReader<ZstdReader<R>>, DecompressError> {
+    let mut dec = if let Some(dictionary) = dictionary {
+        zstd::stream::read::Decoder::with_dictionary(rdr, dictionary)?
+    } else {
+        if let Some(dict_id) = zstd::zstd_safe::get_dict_id_from_frame(rdr.fill_buf()?) {
+            return Err(DecompressError::CorruptArchive(format!(
+                "zstd frame requires dictionary {dict_id}, but no dictionary was given (see `zstd_dictionary`)"
+            )));
+        }
+        zstd::stream::read::Decoder::with_buffer(rdr)?
+    };
+    if let Some(window_log_max) = window_log_max {
+        dec.window_log_max(window_log_max)?;
+    }
+    Ok(DecoderReader(ZstdReader(dec)))
+}
+
+/// The entries written so far by an extraction, see `ExtractOpts::resume_manifest`
+#[cfg(records_entries)]
+#[derive(Default)]
+pub struct Manifest {
+    done: HashSet<String>,
+    file: Option<fs::File>,
+}
+
+#[cfg(records_entries)]
+impl Manifest {
+    /// Read the manifest if there is one, and open it for appending. Without a path
+    /// nothing is skipped or recorded
+    pub fn open(path: Option<&Path>) -> io::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let done = match fs::read_to_string(path) {
+            Ok(text) => text.lines().map(ToString::to_string).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err),
+        };
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self {
+            done,
+            file: Some(file),
+        })
+    }
+
+    pub fn contains(&self, entry: &Path) -> bool {
+        self.done.contains(entry.to_string_lossy().as_ref())
+    }
+
+    /// Append an entry once it's fully written
+    pub fn record(&mut self, entry: &Path) -> io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            let line = entry.to_string_lossy().to_string();
+            writeln!(file, "{line}")?;
+            self.done.insert(line);
+        }
+        Ok(())
+    }
+}
+
+/// Passes on only the `len` bytes that come after the first `start`, dropping the rest,
+/// for decompressing a range of a stream that can't seek
+pub struct RangeWriter<'a> {
+    inner: &'a mut dyn io::Write,
+    skip: u64,
+    remaining: u64,
+}
+
+impl<'a> RangeWriter<'a> {
+    pub fn new(inner: &'a mut dyn io::Write, start: u64, len: u64) -> Self {
+        Self {
+            inner,
+            skip: start,
+            remaining: len,
+        }
+    }
+
+    /// The bytes of the range still to be written
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl io::Write for RangeWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skipped = usize::try_from(self.skip)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        self.skip -= skipped as u64;
+        let rest = &buf[skipped..];
+        let take = usize::try_from(self.remaining)
+            .unwrap_or(usize::MAX)
+            .min(rest.len());
+        self.inner.write_all(&rest[..take])?;
+        self.remaining -= take as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    cell::RefCell,
+    io::{self, Read},
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::decompressors::utils::{
+    check_depth, check_entry_limit, check_path, check_ratio, notify_entry, sink_path,
+};
+use crate::{DecompressError, EntryReaderIter, ExtractOpts, RelPath, RelPathKind};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file entry as the walk found it, before the options are applied
+pub struct WalkedEntry {
+    /// the entry's index in the archive
+    pub index: usize,
+    pub path: PathBuf,
+    pub size: u64,
+    pub compressed_size: Option<u64>,
+    /// why the entry can't be read, which fails the iteration unless it's left out
+    pub unsupported: Option<String>,
+}
+
+enum Walked {
+    Entry(WalkedEntry),
+    Chunk(io::Result<Vec<u8>>),
+}
+
+type Message = Result<Walked, DecompressError>;
+
+/// The walking thread's end
+pub struct Walker {
+    tx: SyncSender<Message>,
+    // how many entries the iterator has moved past
+    advanced: Arc<AtomicUsize>,
+    sent: usize,
+}
+
+impl Walker {
+    /// Send an entry and then the contents `rdr` has, until the iterator moves past
+    /// it. Returns `false` once the iterator is dropped, to end the walk
+    pub fn file(&mut self, entry: WalkedEntry, rdr: &mut dyn Read) -> bool {
+        self.sent += 1;
+        if self.tx.send(Ok(Walked::Entry(entry))).is_err() {
+            return false;
+        }
+        let mut buf = vec![0; CHUNK_SIZE];
+        while self.advanced.load(Ordering::Acquire) < self.sent {
+            let chunk = match rdr.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if self.tx.send(Ok(Walked::Chunk(chunk))).is_err() {
+                return false;
+            }
+            if failed {
+                break;
+            }
+        }
+        true
+    }
+}
+
+/// The caller's end, shared by the iterator and the readers it hands out
+struct Shared {
+    rx: Receiver<Message>,
+    // a message past the current entry's contents, read by a reader
+    pending: Option<Message>,
+    // the entry that can be read, counting the entries sent from 1
+    current: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Shared {
+    fn recv(&mut self) -> Option<Message> {
+        self.pending.take().or_else(|| self.rx.recv().ok())
+    }
+}
+
+struct EntryReader {
+    shared: Rc<RefCell<Shared>>,
+    entry: usize,
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut shared = self.shared.borrow_mut();
+        if shared.current != self.entry {
+            return Ok(0);
+        }
+        while shared.pos == shared.chunk.len() {
+            if shared.pending.is_some() {
+                return Ok(0);
+            }
+            match shared.rx.recv() {
+                Ok(Ok(Walked::Chunk(chunk))) => {
+                    shared.chunk = chunk?;
+                    shared.pos = 0;
+                }
+                Ok(next) => {
+                    shared.pending = Some(next);
+                    return Ok(0);
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(shared.chunk.len() - shared.pos);
+        buf[..n].copy_from_slice(&shared.chunk[shared.pos..shared.pos + n]);
+        shared.pos += n;
+        Ok(n)
+    }
+}
+
+struct Entries {
+    shared: Rc<RefCell<Shared>>,
+    advanced: Arc<AtomicUsize>,
+    opts: ExtractOpts,
+    written: usize,
+    done: bool,
+}
+
+impl Entries {
+    /// The name to hand `entry` out under, or `None` to leave it out, deciding as
+    /// `tar_to_sink` does
+    fn accept(&mut self, entry: WalkedEntry) -> Result<Option<PathBuf>, DecompressError> {
+        let opts = &self.opts;
+        check_path(&entry.path, opts)?;
+        check_depth(&entry.path, opts)?;
+        let Some(path) = sink_path(opts, &entry.path, RelPathKind::File, entry.index) else {
+            notify_entry(
+                opts,
+                &entry.path,
+                RelPathKind::File,
+                Some(entry.size),
+                false,
+            );
+            return Ok(None);
+        };
+        if let Some(unsupported) = entry.unsupported {
+            return Err(DecompressError::UnsupportedFeature(unsupported));
+        }
+        self.written += 1;
+        check_entry_limit(opts, self.written)?;
+        if let Some(compressed) = entry.compressed_size {
+            check_ratio(opts, &entry.path, compressed, entry.size)?;
+        }
+        notify_entry(opts, &entry.path, RelPathKind::File, Some(entry.size), true);
+        Ok(Some(path))
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<(RelPath, Box<dyn Read>), DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let message = {
+                let mut shared = self.shared.borrow_mut();
+                // the walk stops sending the current entry, and what it sent is dropped
+                self.advanced.store(shared.current, Ordering::Release);
+                shared.chunk.clear();
+                shared.pos = 0;
+                let Some(message) = shared.recv() else {
+                    break;
+                };
+                if matches!(message, Ok(Walked::Entry(_))) {
+                    shared.current += 1;
+                }
+                message
+            };
+            let entry = match message {
+                Ok(Walked::Entry(entry)) => entry,
+                Ok(Walked::Chunk(_)) => continue,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+            match self.accept(entry) {
+                Ok(Some(path)) => {
+                    let reader = EntryReader {
+                        shared: Rc::clone(&self.shared),
+                        entry: self.shared.borrow().current,
+                    };
+                    return Some(Ok((RelPath::new(path), Box::new(reader))));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// Run `walk` on a separate thread, handing the entries it sends to `Walker::file` out
+/// lazily, for `Decompressor::entries`. Entries and then their contents in chunks go
+/// over one bounded channel, so the walk keeps at most a few chunks ahead of the
+/// reading. Dropping the iterator ends the walk at the next entry
+pub fn walk_entries(
+    opts: &ExtractOpts,
+    walk: impl FnOnce(&mut Walker) -> Result<(), DecompressError> + Send + 'static,
+) -> EntryReaderIter {
+    let (tx, rx) = mpsc::sync_channel(16);
+    let advanced = Arc::new(AtomicUsize::new(0));
+    let mut walker = Walker {
+        tx,
+        advanced: Arc::clone(&advanced),
+        sent: 0,
+    };
+    thread::spawn(move || {
+        if let Err(err) = walk(&mut walker) {
+            let _res = walker.tx.send(Err(err));
+        }
+    });
+    Box::new(Entries {
+        shared: Rc::new(RefCell::new(Shared {
+            rx,
+            pending: None,
+            current: 0,
+            chunk: vec![],
+            pos: 0,
+        })),
+        advanced,
+        opts: opts.clone(),
+        written: 0,
+        done: false,
+    })
+}
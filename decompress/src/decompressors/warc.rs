@@ -0,0 +1,382 @@
+#[cfg(feature = "warcgz")]
+use crate::decompressors::utils::DecoderReader;
+use crate::decompressors::utils::{
+    check_entry_limit, create_dir_all, create_file, is_allowed_extension, map_path, matches_name,
+    notify_entry, numbered, rename, resolve_conflict, write_contents,
+};
+use crate::{
+    DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing, RelPathKind,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    static ref RE: Regex = Regex::new(r"(?i)\.warc$").unwrap();
+}
+
+#[cfg(feature = "warcgz")]
+lazy_static! {
+    static ref RE_GZ: Regex = Regex::new(r"(?i)\.warc\.gz$").unwrap();
+}
+
+/// The WARC headers we care about, the record block follows them
+struct Record {
+    kind: String,
+    target_uri: Option<String>,
+    id: Option<String>,
+    content_length: u64,
+}
+
+fn corrupt(msg: &str) -> DecompressError {
+    DecompressError::CorruptArchive(format!("warc: {msg}"))
+}
+
+/// Read the next record's headers, leaving the reader at the start of its block
+fn next_record(rdr: &mut impl BufRead) -> Result<Option<Record>, DecompressError> {
+    let mut line = String::new();
+    // records are separated by blank lines
+    loop {
+        line.clear();
+        if rdr.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    if !line.starts_with("WARC/") {
+        return Err(corrupt("expected a `WARC/` version line"));
+    }
+
+    let mut warc_type = None;
+    let mut target_uri = None;
+    let mut record_id = None;
+    let mut content_length = None;
+    loop {
+        line.clear();
+        if rdr.read_line(&mut line)? == 0 {
+            return Err(corrupt("truncated record header"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim().to_string();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "warc-type" => warc_type = Some(value),
+                "warc-target-uri" => target_uri = Some(value),
+                "warc-record-id" => record_id = Some(value),
+                "content-length" => content_length = value.parse::<u64>().ok(),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Some(Record {
+        kind: warc_type.ok_or_else(|| corrupt("record without `WARC-Type`"))?,
+        target_uri,
+        id: record_id,
+        content_length: content_length
+            .ok_or_else(|| corrupt("record without a valid `Content-Length`"))?,
+    }))
+}
+
+/// A relative path for a target URI: host and path, without a query, fragment or any
+/// component that could escape the output folder. Folder URIs get an `index.html`
+fn uri_path(uri: &str) -> PathBuf {
+    let uri = uri.trim_start_matches('<').trim_end_matches('>');
+    let uri = uri.split(['?', '#']).next().unwrap_or_default();
+    let uri = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let mut path = uri
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .map(|part| part.replace(['\\', ':'], "_"))
+        .collect::<PathBuf>();
+    if uri.ends_with('/') || !uri.contains('/') {
+        path.push("index.html");
+    }
+    path
+}
+
+fn record_name(record: &Record) -> String {
+    match (&record.target_uri, record.kind.as_str()) {
+        (Some(uri), "response") => uri_path(uri).to_string_lossy().to_string(),
+        (uri, warc_type) => format!(
+            "{warc_type}: {}",
+            uri.as_ref()
+                .or(record.id.as_ref())
+                .map_or("", String::as_str)
+        ),
+    }
+}
+
+/// Skip the HTTP status line and headers of a response block
+fn skip_http_headers(block: &mut impl BufRead) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if block.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+fn warc_list(mut rdr: impl BufRead) -> Result<Vec<String>, DecompressError> {
+    let mut entries = vec![];
+    while let Some(record) = next_record(&mut rdr)? {
+        entries.push(record_name(&record));
+        io::copy(&mut (&mut rdr).take(record.content_length), &mut io::sink())?;
+    }
+    Ok(entries)
+}
+
+fn warc_extract(
+    mut rdr: impl BufRead,
+    to: &Path,
+    opts: &ExtractOpts,
+) -> Result<Vec<ExtractedFile>, DecompressError> {
+    let mut files = vec![];
+    let mut written = 0;
+    if !to.exists() {
+        create_dir_all(to, opts)?;
+    }
+
+    let mut count = 0;
+    while let Some(record) = next_record(&mut rdr)? {
+        let index = count;
+        count += 1;
+        let mut block = (&mut rdr).take(record.content_length);
+        let Some(uri) = record
+            .target_uri
+            .as_ref()
+            .filter(|_| record.kind == "response")
+        else {
+            io::copy(&mut block, &mut io::sink())?;
+            continue;
+        };
+
+        let entry_path = uri_path(uri);
+        let filepath = entry_path
+            .components()
+            .skip(opts.strip)
+            .collect::<PathBuf>();
+        let outpath = to.join(rename(opts, &filepath, RelPathKind::File));
+        if outpath == to {
+            io::copy(&mut block, &mut io::sink())?;
+            continue;
+        }
+
+        if !(opts.filter)(outpath.as_path())
+            || !is_allowed_extension(opts, &entry_path, RelPathKind::File)
+        {
+            notify_entry(opts, &entry_path, RelPathKind::File, None, false);
+            io::copy(&mut block, &mut io::sink())?;
+            continue;
+        }
+        let Some(outpath) = map_path(opts, outpath.as_path())
+            .map(|outpath| numbered(opts, outpath, RelPathKind::File, index))
+            .map(|outpath| resolve_conflict(opts, outpath, RelPathKind::File))
+            .transpose()?
+            .flatten()
+        else {
+            notify_entry(opts, &entry_path, RelPathKind::File, None, false);
+            io::copy(&mut block, &mut io::sink())?;
+            continue;
+        };
+        written += 1;
+        check_entry_limit(opts, written)?;
+
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                create_dir_all(p, opts)?;
+            }
+        }
+        skip_http_headers(&mut block)?;
+        let mut outfile = create_file(opts, &outpath)?;
+        let (size, hash) = write_contents(opts, &entry_path, &mut block, &mut outfile)?;
+        outfile.persist()?;
+        files.push(ExtractedFile {
+            path: outpath.to_string_lossy().to_string(),
+            mtime: None,
+            size,
+            hash,
+        });
+        notify_entry(opts, &entry_path, RelPathKind::File, Some(size), true);
+    }
+    Ok(files)
+}
+
+/// WARC web archives. Only `response` records are extracted, each to a path made of
+/// its target URI's host and path, holding the HTTP payload as-is (no de-chunking
+/// or content decoding).
+#[derive(Default)]
+pub struct Warc {
+    re: Option<Regex>,
+    extra: Option<Regex>,
+}
+
+impl Warc {
+    #[must_use]
+    pub fn new(re: Option<Regex>) -> Self {
+        Self { re, extra: None }
+    }
+    #[must_use]
+    pub fn build(re: Option<Regex>) -> Box<Self> {
+        Box::new(Self::new(re))
+    }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
+}
+
+impl Decompressor for Warc {
+    fn id(&self) -> &'static str {
+        "warc"
+    }
+
+    fn test_mimetype(&self, archive: &str) -> bool {
+        archive == "application/warc"
+    }
+
+    fn test(&self, archive: &Path) -> bool {
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
+    }
+
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        Ok(Listing {
+            id: "warc",
+            entries: warc_list(BufReader::new(File::open(archive)?))?,
+        })
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        Ok(Decompression::new(
+            "warc",
+            to,
+            warc_extract(BufReader::new(reader), to, opts)?,
+        ))
+    }
+}
+
+/// Gzipped WARC web archives, where every record is usually its own gzip member
+#[cfg(feature = "warcgz")]
+#[derive(Default)]
+pub struct WarcGz {
+    re: Option<Regex>,
+    extra: Option<Regex>,
+}
+
+#[cfg(feature = "warcgz")]
+impl WarcGz {
+    #[must_use]
+    pub fn new(re: Option<Regex>) -> Self {
+        Self { re, extra: None }
+    }
+    #[must_use]
+    pub fn build(re: Option<Regex>) -> Box<Self> {
+        Box::new(Self::new(re))
+    }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
+}
+
+#[cfg(feature = "warcgz")]
+fn gz_reader(reader: impl Read) -> impl BufRead {
+    BufReader::new(DecoderReader(flate2::bufread::MultiGzDecoder::new(
+        BufReader::new(reader),
+    )))
+}
+
+#[cfg(feature = "warcgz")]
+impl Decompressor for WarcGz {
+    fn id(&self) -> &'static str {
+        "warcgz"
+    }
+
+    fn test_mimetype(&self, archive: &str) -> bool {
+        archive == "application/gzip"
+    }
+
+    fn test_content(&self, archive: &Path) -> bool {
+        let mut version = [0; 5];
+        File::open(archive).map_or(false, |file| {
+            gz_reader(file).read_exact(&mut version).is_ok() && &version == b"WARC/"
+        })
+    }
+
+    fn test(&self, archive: &Path) -> bool {
+        matches_name(self.re.as_ref(), &RE_GZ, self.extra.as_ref(), archive)
+    }
+
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        Ok(Listing {
+            id: "warcgz",
+            entries: warc_list(gz_reader(File::open(archive)?))?,
+        })
+    }
+
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        Ok(Decompression::new(
+            "warcgz",
+            to,
+            warc_extract(gz_reader(reader), to, opts)?,
+        ))
+    }
+}
@@ -1,132 +1,627 @@
 use std::{
     fs::File,
-    io::{self, BufReader},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use encoding_rs::Encoding;
 use lazy_static::lazy_static;
 use regex::Regex;
-use zip::ZipArchive;
+use zip::{
+    read::{read_zipfile_from_stream, ZipFile},
+    result::ZipError,
+    ZipArchive,
+};
 
+use crate::decompressors::utils::matches_name;
 use crate::{
-    decompressors::utils::normalize_mode, DecompressError, Decompression, Decompressor,
-    ExtractOpts, Listing,
+    decompressors::utils::{
+        apply_fixed_mtime, check_depth, check_entry_limit, check_ratio, collected, create_dir_all,
+        create_file, drain, is_allowed_extension, is_apple_metadata, is_too_deep, is_unsafe_path,
+        is_up_to_date, lossy_name_warning, map_path, normalize_mode, notify_entry, numbered,
+        peek_content, rename, resolve_conflict, sanitize, sink_path, system_time, unix_mtime,
+        write_contents, CaseFolds, DirMtimes, Manifest,
+    },
+    decompressors::walker::{walk_entries, WalkedEntry},
+    ArchiveMetadata, DecompressError, Decompression, Decompressor, EntryReaderIter, EntrySink,
+    ExtractOpts, ExtractedFile, Listing, ListingEntry, ListingIter, RelPath, RelPathKind,
 };
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"(?i)\.zip$").unwrap();
 }
 
+const LOG_TARGET: &str = "decompress::zip";
+
+/// An entry's name and its path in the archive. Names stored without the UTF-8 flag
+/// are decoded with `encoding` when given, rather than as CP437, and backslashes
+/// become separators with `normalize_separators`
+fn entry_name(
+    file: &ZipFile<'_>,
+    encoding: Option<&'static Encoding>,
+    normalize_separators: bool,
+    reject_unsafe_paths: bool,
+) -> Result<(String, PathBuf), DecompressError> {
+    let legacy = file.name().as_bytes() != file.name_raw();
+    let name = match encoding {
+        Some(encoding) if legacy => encoding
+            .decode_without_bom_handling(file.name_raw())
+            .0
+            .into_owned(),
+        _ => file.name().to_string(),
+    };
+    let name = if normalize_separators {
+        name.replace('\\', "/")
+    } else {
+        name
+    };
+    if reject_unsafe_paths && is_unsafe_path(Path::new(&name)) {
+        return Err(DecompressError::PathEscape(name));
+    }
+    let path = if name == file.name() {
+        file.enclosed_name().map(Path::to_path_buf)
+    } else {
+        Some(PathBuf::from(&name)).filter(|path| !name.contains('\0') && !is_unsafe_path(path))
+    };
+    path.map(|path| (name, path))
+        .ok_or_else(|| DecompressError::Error("Invalid file path".to_string()))
+}
+
+/// Returns `true` for a name that isn't valid UTF-8 and that `encoding` doesn't decode
+/// cleanly either, so the name we have for it is a lossy conversion or a guess
+fn is_name_lossy(file: &ZipFile<'_>, encoding: Option<&'static Encoding>) -> bool {
+    let raw = file.name_raw();
+    std::str::from_utf8(raw).is_err()
+        && encoding.map_or(true, |encoding| encoding.decode_without_bom_handling(raw).1)
+}
+
+/// Parts of the format that the `zip` crate doesn't read (such as multi-disk archives)
+/// as `DecompressError::UnsupportedFeature`, a broken or truncated archive as
+/// `DecompressError::CorruptArchive`, other errors through `other`. Zip64 records
+/// are read, and entries of any size are streamed
+fn zip_error(err: ZipError, other: fn(String) -> DecompressError) -> DecompressError {
+    match err {
+        ZipError::UnsupportedArchive(msg) => DecompressError::UnsupportedFeature(msg.to_string()),
+        ZipError::InvalidArchive(msg) => DecompressError::CorruptArchive(msg.to_string()),
+        err => other(err.to_string()),
+    }
+}
+
 fn build_archive(archive: &Path) -> Result<ZipArchive<BufReader<File>>, DecompressError> {
     let rdr = ZipArchive::new(BufReader::new(File::open(archive)?))
-        .map_err(|err| DecompressError::Error(err.to_string()))?;
+        .map_err(|err| zip_error(err, DecompressError::Error))?;
 
     Ok(rdr)
 }
+/// What an extraction has gathered so far, across entries
+#[derive(Default)]
+struct ExtractState {
+    files: Vec<ExtractedFile>,
+    dir_mtimes: DirMtimes,
+    dirs: Vec<PathBuf>,
+    manifest: Manifest,
+    written: usize,
+    renamed: Vec<(String, String)>,
+    warnings: Vec<String>,
+    case_folds: CaseFolds,
+    skipped: Vec<String>,
+}
+
+impl ExtractState {
+    /// Remember a sanitized rename, and warn about an entry whose name was decoded lossily
+    fn note_name(&mut self, original: &Path, written: &Path, lossy: Option<&Path>) {
+        if let Some(entry) = lossy {
+            self.warnings.push(lossy_name_warning(entry));
+        }
+        if written != original {
+            self.renamed.push((
+                original.to_string_lossy().to_string(),
+                written.to_string_lossy().to_string(),
+            ));
+        }
+    }
+
+    fn finish(self, to: &Path, opts: &ExtractOpts) -> Result<Decompression, DecompressError> {
+        self.dir_mtimes.apply()?;
+        apply_fixed_mtime(to, &self.files, &self.dirs, opts)?;
+        Ok(Decompression::new("zip", to, self.files)
+            .with_renamed(self.renamed)
+            .with_warnings(self.warnings)
+            .with_skipped(self.skipped))
+    }
+}
+
+/// The entry's modification time, taking its timezone-less DOS time as UTC
+fn last_modified(file: &ZipFile<'_>) -> Option<SystemTime> {
+    file.last_modified()
+        .to_time()
+        .ok()
+        .and_then(|mtime| u64::try_from(mtime.unix_timestamp()).ok())
+        .map(system_time)
+}
+
+/// Write the entry's comment next to its file, as `<file>.comment`, when
+/// `zip_comments_to_sidecar` is set and it has one
+fn write_comment_sidecar(
+    opts: &ExtractOpts,
+    outpath: &Path,
+    file: &ZipFile<'_>,
+) -> Result<(), DecompressError> {
+    if opts.zip_comments_to_sidecar && !file.comment().is_empty() {
+        let mut sidecar = outpath.as_os_str().to_owned();
+        sidecar.push(".comment");
+        std::fs::write(sidecar, file.comment())?;
+    }
+    Ok(())
+}
+
+/// Extract a single entry, whether it was found through the central directory or
+/// read from a stream
+fn extract_entry(
+    file: &mut ZipFile<'_>,
+    index: usize,
+    to: &Path,
+    opts: &ExtractOpts,
+    state: &mut ExtractState,
+) -> Result<(), DecompressError> {
+    use std::fs;
+
+    let (_, filepath) = entry_name(
+        file,
+        opts.filename_encoding,
+        opts.normalize_separators,
+        opts.reject_unsafe_paths,
+    )?;
+    check_depth(&filepath, opts)?;
+
+    let entry_path = filepath.clone();
+    let kind = if file.is_dir() {
+        RelPathKind::Dir
+    } else {
+        RelPathKind::File
+    };
+    log::trace!(target: LOG_TARGET, "considering {} ({kind:?})", entry_path.display());
+
+    // strip prefixed components. this can be 0 parts, in which case strip does not happen.
+    // it's done for when archives contain an enclosing folder
+    let filepath = filepath.components().skip(opts.strip).collect::<PathBuf>();
+    let filepath = rename(opts, &filepath, kind);
+    let sanitized = sanitize(opts, &filepath);
+
+    // because we potentially stripped a component, we may have an empty path, in which case
+    // the joined target will be identical to the target folder
+    // we take this approach to avoid hardcoding a check against empty ""
+    let outpath = to.join(&sanitized);
+    if outpath == to {
+        return Ok(());
+    }
+
+    if !(opts.filter)(outpath.as_path())
+        || !is_allowed_extension(opts, &entry_path, kind)
+        || is_apple_metadata(opts, &entry_path)
+    {
+        log::debug!(target: LOG_TARGET, "filtered out {}", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        return Ok(());
+    }
+    if is_too_deep(opts, to, &sanitized)? {
+        log::debug!(target: LOG_TARGET, "skipped {}, it's too deep", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        state.skipped.push(outpath.to_string_lossy().to_string());
+        return Ok(());
+    }
+    let Some(head) = peek_content(opts, &entry_path, kind, file)? else {
+        log::debug!(target: LOG_TARGET, "filtered out {} by content", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        return Ok(());
+    };
+
+    let Some(outpath) = map_path(opts, outpath.as_path()) else {
+        log::debug!(target: LOG_TARGET, "mapped out {}", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        return Ok(());
+    };
+    let outpath = numbered(opts, outpath, kind, index);
+    log::trace!(target: LOG_TARGET, "{} maps to {}", entry_path.display(), outpath.display());
+    if state.manifest.contains(&entry_path) {
+        log::debug!(target: LOG_TARGET, "skipped {}, already extracted", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        return Ok(());
+    }
+    let outpath = state.case_folds.resolve(opts, to, outpath, kind)?;
+    let Some(outpath) = resolve_conflict(opts, outpath, kind)? else {
+        log::debug!(target: LOG_TARGET, "skipped {}, it already exists", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        return Ok(());
+    };
+    if is_up_to_date(opts, &outpath, kind, last_modified(file)) {
+        log::debug!(target: LOG_TARGET, "skipped {}, it isn't newer", entry_path.display());
+        notify_entry(opts, &entry_path, kind, Some(file.size()), false);
+        state.skipped.push(outpath.to_string_lossy().to_string());
+        return Ok(());
+    }
+    state.written += 1;
+    check_entry_limit(opts, state.written)?;
+    let lossy = is_name_lossy(file, opts.filename_encoding).then_some(entry_path.as_path());
+    state.note_name(&filepath, &sanitized, lossy);
+
+    // directory entries are created even when nothing is extracted into them, so
+    // archives that carry empty folders round-trip
+    if kind == RelPathKind::Dir {
+        create_dir_all(&outpath, opts)?;
+        state.dirs.push(outpath.to_path_buf());
+    } else {
+        check_ratio(opts, &entry_path, file.compressed_size(), file.size())?;
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                create_dir_all(p, opts)?;
+            }
+        }
+        let mut outfile = create_file(opts, &outpath)?;
+        let (size, hash) = if opts.structure_only {
+            (0, None)
+        } else {
+            write_contents(
+                opts,
+                &entry_path,
+                &mut head.as_slice().chain(&mut *file),
+                &mut outfile,
+            )?
+        };
+        outfile.persist()?;
+        write_comment_sidecar(opts, &outpath, file)?;
+        state.files.push(ExtractedFile {
+            path: outpath.to_string_lossy().to_string(),
+            mtime: last_modified(file),
+            size,
+            hash,
+        });
+    }
+    // Get and Set permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = file.unix_mode() {
+            let mut mode = normalize_mode(mode);
+            // a folder we can't enter would fail the entries that follow it
+            if kind == RelPathKind::Dir {
+                mode = opts.dir_mode.unwrap_or(mode | 0o700);
+            }
+            fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    // zip stores a timezone-less DOS time, which we take as UTC
+    if opts.preserve_mtime {
+        if let Ok(mtime) = file.last_modified().to_time() {
+            let mtime = unix_mtime(mtime.unix_timestamp());
+            if file.is_dir() {
+                state.dir_mtimes.push(outpath.to_path_buf(), mtime);
+            } else {
+                filetime::set_file_mtime(&outpath, mtime)?;
+            }
+        }
+    }
+    state.manifest.record(&entry_path)?;
+    log::debug!(target: LOG_TARGET, "extracted {} to {}", entry_path.display(), outpath.display());
+    notify_entry(opts, &entry_path, kind, Some(file.size()), true);
+    Ok(())
+}
+
+/// Extract entries in the order of their local headers, without the central directory.
+/// Entries that only record their sizes after their data, and encrypted entries, fail
+/// with `DecompressError::UnsupportedFeature`
+fn extract_stream(
+    mut rdr: impl Read,
+    to: &Path,
+    opts: &ExtractOpts,
+) -> Result<Decompression, DecompressError> {
+    let mut state = ExtractState {
+        manifest: Manifest::open(opts.resume_manifest.as_deref())?,
+        ..ExtractState::default()
+    };
+    if !to.exists() {
+        create_dir_all(to, opts)?;
+    }
+    let mut index = 0;
+    while let Some(mut file) =
+        read_zipfile_from_stream(&mut rdr).map_err(|err| zip_error(err, DecompressError::Error))?
+    {
+        extract_entry(&mut file, index, to, opts, &mut state)?;
+        index += 1;
+    }
+    state.finish(to, opts)
+}
+
 #[derive(Default)]
 pub struct Zip {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 impl Zip {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Zip {
+    fn id(&self) -> &'static str {
+        "zip"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/zip"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         let mut rdr = build_archive(archive)?;
         let mut entries = vec![];
         for i in 0..rdr.len() {
             let file = rdr
                 .by_index(i)
-                .map_err(|err| DecompressError::Error(err.to_string()))?;
-            let filepath = file
-                .enclosed_name()
-                .ok_or_else(|| DecompressError::Error("Invalid file path".to_string()))?;
+                .map_err(|err| zip_error(err, DecompressError::Error))?;
+            let (_, filepath) = entry_name(
+                &file,
+                opts.filename_encoding,
+                opts.normalize_separators,
+                opts.reject_unsafe_paths,
+            )?;
             entries.push(filepath.to_string_lossy().to_string());
         }
         Ok(Listing { id: "zip", entries })
     }
 
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        let reject_unsafe_paths = opts.reject_unsafe_paths;
+        let encoding = opts.filename_encoding;
+        let normalize_separators = opts.normalize_separators;
+        Ok(Box::new((0..rdr.len()).map(move |i| {
+            let file = rdr
+                .by_index_raw(i)
+                .map_err(|err| zip_error(err, DecompressError::Error))?;
+            let (_, filepath) =
+                entry_name(&file, encoding, normalize_separators, reject_unsafe_paths)?;
+            Ok(ListingEntry {
+                path: RelPath::new(filepath),
+                kind: if file.is_dir() {
+                    RelPathKind::Dir
+                } else {
+                    RelPathKind::File
+                },
+                size: Some(file.size()),
+                compressed_size: Some(file.compressed_size()),
+                comment: Some(file.comment())
+                    .filter(|comment| !comment.is_empty())
+                    .map(str::to_string),
+                mode: None,
+                is_name_lossy: is_name_lossy(&file, encoding),
+            })
+        })))
+    }
+
+    fn count(&self, archive: &Path, opts: &ExtractOpts) -> Result<usize, DecompressError> {
+        // the central directory knows the count, paths only need a look when they're checked
+        if opts.reject_unsafe_paths {
+            self.list_iter(archive, opts)?
+                .try_fold(0, |count, entry| entry.map(|_| count + 1))
+        } else {
+            Ok(build_archive(archive)?.len())
+        }
+    }
+
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        let mut errors = vec![];
+        for i in 0..rdr.len() {
+            // the CRC is checked once an entry is read to its end
+            let res = rdr
+                .by_index(i)
+                .map_err(|err| zip_error(err, DecompressError::CorruptArchive))
+                .and_then(|mut file| {
+                    let name = file.name().to_string();
+                    drain(&mut file, &name)
+                });
+            match res {
+                Err(err) if opts.continue_on_error => errors.push(err),
+                res => res?,
+            }
+        }
+        collected(errors)
+    }
+
+    fn archive_comment(&self, archive: &Path) -> Result<Option<String>, DecompressError> {
+        let rdr = build_archive(archive)?;
+        Ok(Some(String::from_utf8_lossy(rdr.comment()).to_string())
+            .filter(|comment| !comment.is_empty()))
+    }
+
+    fn is_encrypted(&self, archive: &Path) -> Result<bool, DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        for i in 0..rdr.len() {
+            // the flag isn't exposed, reading an entry without a password trips on it
+            match rdr.by_index(i) {
+                Err(ZipError::UnsupportedArchive(msg)) if msg == ZipError::PASSWORD_REQUIRED => {
+                    return Ok(true)
+                }
+                Err(err) => return Err(zip_error(err, DecompressError::Error)),
+                Ok(_) => {}
+            }
+        }
+        Ok(false)
+    }
+
+    fn metadata(&self, archive: &Path) -> Result<ArchiveMetadata, DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        let comment = Some(String::from_utf8_lossy(rdr.comment()).to_string())
+            .filter(|comment| !comment.is_empty());
+        let starts = (0..rdr.len())
+            .map(|i| {
+                rdr.by_index_raw(i)
+                    .map(|file| file.central_header_start())
+                    .map_err(|err| zip_error(err, DecompressError::Error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // the zip crate reads past both fields, so they come from each central
+        // directory header: the version needed at offset 6, the flags at 8
+        let mut file = rdr.into_inner();
+        let mut encrypted = false;
+        let mut version_needed = None;
+        for start in &starts {
+            let mut fields = [0; 4];
+            file.seek(SeekFrom::Start(start + 6))?;
+            file.read_exact(&mut fields)?;
+            version_needed = version_needed.max(Some(u16::from_le_bytes([fields[0], fields[1]])));
+            encrypted |= fields[2] & 1 == 1;
+        }
+        Ok(ArchiveMetadata {
+            id: self.id(),
+            entry_count: starts.len(),
+            comment,
+            encrypted,
+            version_needed,
+        })
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
         opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        use std::fs;
-
-        let mut files = vec![];
+        if opts.zip_streaming {
+            return extract_stream(BufReader::new(File::open(archive)?), to, opts);
+        }
+        let mut state = ExtractState {
+            manifest: Manifest::open(opts.resume_manifest.as_deref())?,
+            ..ExtractState::default()
+        };
         let mut rdr = build_archive(archive)?;
         if !to.exists() {
-            fs::create_dir_all(to)?;
+            create_dir_all(to, opts)?;
         }
 
         for i in 0..rdr.len() {
             let mut file = rdr
                 .by_index(i)
-                .map_err(|err| DecompressError::Error(err.to_string()))?;
-            let filepath = file
-                .enclosed_name()
-                .ok_or_else(|| DecompressError::Error("Invalid file path".to_string()))?;
-
-            // strip prefixed components. this can be 0 parts, in which case strip does not happen.
-            // it's done for when archives contain an enclosing folder
-            let filepath = filepath.components().skip(opts.strip).collect::<PathBuf>();
-
-            // because we potentially stripped a component, we may have an empty path, in which case
-            // the joined target will be identical to the target folder
-            // we take this approach to avoid hardcoding a check against empty ""
-            let outpath = to.join(filepath);
-            if outpath == to {
-                continue;
-            }
+                .map_err(|err| zip_error(err, DecompressError::Error))?;
+            extract_entry(&mut file, i, to, opts, &mut state)?;
+        }
+        state.finish(to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        _name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        if opts.zip_streaming {
+            extract_stream(BufReader::new(reader), to, opts)
+        } else {
+            Err(DecompressError::SeekRequired(self.id()))
+        }
+    }
+
+    fn decompress_to_sink(
+        &self,
+        archive: &Path,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        let mut written = 0;
+        for i in 0..rdr.len() {
+            let mut file = rdr
+                .by_index(i)
+                .map_err(|err| zip_error(err, DecompressError::Error))?;
+            let (_, entry_path) = entry_name(
+                &file,
+                opts.filename_encoding,
+                opts.normalize_separators,
+                opts.reject_unsafe_paths,
+            )?;
+            check_depth(&entry_path, opts)?;
+            let kind = if file.is_dir() {
+                RelPathKind::Dir
+            } else {
+                RelPathKind::File
+            };
 
-            if !(opts.filter)(outpath.as_path()) {
+            let Some(path) = sink_path(opts, &entry_path, kind, i) else {
+                notify_entry(opts, &entry_path, kind, Some(file.size()), false);
                 continue;
+            };
+            written += 1;
+            check_entry_limit(opts, written)?;
+            if kind == RelPathKind::File {
+                check_ratio(opts, &entry_path, file.compressed_size(), file.size())?;
             }
+            sink.write_entry(&RelPath::new(path), kind, &mut file)?;
+            notify_entry(opts, &entry_path, kind, Some(file.size()), true);
+        }
+        Ok(())
+    }
 
-            let outpath = (opts.map)(outpath.as_path());
-
-            if file.name().ends_with('/') {
-                fs::create_dir_all(&outpath)?;
-            } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        fs::create_dir_all(p)?;
-                    }
+    fn entries(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        let mut rdr = build_archive(archive)?;
+        let encoding = opts.filename_encoding;
+        let normalize_separators = opts.normalize_separators;
+        let reject_unsafe_paths = opts.reject_unsafe_paths;
+        Ok(walk_entries(opts, move |walker| {
+            for i in 0..rdr.len() {
+                let mut file = rdr
+                    .by_index(i)
+                    .map_err(|err| zip_error(err, DecompressError::Error))?;
+                if file.is_dir() {
+                    continue;
                 }
-                let mut outfile = fs::File::create(&outpath)?;
-                io::copy(&mut file, &mut outfile)?;
-                files.push(outpath.to_string_lossy().to_string());
-            }
-            // Get and Set permissions
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = file.unix_mode() {
-                    let mode = normalize_mode(mode);
-                    fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
+                let (_, path) =
+                    entry_name(&file, encoding, normalize_separators, reject_unsafe_paths)?;
+                let walked = WalkedEntry {
+                    index: i,
+                    path,
+                    size: file.size(),
+                    compressed_size: Some(file.compressed_size()),
+                    unsupported: None,
+                };
+                if !walker.file(walked, &mut file) {
+                    break;
                 }
             }
-        }
-        Ok(Decompression { id: "zip", files })
+            Ok(())
+        }))
     }
 }
@@ -1,41 +1,103 @@
-use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, Listing};
+use crate::decompressors::utils::{create_dir_all, drain, matches_name, zstd_decoder, RangeWriter};
+use crate::{DecompressError, Decompression, Decompressor, ExtractOpts, ExtractedFile, Listing};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::{fs, fs::File, io, io::BufReader, path::Path};
+use std::io::{Seek, SeekFrom};
+use std::{fs, fs::File, io, io::BufReader, io::Read, io::Write, path::Path};
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"(?i)\.zst$").unwrap();
 }
 
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+// skippable frame header, and the seek table footer
+const SEEK_TABLE_FRAMING: u64 = 8 + 9;
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// The (compressed, decompressed) sizes of every frame of a seekable zstd file, read
+/// from the seek table in the skippable frame it ends with. `None` for plain zstd files
+fn seek_table(file: &mut File) -> Result<Option<Vec<(u64, u64)>>, DecompressError> {
+    let end = file.seek(SeekFrom::End(0))?;
+    if end < SEEK_TABLE_FRAMING {
+        return Ok(None);
+    }
+    let mut footer = [0u8; 9];
+    file.seek(SeekFrom::Start(end - 9))?;
+    file.read_exact(&mut footer)?;
+    if le_u32(&footer[5..]) != SEEKABLE_MAGIC {
+        return Ok(None);
+    }
+    // with checksums, every entry carries 4 more bytes
+    let entry = if footer[4] & 0x80 == 0 { 8 } else { 12 };
+    let table = u64::from(le_u32(&footer)) * entry;
+    let corrupt = || DecompressError::CorruptArchive("invalid zstd seek table".to_string());
+    if table + SEEK_TABLE_FRAMING > end {
+        return Err(corrupt());
+    }
+    let mut header = [0u8; 8];
+    file.seek(SeekFrom::Start(end - table - SEEK_TABLE_FRAMING))?;
+    file.read_exact(&mut header)?;
+    if le_u32(&header) != SKIPPABLE_MAGIC || u64::from(le_u32(&header[4..])) != table + 9 {
+        return Err(corrupt());
+    }
+    let mut entries = vec![0u8; usize::try_from(table).map_err(|_| corrupt())?];
+    file.read_exact(&mut entries)?;
+    Ok(Some(
+        entries
+            .chunks_exact(usize::try_from(entry).map_err(|_| corrupt())?)
+            .map(|e| (u64::from(le_u32(e)), u64::from(le_u32(&e[4..]))))
+            .collect(),
+    ))
+}
+
 #[derive(Default)]
 pub struct Zstd {
     re: Option<Regex>,
+    extra: Option<Regex>,
 }
 
 impl Zstd {
     #[must_use]
     pub fn new(re: Option<Regex>) -> Self {
-        Self { re }
+        Self { re, extra: None }
     }
     #[must_use]
     pub fn build(re: Option<Regex>) -> Box<Self> {
         Box::new(Self::new(re))
     }
+    /// Match the file names any of `res` matches, in place of the default ones
+    #[must_use]
+    pub fn build_multi(res: &[Regex]) -> Box<Self> {
+        Self::build(Some(super::utils::any_of(res)))
+    }
+    /// Match the default file names, and also the ones `extra` matches
+    #[must_use]
+    pub fn build_extended(extra: Regex) -> Box<Self> {
+        Box::new(Self {
+            re: None,
+            extra: Some(extra),
+        })
+    }
 }
 
 impl Decompressor for Zstd {
+    fn id(&self) -> &'static str {
+        "zst"
+    }
+
     fn test_mimetype(&self, archive: &str) -> bool {
         archive == "application/zstd"
     }
 
     fn test(&self, archive: &Path) -> bool {
-        archive
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .map_or(false, |f| self.re.as_ref().unwrap_or(&*RE).is_match(f))
+        matches_name(self.re.as_ref(), &RE, self.extra.as_ref(), archive)
     }
 
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError> {
+    fn list(&self, archive: &Path, _opts: &ExtractOpts) -> Result<Listing, DecompressError> {
         Ok(Listing {
             id: "zst",
             entries: vec![archive
@@ -46,28 +108,118 @@ impl Decompressor for Zstd {
         })
     }
 
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        drain(
+            &mut zstd_decoder(
+                fd,
+                opts.zstd_dictionary.as_deref(),
+                opts.zstd_window_log_max,
+            )?,
+            &archive.to_string_lossy(),
+        )
+    }
+
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let fd = BufReader::new(File::open(archive)?);
+        Ok(io::copy(
+            &mut zstd_decoder(
+                fd,
+                opts.zstd_dictionary.as_deref(),
+                opts.zstd_window_log_max,
+            )?,
+            writer,
+        )?)
+    }
+
+    fn decompress_range(
+        &self,
+        archive: &Path,
+        start: u64,
+        len: u64,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let mut file = File::open(archive)?;
+        let Some(frames) = seek_table(&mut file)? else {
+            let mut range = RangeWriter::new(writer, start, len);
+            self.decompress_to_writer(archive, &mut range, opts)?;
+            return Ok(len - range.remaining());
+        };
+        let end = start.saturating_add(len);
+        // skip the frames that end before the range, and take the ones it reaches into
+        let (mut offset, mut first) = (0, 0);
+        let (mut compressed, mut covered) = (0, 0);
+        for (frame_compressed, frame_decompressed) in frames {
+            if compressed == 0 && first + frame_decompressed <= start {
+                offset += frame_compressed;
+                first += frame_decompressed;
+            } else if first + covered < end {
+                compressed += frame_compressed;
+                covered += frame_decompressed;
+            }
+        }
+        if compressed == 0 {
+            // the range starts past the end
+            return Ok(0);
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut dec = zstd_decoder(
+            BufReader::new(file.take(compressed)),
+            opts.zstd_dictionary.as_deref(),
+            opts.zstd_window_log_max,
+        )?;
+        let mut range = RangeWriter::new(writer, start - first, len);
+        io::copy(&mut dec, &mut range)?;
+        Ok(len - range.remaining())
+    }
+
     fn decompress(
         &self,
         archive: &Path,
         to: &Path,
-        _opts: &ExtractOpts,
+        opts: &ExtractOpts,
     ) -> Result<Decompression, DecompressError> {
-        let fd = BufReader::new(File::open(archive)?);
-        let dec = zstd::stream::read::Decoder::new(fd)?;
+        self.decompress_reader(Box::new(File::open(archive)?), archive, to, opts)
+    }
+
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let fd = BufReader::new(reader);
+        let dec = zstd_decoder(
+            fd,
+            opts.zstd_dictionary.as_deref(),
+            opts.zstd_window_log_max,
+        )?;
         if !Path::new(to).exists() {
-            let _res = fs::create_dir_all(to);
+            let _res = create_dir_all(to, opts);
         }
         let target = to.join(
-            archive
-                .file_stem()
+            name.file_stem()
                 .ok_or_else(|| DecompressError::Error("cannot compose a file name".to_string()))?,
         );
         let mut outfile = fs::File::create(&target)?;
 
-        io::copy(&mut BufReader::new(dec), &mut outfile)?;
-        Ok(Decompression {
-            id: "zst",
-            files: vec![target.to_string_lossy().to_string()],
-        })
+        let size = io::copy(&mut BufReader::new(dec), &mut outfile)?;
+        Ok(Decompression::new(
+            "zst",
+            to,
+            vec![ExtractedFile {
+                path: target.to_string_lossy().to_string(),
+                mtime: None,
+                size,
+                hash: None,
+            }],
+        ))
     }
 }
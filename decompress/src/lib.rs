@@ -21,15 +21,25 @@
 #![allow(clippy::module_name_repetitions)]
 pub mod decompressors;
 
+#[cfg(decodes_names)]
+pub use encoding_rs;
+
 use derive_builder::Builder;
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::borrow::Cow;
-use std::{convert::Infallible, io, path::Path};
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+use std::{convert::Infallible, io, io::Read, io::Write, path::Path};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum DecompressError {
     #[error("could not decompress: `{0}`")]
-    IO(#[from] io::Error),
+    IO(io::Error),
 
     #[error("could not decompress: `{0}`")]
     Error(String),
@@ -39,13 +49,339 @@ pub enum DecompressError {
 
     #[error("no compressor found")]
     MissingCompressor,
+
+    #[error("this looks like an archive that needs the `{0}` feature, which isn't enabled")]
+    FeatureDisabled(&'static str),
+
+    #[error("corrupt archive: `{0}`")]
+    CorruptArchive(String),
+
+    #[error("entry path escapes the output folder: `{0}`")]
+    PathEscape(String),
+
+    #[error("unsupported entry type in `{id}` archive: `{entry_type}`")]
+    UnsupportedEntryType {
+        id: &'static str,
+        entry_type: String,
+    },
+
+    #[error("`{0}` archives cannot be decompressed from a stream, they need `Seek`")]
+    SeekRequired(&'static str),
+
+    #[error("`{0}` archives hold several files, they can't be written to a single writer")]
+    NotSingleFile(&'static str),
+
+    #[error("`{0}` archives can't be extracted to an entry sink")]
+    SinkUnsupported(&'static str),
+
+    #[error("`{0}` archives can't be read entry by entry")]
+    EntriesUnsupported(&'static str),
+
+    #[error("archive has more than {limit} entries to extract")]
+    TooManyEntries { limit: usize },
+
+    #[error("entry path is {depth} levels deep, over the limit of {limit}")]
+    PathTooDeep { limit: usize, depth: usize },
+
+    #[error("{} errors, the first: {}", .0.len(), .0[0])]
+    Multiple(Vec<DecompressError>),
+
+    #[error("entries not found in archive: {}", .0.join(", "))]
+    MissingEntries(Vec<String>),
+
+    #[error("checksum mismatch: `{0}`")]
+    ChecksumMismatch(String),
+
+    #[error("unsupported archive feature: `{0}`")]
+    UnsupportedFeature(String),
+
+    #[error("extraction aborted on an existing file: `{0}`")]
+    Aborted(String),
+
+    #[error("`{a}` and `{b}` differ only by case, and would be written to the same file")]
+    CaseCollision { a: String, b: String },
+
+    #[error("entry `{entry}` expands {ratio:.0} times its compressed size, over the limit")]
+    SuspiciousRatio { entry: String, ratio: f64 },
+
+    #[error("archive is wrapped in more than {limit} levels of archives")]
+    TooManyLevels { limit: usize },
+}
+
+impl From<io::Error> for DecompressError {
+    fn from(err: io::Error) -> Self {
+        // an error a decoder's reader classified, passed on by readers that only know `io::Error`
+        err.downcast::<Self>().unwrap_or_else(Self::IO)
+    }
+}
+
+lazy_static! {
+    static ref ARCHIVE_EXT_RE: Regex =
+        Regex::new(
+            r"(?i)((\.tar|\.warc)?\.(gz|bz2|bz|xz|zst)|\.(tar|tgz|tbz|txz|tzst|zip|ar|deb|rar|warc|lzh|lha))$"
+        )
+        .unwrap();
+}
+
+/// Archives `unwrap_single` goes through before giving up on a chain that doesn't end
+const MAX_UNWRAP_LEVELS: usize = 16;
+
+/// The folder entries are extracted into, `to` or the `wrap_in` folder under it
+fn wrapped<'a>(to: &'a Path, opts: &ExtractOpts) -> Cow<'a, Path> {
+    opts.wrap_in
+        .as_ref()
+        .map_or(Cow::Borrowed(to), |wrap_in| Cow::Owned(to.join(wrap_in)))
+}
+
+/// Write `<hash>  <path>` lines for the hashed files, sorted by their path under `root`
+fn write_manifest(manifest: &Path, root: &Path, files: &[ExtractedFile]) -> io::Result<()> {
+    let mut lines = files
+        .iter()
+        .filter_map(|file| {
+            let path = Path::new(&file.path);
+            let path = RelPath::new(path.strip_prefix(root).unwrap_or(path));
+            Some((path.parts().join("/"), file.hash.as_ref()?))
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    let mut out = io::BufWriter::new(std::fs::File::create(manifest)?);
+    for (path, hash) in lines {
+        writeln!(out, "{hash}  {path}")?;
+    }
+    out.flush()
+}
+
+/// Move every hashed file into `store` as `<first two hex digits>/<hash>`, dropping the
+/// ones already stored, index them by their path under `root` and point `files` and
+/// `file_infos` at where they're stored
+fn store_content_addressed(store: &Path, root: &Path, res: &mut Decompression) -> io::Result<()> {
+    for file in &mut res.file_infos {
+        let Some(hash) = &file.hash else {
+            continue;
+        };
+        let path = PathBuf::from(&file.path);
+        let stored = store.join(hash.get(..2).unwrap_or(hash)).join(hash);
+        if stored.exists() {
+            std::fs::remove_file(&path)?;
+        } else {
+            std::fs::create_dir_all(stored.parent().unwrap_or(store))?;
+            if std::fs::rename(&path, &stored).is_err() {
+                std::fs::copy(&path, &stored)?;
+                std::fs::remove_file(&path)?;
+            }
+        }
+        let rel = RelPath::new(path.strip_prefix(root).unwrap_or(&path));
+        res.content_index
+            .push((rel.parts().join("/"), hash.clone()));
+        let stored = stored.to_string_lossy().to_string();
+        if let Some(listed) = res.files.iter_mut().find(|listed| **listed == file.path) {
+            listed.clone_from(&stored);
+        }
+        file.path = stored;
+    }
+    Ok(())
+}
+
+/// The number of leading path components that all entries share. File names don't count,
+/// so a lone file at `a/b.txt` shares `a`
+fn common_prefix_len(entries: ListingIter) -> Result<usize, DecompressError> {
+    let mut prefix: Option<Vec<OsString>> = None;
+    for entry in entries {
+        let entry = entry?;
+        let mut parts = entry
+            .path
+            .as_path()
+            .components()
+            .map(|component| component.as_os_str().to_os_string())
+            .collect::<Vec<_>>();
+        if entry.kind != RelPathKind::Dir {
+            parts.pop();
+        }
+        prefix = Some(match prefix {
+            None => parts,
+            Some(mut prefix) => {
+                let len = prefix
+                    .iter()
+                    .zip(&parts)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                prefix.truncate(len);
+                prefix
+            }
+        });
+    }
+    Ok(prefix.map_or(0, |prefix| prefix.len()))
+}
+
+/// Strip all known archive extensions from a file name, including compound tar
+/// extensions, so that `foo.tar.gz` becomes `foo` and `data.gz` becomes `data`.
+///
+/// A name that is nothing but an extension (`.zip`) is left as it is
+#[must_use]
+pub fn strip_archive_extensions(name: &str) -> String {
+    let mut name = name.to_string();
+    while let Some(m) = ARCHIVE_EXT_RE.find(&name) {
+        if m.start() == 0 {
+            break;
+        }
+        name.truncate(m.start());
+    }
+    name
 }
 
 pub type FilterFn = dyn Fn(&Path) -> bool;
 pub type MapFn = dyn Fn(&Path) -> Cow<'_, Path>;
+pub type MapOptFn = dyn Fn(&Path) -> Option<Cow<'_, Path>>;
+pub type EntryFn = dyn Fn(&EntryInfo);
+pub type TransformFn = dyn Fn(&RelPath, &mut Vec<u8>);
+pub type ContentFilterFn = dyn Fn(&RelPath, &[u8]) -> bool;
+pub type SymlinkTargetFn = dyn Fn(&RelPath, &Path) -> PathBuf;
+pub type ConflictFn = dyn Fn(&Path) -> ConflictResolution;
+
+/// A relative path of an entry, as stored in the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelPath(PathBuf);
+
+impl RelPath {
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self(path.into())
+    }
+
+    #[must_use]
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The path's components, leaving out root, prefix and `.` components
+    #[must_use]
+    pub fn parts(&self) -> Vec<String> {
+        self.0
+            .components()
+            .filter_map(|component| match component {
+                std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                std::path::Component::ParentDir => Some("..".to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The kind of an archive entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelPathKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// Describes an archive entry, as given to `ExtractOpts.on_entry`
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    pub path: RelPath,
+    pub kind: RelPathKind,
+    /// Uncompressed size, if known
+    pub size: Option<u64>,
+    /// `false` if the entry was skipped, by `filter`, `map_opt`, `allowed_extensions`,
+    /// `skip_apple_metadata` or a resume manifest
+    pub extracted: bool,
+    /// Sum of the uncompressed sizes of all the archive's entries, skipped ones included,
+    /// with `precompute_total`. `None` when the archive doesn't list the size of every
+    /// entry (lha, rar)
+    pub total_expected: Option<u64>,
+}
+
+/// What to do about a file that's about to be written over an existing one, as
+/// decided by `ExtractOpts.on_conflict`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and skip the entry
+    Skip,
+    /// Write the entry next to it, as `name (1).ext` or the first free number after
+    Rename,
+    /// Stop extracting with `DecompressError::Aborted`
+    Abort,
+}
+
+/// How symlink entries are written (tar)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Write an empty file in place of the link
+    #[default]
+    Empty,
+    /// Create the symlink (unix only, elsewhere it's written as with `Dereference`).
+    /// Entries that would be written at or through a link created before them fail with
+    /// `DecompressError::PathEscape`
+    Create,
+    /// For filesystems without symlinks: write a copy of the target when it's a file
+    /// extracted into the output folder, or else a text file holding the target path
+    Dereference,
+}
 
-#[derive(Builder)]
-#[builder(pattern = "owned")]
+/// What becomes of symlinks to an absolute path, such as `/usr/lib/...` (tar, unless
+/// `symlinks` is `SymlinkPolicy::Empty`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AbsoluteSymlinkPolicy {
+    /// Leave the entry out
+    #[default]
+    Skip,
+    /// Write it per `symlinks`, and don't count it as escaping for
+    /// `validate_symlinks_after`
+    Create,
+    /// Stop extracting with `DecompressError::PathEscape`
+    Error,
+}
+
+/// What to do about an entry whose path only differs by case from one extracted before
+/// it, when the output folder is on a case-insensitive filesystem (tar, zip)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseCollisionPolicy {
+    /// Stop extracting with `DecompressError::CaseCollision`
+    #[default]
+    Error,
+    /// Write the later entry as `name (1).ext` or the first free number after
+    Rename,
+    /// Let the later entry overwrite the earlier one
+    Overwrite,
+}
+
+/// A declarative rename, applied by `rename_rules` to the entry paths it matches.
+/// Fields left `None` do nothing, so a rule may only replace a prefix or only add
+/// a suffix
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameRule {
+    /// Only rename entries whose relative path matches this glob, where `?` and `*`
+    /// match within a folder name and `**` across folders (`**/*.conf`). `None`
+    /// matches every entry
+    pub matches: Option<String>,
+    /// Replace this leading folder (first) with another (second), which may be empty
+    /// to drop it. Compared by whole path components
+    pub replace_prefix: Option<(String, String)>,
+    /// Append this to the name of matching files, folders are left as they are
+    pub suffix: Option<String>,
+}
+
+/// The hash `write_manifest` lists extracted files with, and `content_addressed` stores
+/// them under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    Sha256,
+    Sha1,
+    Blake3,
+}
+
+/// Options for listing and decompressing, checked when built. Closures are shared, so
+/// an `ExtractOpts` can be cloned and reused across archives.
+///
+/// Contradictory or invalid ones (`map` with `map_opt`, `strict_ownership` without
+/// `preserve_ownership`, a `dir_mode` beyond `0o7777`, or a `wrap_in` that isn't a
+/// relative folder) fail `build()` with a `ValidationError` that names them all
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Builder, Clone)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct ExtractOpts {
     #[builder(default)]
     pub detect_content: bool,
@@ -53,32 +389,541 @@ pub struct ExtractOpts {
     #[builder(default)]
     pub strip: usize,
 
-    #[builder(setter(custom), default = "Box::new(|_| true)")]
-    pub filter: Box<FilterFn>,
+    /// Strip the leading folders that all entries share, before `strip` is applied.
+    /// Finding them takes an extra pass over the archive, which for compressed tarballs
+    /// means decompressing it twice. Not supported by `decompress_reader`
+    #[builder(default)]
+    pub strip_common_prefix: bool,
+
+    /// Extract into this folder under the output folder, as if every entry path started
+    /// with it. Applies after `strip`, so it can give bare and enclosed archives the same
+    /// shape. `Decompression::to` is then the wrapper folder
+    #[builder(default)]
+    pub wrap_in: Option<String>,
+
+    #[builder(setter(custom), default = "Arc::new(|_| true)")]
+    pub filter: Arc<FilterFn>,
+
+    #[builder(setter(custom), default = "Arc::new(|path| Cow::from(path))")]
+    pub map: Arc<MapFn>,
+
+    /// Like `map`, and takes its place when set, but returning `None` skips the entry
+    /// as `filter` would (tar, zip, warc; rar extracts in one go and maps nothing)
+    #[builder(setter(custom), default)]
+    pub map_opt: Option<Arc<MapOptFn>>,
+
+    /// Decide on every regular file from its path and its first `content_filter_prefix`
+    /// bytes (fewer for smaller files), after `filter` let it through (tar, zip). Those
+    /// bytes are read ahead into a buffer and written out before the rest of the stream,
+    /// so filtered files are never created. Sparse tar entries aren't peeked at
+    #[builder(setter(custom), default)]
+    pub content_filter: Option<Arc<ContentFilterFn>>,
+
+    /// How many leading bytes `content_filter` is given
+    #[builder(default = "512")]
+    pub content_filter_prefix: usize,
+
+    /// Called for every entry once it was either extracted or skipped by `filter` (tar, zip, rar)
+    #[builder(setter(custom), default)]
+    pub on_entry: Option<Arc<EntryFn>>,
+
+    /// List the archive before extracting it, and hand `on_entry` the sum of all entry
+    /// sizes as `EntryInfo::total_expected`, for a progress bar with a known end (when
+    /// every entry's size is listed). Like `strip_common_prefix`, this takes an extra
+    /// pass over the archive
+    #[builder(default)]
+    pub precompute_total: bool,
+
+    /// Rewrite the contents of every regular file before it's written (tar, zip, ar,
+    /// warc). Transformed entries are read into memory whole rather than streamed, so
+    /// this costs as much memory as the largest file. Directories, symlinks and sparse
+    /// tar entries are written as-is
+    #[builder(setter(custom), default)]
+    pub transform: Option<Arc<TransformFn>>,
+
+    /// Read file entries smaller than this many bytes into memory and write them in
+    /// one go, streaming larger ones, which saves syscalls on archives of many tiny
+    /// files (tar, zip, ar, warc, lha). Without it, every entry is streamed
+    #[builder(default)]
+    pub buffer_threshold: Option<u64>,
+
+    /// Called with the output path of a file entry that already exists, to decide whether
+    /// to overwrite it (tar, zip, ar, warc). Without it, existing files are overwritten
+    #[builder(setter(custom), default)]
+    pub on_conflict: Option<Arc<ConflictFn>>,
+
+    /// Only extract files that are newer than the ones already on disk, comparing
+    /// modification times (tar, zip). Files at least as new are left alone and listed
+    /// in `Decompression::skipped`, entries without a time are always extracted
+    #[builder(default)]
+    pub only_if_newer: bool,
+
+    /// What to do about files whose paths only differ by case, see
+    /// `CaseCollisionPolicy`. Whether the output folder is case-insensitive is probed
+    /// with a scratch file the first time two such paths turn up
+    #[builder(default)]
+    pub case_collision: CaseCollisionPolicy,
+
+    /// Take the output folder as case-insensitive without probing it, for filesystems
+    /// the probe can't tell about (such as network shares)
+    #[builder(default)]
+    pub assume_case_insensitive: bool,
+
+    /// How symlink entries are written, see `SymlinkPolicy`
+    #[builder(default)]
+    pub symlinks: SymlinkPolicy,
+
+    /// How symlinks to an absolute path are written, see `AbsoluteSymlinkPolicy`. A link
+    /// with a relative target that leads out of the output folder always fails with
+    /// `DecompressError::PathEscape` when its entry is reached
+    #[builder(default)]
+    pub absolute_symlinks: AbsoluteSymlinkPolicy,
+
+    /// Given a symlink entry's path and target, the target to create it with (tar), such
+    /// as to fix up links for a relocated install. Runs after `absolute_symlinks` had its
+    /// say on the original target. The new target has to stay inside the output folder,
+    /// or be absolute under `AbsoluteSymlinkPolicy::Create`, or extraction fails with
+    /// `DecompressError::PathEscape`
+    #[builder(setter(custom), default)]
+    pub rewrite_symlink_target: Option<Arc<SymlinkTargetFn>>,
+
+    /// Write hard link entries (tar) as hard links to the file they point to, rather than
+    /// as copies of it, so repeated contents take up space once. Falls back to a copy
+    /// where the link can't be created, such as on filesystems without hard links
+    #[builder(default)]
+    pub preserve_hardlinks: bool,
+
+    /// Once all entries are written, follow every created symlink (tar, with
+    /// `SymlinkPolicy::Create`) through its whole chain and fail with
+    /// `DecompressError::PathEscape` if it ends up outside the output folder. A link that
+    /// escapes by itself already fails when its entry is reached, this catches links that
+    /// only escape through other links, which no single entry shows
+    #[builder(default)]
+    pub validate_symlinks_after: bool,
+
+    /// Only extract files with one of these extensions (such as `"png"`), compared
+    /// case-insensitively. Other files are skipped as `filter` would skip them, folders
+    /// are kept (tar, zip, warc)
+    #[builder(default)]
+    pub allowed_extensions: Option<HashSet<String>>,
+
+    /// Write every regular file as `.<name>.tmp` next to where it goes, and rename it into
+    /// place once all of it was written and checked, so readers of the output folder never
+    /// see a partial file (tar, zip, ar, warc, lha, rar). A file that fails is removed
+    /// along with its temporary copy. rar extracts into a temporary folder inside the
+    /// output folder instead, and moves its files out once all are written
+    #[builder(default)]
+    pub atomic_writes: bool,
+
+    /// Only lay out the archive: create its folders and an empty file at every regular
+    /// file's path, with modes and modification times applied as for a full extraction,
+    /// but write none of the contents (tar, zip). The empty files are in
+    /// `Decompression::files` with a size of 0
+    #[builder(default)]
+    pub structure_only: bool,
+
+    /// Skip the metadata macOS adds to archives: entries under `__MACOSX/` and
+    /// resource forks stored as files whose name starts with `._` (tar, zip)
+    #[builder(default)]
+    pub skip_apple_metadata: bool,
+
+    /// Prefix the name of every extracted file with its position in the archive,
+    /// zero-padded (`00042_name`), so the archive's order can be told from the output.
+    /// Applied after `map`, folders are left as they are (tar, zip, ar, warc)
+    #[builder(default)]
+    pub number_entries: bool,
+
+    /// Rename entries by rules rather than a `map` closure: every matching rule is
+    /// applied in order to the relative path after `strip`, before `map` sees it.
+    /// Paths that no rule matches are left as they are (tar, zip, warc, lha)
+    #[builder(default)]
+    pub rename_rules: Vec<RenameRule>,
+
+    /// Replace the characters this platform doesn't allow in file names (`<>:"|?*` and
+    /// control characters on Windows, NUL elsewhere) with `_` in every path component,
+    /// listing the renames in `Decompression::renamed` (tar, zip)
+    #[builder(default)]
+    pub sanitize_names: bool,
+
+    /// Fail with `DecompressError::TooManyEntries` rather than write more entries than
+    /// this, counting only the ones that aren't skipped (tar, zip, warc, rar)
+    #[builder(default)]
+    pub max_entries: Option<usize>,
+
+    /// Fail with `DecompressError::PathTooDeep` on an entry path of more components than
+    /// this, before anything is written for it (tar, zip, rar)
+    #[builder(default)]
+    pub max_path_depth: Option<usize>,
+
+    /// Leave out entries whose paths, after `strip`, have more components than this, to
+    /// preview the top of a large archive (tar, zip). Unlike `max_path_depth` this isn't
+    /// an error: the entries are listed in `Decompression::skipped`, and the folders above
+    /// them are still created down to this depth
+    #[builder(default)]
+    pub max_extract_depth: Option<usize>,
+
+    /// Fail with `DecompressError::SuspiciousRatio` on a file entry whose declared size
+    /// is more than this many times its compressed size, before anything is written for
+    /// it (zip, lha). Other formats don't declare both sizes and aren't checked
+    #[builder(default)]
+    pub max_compression_ratio: Option<f64>,
+
+    /// Fail with `DecompressError::PathEscape` on entries with an absolute path or a `..`
+    /// component, both when listing and decompressing
+    #[builder(default)]
+    pub reject_unsafe_paths: bool,
+
+    /// Restore file and directory modification times from the archive (tar, zip).
+    /// Directory times are applied last, so they aren't clobbered by their children.
+    #[builder(default)]
+    pub preserve_mtime: bool,
+
+    /// Give every extracted file and folder this modification time instead (tar, zip,
+    /// rar), such as `SOURCE_DATE_EPOCH` for reproducible trees. Takes precedence over
+    /// `preserve_mtime`
+    #[builder(default)]
+    pub set_all_mtimes: Option<SystemTime>,
+
+    /// Restore extended attributes stored in PAX records (tar). Needs the `xattr` feature,
+    /// and is a no-op without it or on platforms without extended attributes
+    #[builder(default)]
+    pub preserve_xattrs: bool,
+
+    /// Restore the owner and group recorded in tar headers (unix only). Needs privilege,
+    /// a `chown` that isn't permitted is skipped unless `strict_ownership` is set
+    #[builder(default)]
+    pub preserve_ownership: bool,
+
+    /// Fail rather than skip when `preserve_ownership` isn't permitted to `chown`
+    #[builder(default)]
+    pub strict_ownership: bool,
+
+    /// Fail with `DecompressError::UnsupportedEntryType` on entries that can't be
+    /// extracted, like devices and fifos, rather than skip them with a warning (tar)
+    #[builder(default)]
+    pub strict_entry_types: bool,
+
+    /// Permissions for every folder created while extracting, parents and directory
+    /// entries alike, set regardless of the umask (unix only). `None` leaves them to
+    /// the umask, and to the archive for zip directory entries
+    #[builder(default)]
+    pub dir_mode: Option<u32>,
+
+    /// Decode zip entry names that aren't flagged as UTF-8 with this encoding (such as
+    /// `encoding_rs::SHIFT_JIS`), rather than as CP437. LHA names, which carry no
+    /// encoding at all, are decoded with it too rather than as UTF-8. Needs the `zip` or
+    /// `lha` feature
+    #[cfg(decodes_names)]
+    #[builder(default)]
+    pub filename_encoding: Option<&'static encoding_rs::Encoding>,
+
+    /// Treat backslashes in zip entry names as path separators, as archives made on
+    /// Windows sometimes use them. Otherwise they end up as literal backslashes in file
+    /// names on unix
+    #[builder(default = "true")]
+    pub normalize_separators: bool,
+
+    /// Write the comment of every zip entry that has one to `<file>.comment`, next to
+    /// the extracted file
+    #[builder(default)]
+    pub zip_comments_to_sidecar: bool,
+
+    /// Extract zip archives by reading their local file headers in order, rather than
+    /// loading the central directory first. This keeps memory flat for archives with
+    /// many entries, and lets zip extract from a non-seekable stream in
+    /// `decompress_reader`. Entry comments and permissions are only stored in the
+    /// central directory, so they're not restored, and entries whose sizes follow
+    /// their data fail with `DecompressError::UnsupportedFeature`
+    #[builder(default)]
+    pub zip_streaming: bool,
+
+    /// Retry creating and writing an entry's file up to this many times, backing off
+    /// briefly, when it fails with `Interrupted`, `WouldBlock` or `TimedOut`, as flaky
+    /// network filesystems do. Other errors fail right away (tar, zip, ar, warc, lha)
+    #[builder(default)]
+    pub io_retries: u8,
+
+    /// Hash every regular file while it's written, and once extraction is done write
+    /// `<hex hash>  <path>` lines to this file, sorted by path relative to the output
+    /// folder (tar, zip, ar, warc, lha), the format `sha256sum -c` reads. Sparse tar
+    /// entries are left out. Needs the `checksums` feature
+    #[builder(default)]
+    pub write_manifest: Option<(PathBuf, HashAlgo)>,
+
+    /// Hash every regular file while it's written, and once extraction is done move it
+    /// to `<root>/<first two hex digits>/<hex hash>` rather than leaving it at its archive
+    /// path, for a deduplicating cache (tar, zip, ar, warc, lha). A file whose hash is
+    /// already stored is dropped. `Decompression::content_index` maps the archive paths to
+    /// the hashes. Folders and links are still created under the output folder, and files
+    /// that aren't hashed (sparse tar entries, hard links) stay there too. Needs the
+    /// `checksums` feature
+    #[builder(default)]
+    pub content_addressed: Option<(PathBuf, HashAlgo)>,
+
+    /// Resume an interrupted extraction: every entry written is appended to this file
+    /// (one archive path per line), and entries already listed in it are skipped (tar,
+    /// zip). rar can't skip single entries, so it is only skipped once fully listed
+    #[builder(default)]
+    pub resume_manifest: Option<PathBuf>,
+
+    /// Extract `.gz` files that turn out to hold a tarball (such as a `data.gz`) as a tar
+    /// archive, rather than writing the tarball out. Needs the `tarball` feature
+    #[builder(default)]
+    pub auto_untar_gz: bool,
+
+    /// Name the file decompressed from a `.gz` after the original name stored in its
+    /// header (which may hold folders, `strip` applies to them), rather than after the
+    /// archive. Archives without one are still named after the archive
+    #[builder(default)]
+    pub use_gzip_original_name: bool,
 
-    #[builder(setter(custom), default = "Box::new(|path| Cow::from(path))")]
-    pub map: Box<MapFn>,
+    /// Keep going after an error where the format allows it, and report all errors as
+    /// `DecompressError::Multiple` (`verify`)
+    #[builder(default)]
+    pub continue_on_error: bool,
+
+    /// Dictionary used to decode zstd streams that were compressed with one
+    #[builder(default)]
+    pub zstd_dictionary: Option<Vec<u8>>,
+
+    /// Largest zstd window to accept, as a power of 2. Frames made with `--long` or a
+    /// high level may need more than the default limit of 27 (128MB), at the cost of
+    /// that much memory
+    #[builder(default)]
+    pub zstd_window_log_max: Option<u32>,
 }
 
 impl ExtractOptsBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let mut conflicts = vec![];
+        if self.map.is_some() && matches!(self.map_opt, Some(Some(_))) {
+            conflicts.push("`map` and `map_opt` both set, only one of them can map paths");
+        }
+        if let (Some(Some((_, manifest))), Some(Some((_, store)))) =
+            (&self.write_manifest, &self.content_addressed)
+        {
+            if manifest != store {
+                conflicts.push("`write_manifest` and `content_addressed` hash differently");
+            }
+        }
+        if cfg!(not(feature = "checksums"))
+            && (matches!(self.write_manifest, Some(Some(_)))
+                || matches!(self.content_addressed, Some(Some(_))))
+        {
+            conflicts.push("`write_manifest` and `content_addressed` need the `checksums` feature");
+        }
+        if self.strict_ownership == Some(true) && self.preserve_ownership != Some(true) {
+            conflicts.push("`strict_ownership` without `preserve_ownership` has no effect");
+        }
+        if matches!(self.dir_mode, Some(Some(mode)) if mode > 0o7777) {
+            conflicts.push("`dir_mode` has bits beyond `0o7777`");
+        }
+        if matches!(self.max_compression_ratio, Some(Some(ratio)) if ratio.is_nan() || ratio <= 0.0)
+        {
+            conflicts.push("`max_compression_ratio` must be positive");
+        }
+        if let Some(Some(wrap_in)) = &self.wrap_in {
+            let path = Path::new(wrap_in);
+            if path.as_os_str().is_empty()
+                || !path
+                    .components()
+                    .all(|component| matches!(component, std::path::Component::Normal(_)))
+            {
+                conflicts.push("`wrap_in` must be a relative folder without `..`");
+            }
+        }
+        if let Some(rules) = &self.rename_rules {
+            if rules.iter().any(|rule| {
+                rule.replace_prefix.as_ref().is_some_and(|(_, to)| {
+                    !Path::new(to)
+                        .components()
+                        .all(|component| matches!(component, std::path::Component::Normal(_)))
+                }) || rule
+                    .suffix
+                    .as_ref()
+                    .is_some_and(|suffix| suffix.contains(['/', '\\']))
+            }) {
+                conflicts.push(
+                    "`rename_rules` must rename to relative folders without `..`, and suffixes can't hold separators",
+                );
+            }
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("conflicting options: {}", conflicts.join("; ")))
+        }
+    }
+
     /// Given a predicate, filter a path in.
     #[must_use]
     pub fn filter(mut self, value: impl Fn(&Path) -> bool + 'static) -> Self {
-        self.filter = Some(Box::new(value));
+        self.filter = Some(Arc::new(value));
         self
     }
     /// Given a mapping function, transform a path into a different or similar path
     #[must_use]
     pub fn map(mut self, value: impl Fn(&Path) -> Cow<'_, Path> + 'static) -> Self {
-        self.map = Some(Box::new(value));
+        self.map = Some(Arc::new(value));
+        self
+    }
+    /// Given a mapping function, transform a path, or skip its entry by returning `None`
+    #[must_use]
+    pub fn map_opt(mut self, value: impl Fn(&Path) -> Option<Cow<'_, Path>> + 'static) -> Self {
+        self.map_opt = Some(Some(Arc::new(value)));
+        self
+    }
+    /// Given a predicate over a path and the first bytes of its file, filter it in
+    #[must_use]
+    pub fn content_filter(mut self, value: impl Fn(&RelPath, &[u8]) -> bool + 'static) -> Self {
+        self.content_filter = Some(Some(Arc::new(value)));
+        self
+    }
+    /// Given a callback, get notified of every entry and whether it was extracted
+    #[must_use]
+    pub fn on_entry(mut self, value: impl Fn(&EntryInfo) + 'static) -> Self {
+        self.on_entry = Some(Some(Arc::new(value)));
+        self
+    }
+    /// Given a callback, decide what to do about each file that already exists
+    #[must_use]
+    pub fn on_conflict(mut self, value: impl Fn(&Path) -> ConflictResolution + 'static) -> Self {
+        self.on_conflict = Some(Some(Arc::new(value)));
+        self
+    }
+    /// Given a callback, rewrite the contents of each extracted file
+    #[must_use]
+    pub fn transform(mut self, value: impl Fn(&RelPath, &mut Vec<u8>) + 'static) -> Self {
+        self.transform = Some(Some(Arc::new(value)));
+        self
+    }
+    /// Given a callback, rewrite the target of each symlink before it's created
+    #[must_use]
+    pub fn rewrite_symlink_target(
+        mut self,
+        value: impl Fn(&RelPath, &Path) -> PathBuf + 'static,
+    ) -> Self {
+        self.rewrite_symlink_target = Some(Some(Arc::new(value)));
         self
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Decompression {
     pub id: &'static str,
     pub files: Vec<String>,
+    /// The folder the archive was decompressed into
+    pub to: PathBuf,
+    /// The files written, along with what the archive says about them
+    pub file_infos: Vec<ExtractedFile>,
+    /// The ids of the formats that were unwrapped to get to the files, outermost first
+    /// (`["ar", "targz"]` for a deb). Just `[id]` for a single format
+    pub format_chain: Vec<&'static str>,
+    /// Entries written under another name by `sanitize_names`, as `(original, sanitized)`
+    /// paths relative to `to`
+    pub renamed: Vec<(String, String)>,
+    /// Things worth knowing about entries, such as names that aren't valid UTF-8 (tar,
+    /// zip) or entry types that can't be extracted (tar)
+    pub warnings: Vec<String>,
+    /// Files left as they were on disk because they were at least as new as their
+    /// entries, with `only_if_newer`, entries too deep for `max_extract_depth`, and
+    /// entries of types that can't be extracted
+    pub skipped: Vec<String>,
+    /// `(path, hex hash)` for the files moved to the store with `content_addressed`, paths
+    /// relative to the output folder
+    pub content_index: Vec<(String, String)>,
+}
+
+impl Decompression {
+    /// A decompression into `to`, whose `files` are the paths of `file_infos`
+    #[must_use]
+    pub fn new(id: &'static str, to: &Path, file_infos: Vec<ExtractedFile>) -> Self {
+        Self {
+            id,
+            files: file_infos.iter().map(|f| f.path.clone()).collect(),
+            to: to.to_path_buf(),
+            file_infos,
+            format_chain: vec![id],
+            renamed: vec![],
+            warnings: vec![],
+            skipped: vec![],
+            content_index: vec![],
+        }
+    }
+
+    /// The same decompression, reached by unwrapping `chain` (outermost first)
+    #[must_use]
+    pub fn with_format_chain(mut self, chain: Vec<&'static str>) -> Self {
+        self.format_chain = chain;
+        self
+    }
+
+    /// The same decompression, with entries renamed by `sanitize_names`
+    #[must_use]
+    pub fn with_renamed(mut self, renamed: Vec<(String, String)>) -> Self {
+        self.renamed = renamed;
+        self
+    }
+
+    /// The same decompression, with warnings about the entries
+    #[must_use]
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// The same decompression, with files skipped by `only_if_newer`
+    #[must_use]
+    pub fn with_skipped(mut self, skipped: Vec<String>) -> Self {
+        self.skipped = skipped;
+        self
+    }
+}
+
+/// Where `Decompress::decompress_to_sink` stores entries, in place of the filesystem
+pub trait EntrySink {
+    /// Store an entry under `path`, relative to the sink. Files come with their contents,
+    /// symlinks with their target, and folders with nothing to read
+    ///
+    /// # Errors
+    ///
+    /// An error stops the extraction, which returns it
+    fn write_entry(
+        &mut self,
+        path: &RelPath,
+        kind: RelPathKind,
+        reader: &mut dyn Read,
+    ) -> io::Result<()>;
+}
+
+/// Files in memory, by their `/`-separated path. Folders and symlinks are left out
+impl EntrySink for BTreeMap<String, Vec<u8>> {
+    fn write_entry(
+        &mut self,
+        path: &RelPath,
+        kind: RelPathKind,
+        reader: &mut dyn Read,
+    ) -> io::Result<()> {
+        if kind == RelPathKind::File {
+            let mut contents = vec![];
+            reader.read_to_end(&mut contents)?;
+            self.insert(path.parts().join("/"), contents);
+        }
+        Ok(())
+    }
+}
+
+/// A file written by a decompression
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    pub path: String,
+    /// Modification time recorded in the archive, if it has one
+    pub mtime: Option<SystemTime>,
+    /// Uncompressed size
+    pub size: u64,
+    /// Hex digest of the contents as written, when `write_manifest` or
+    /// `content_addressed` is set
+    pub hash: Option<String>,
 }
 
 #[derive(Debug)]
@@ -87,6 +932,125 @@ pub struct Listing {
     pub entries: Vec<String>,
 }
 
+/// How `identify` picked a decompressor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionMethod {
+    /// The archive's magic bytes (and the extension, when it agrees on the format)
+    Content,
+    /// The file name only, content sniffing didn't recognize the format
+    Extension,
+}
+
+/// The decompressor that `identify` picked, and how
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identification {
+    pub id: &'static str,
+    pub via: DetectionMethod,
+}
+
+/// An entry, as yielded by `list_iter`
+#[derive(Debug, Clone)]
+pub struct ListingEntry {
+    pub path: RelPath,
+    pub kind: RelPathKind,
+    /// Uncompressed size, if known
+    pub size: Option<u64>,
+    /// Size in the archive, for formats that compress entries one by one (zip)
+    pub compressed_size: Option<u64>,
+    /// The entry's comment, if it has a non-empty one (zip)
+    pub comment: Option<String>,
+    /// Unix mode as stored in the entry header, if listed (ar)
+    pub mode: Option<u32>,
+    /// `true` if the stored name isn't valid UTF-8, so `path` is a lossy or guessed
+    /// conversion of it and `filename_encoding` may be needed (tar, zip, ar)
+    pub is_name_lossy: bool,
+}
+
+/// Entry count and sizes of an archive, see `Decompress::summary`
+#[derive(Debug, Clone)]
+pub struct ArchiveSummary {
+    pub entry_count: usize,
+    /// Sum of the known uncompressed sizes
+    pub total_uncompressed: u64,
+    /// Sum of the known compressed sizes. Stays 0 for formats that compress the archive
+    /// as a whole (compressed tarballs) or not at all
+    pub total_compressed: u64,
+    pub largest_entry: Option<ListingEntry>,
+}
+
+/// A folder tree of an archive's entries, see `Decompress::tree`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveNode {
+    /// Any entry that isn't a folder, symlinks included
+    File {
+        name: String,
+        size: Option<u64>,
+    },
+    Dir {
+        name: String,
+        children: Vec<Self>,
+    },
+}
+
+impl ArchiveNode {
+    /// The node's name, one path component. The root of a tree has an empty name
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::File { name, .. } | Self::Dir { name, .. } => name,
+        }
+    }
+
+    /// Add an entry under this folder, creating the folders on its path that the archive
+    /// has no entries for, and merging into the ones already there
+    fn insert(&mut self, parts: &[String], kind: RelPathKind, size: Option<u64>) {
+        let Self::Dir { children, .. } = self else {
+            return;
+        };
+        match parts {
+            [] => {}
+            [name] if kind != RelPathKind::Dir => children.push(Self::File {
+                name: name.clone(),
+                size,
+            }),
+            [name, rest @ ..] => {
+                let pos = children
+                    .iter()
+                    .position(|child| matches!(child, Self::Dir { .. }) && child.name() == name)
+                    .unwrap_or_else(|| {
+                        children.push(Self::Dir {
+                            name: name.clone(),
+                            children: vec![],
+                        });
+                        children.len() - 1
+                    });
+                children[pos].insert(rest, kind, size);
+            }
+        }
+    }
+}
+
+/// What an archive says about itself as a whole, see `Decompress::metadata`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMetadata {
+    /// The id of the decompressor that read it
+    pub id: &'static str,
+    pub entry_count: usize,
+    /// The archive-level comment (zip), if it has a non-empty one
+    pub comment: Option<String>,
+    /// Whether any entry (or, for rar, the headers) is encrypted
+    pub encrypted: bool,
+    /// The highest "version needed to extract" of any entry, as `major * 10 + minor`
+    /// (zip, `20` for plain deflate)
+    pub version_needed: Option<u16>,
+}
+
+pub type ListingIter = Box<dyn Iterator<Item = Result<ListingEntry, DecompressError>>>;
+
+/// File entries and readers of their contents, see `Decompress::entries`
+pub type EntryReaderIter =
+    Box<dyn Iterator<Item = Result<(RelPath, Box<dyn Read>), DecompressError>>>;
+
 ///
 /// `Decompressor` is a trait that you can implement to add your own decompressor type.
 /// A `Decompressor` is inserted into a stack, where given a potential archive file,
@@ -98,139 +1062,978 @@ pub struct Listing {
 /// decision when building a custom stack.
 ///
 pub trait Decompressor {
+    ///
+    /// The id of this `Decompressor`, as reported in `Listing` and `Decompression`
+    fn id(&self) -> &'static str;
+
     ///
     /// Test if this `Decompressor` can unpack an archive, given a mimetype.
     fn test_mimetype(&self, mimetype: &str) -> bool;
 
     ///
-    /// Test if this `Decompressor` can unpack an archive, given a path.
+    /// Test if the content of an archive, whose mimetype matched, is for this
+    /// `Decompressor`. Formats that share a compression (a `.gz` and a `.tar.gz`) peek
+    /// into the decompressed stream here. Defaults to `true`
+    fn test_content(&self, _archive: &Path) -> bool {
+        true
+    }
+
+    ///
+    /// Test if this `Decompressor` can unpack an archive, given a path.
     /// The convention is to use `Regex` internally to test a path, because this is
     /// a convenient way for end users to override the behavior.
     /// You may choose to implement a different, but configurable, testing strategy.
     fn test(&self, archive: &Path) -> bool;
 
-    ///
-    /// List an archive
+    ///
+    /// List an archive
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails.
+    fn list(&self, archive: &Path, opts: &ExtractOpts) -> Result<Listing, DecompressError>;
+
+    ///
+    /// List an archive lazily, one entry at a time. The default implementation is
+    /// backed by `list`, and reports every entry as a file of unknown size.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive cannot be opened. Errors
+    /// while reading entries are yielded by the iterator.
+    fn list_iter(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        let listing = self.list(archive, opts)?;
+        Ok(Box::new(listing.entries.into_iter().map(|name| {
+            Ok(ListingEntry {
+                path: RelPath::new(name),
+                kind: RelPathKind::File,
+                size: None,
+                compressed_size: None,
+                comment: None,
+                mode: None,
+                is_name_lossy: false,
+            })
+        })))
+    }
+
+    ///
+    /// Count the entries of an archive, the ones `list` would report
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if listing fails.
+    fn count(&self, archive: &Path, opts: &ExtractOpts) -> Result<usize, DecompressError> {
+        self.list_iter(archive, opts)?
+            .try_fold(0, |count, entry| entry.map(|_| count + 1))
+    }
+
+    ///
+    /// Decompress an archive
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails.
+    fn decompress(
+        &self,
+        archive: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError>;
+
+    ///
+    /// Check that every entry of an archive decodes, without writing anything. The
+    /// default implementation only walks the listing.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive is corrupt.
+    fn verify(&self, archive: &Path, opts: &ExtractOpts) -> Result<(), DecompressError> {
+        self.list_iter(archive, opts)?
+            .try_for_each(|entry| entry.map(|_| ()))
+    }
+
+    ///
+    /// Decompress an archive from a stream. `name` stands in for the archive path,
+    /// for decompressors that derive output names from it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails. The default implementation
+    /// returns `DecompressError::SeekRequired` without reading from `reader`.
+    fn decompress_reader(
+        &self,
+        reader: Box<dyn Read>,
+        name: &Path,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let _ = (reader, name, to, opts);
+        Err(DecompressError::SeekRequired(self.id()))
+    }
+
+    ///
+    /// Decompress a single-file archive (such as gz) into `writer`, returning the number
+    /// of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails. The default implementation,
+    /// used by multi-file archives, returns `DecompressError::NotSingleFile`.
+    fn decompress_to_writer(
+        &self,
+        archive: &Path,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let _ = (archive, writer, opts);
+        Err(DecompressError::NotSingleFile(self.id()))
+    }
+
+    ///
+    /// Decompress `len` bytes of a single-file archive, starting `start` bytes into its
+    /// uncompressed content, into `writer`. Returns the number of bytes written, which is
+    /// less than `len` when the content ends first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails. The default implementation
+    /// decompresses everything through `decompress_to_writer`, keeping only the range.
+    fn decompress_range(
+        &self,
+        archive: &Path,
+        start: u64,
+        len: u64,
+        writer: &mut dyn Write,
+        opts: &ExtractOpts,
+    ) -> Result<u64, DecompressError> {
+        let mut range = decompressors::utils::RangeWriter::new(writer, start, len);
+        self.decompress_to_writer(archive, &mut range, opts)?;
+        Ok(len - range.remaining())
+    }
+
+    ///
+    /// Hand the archive's entries to `sink` instead of writing them to the filesystem.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if unpacking fails, or whatever error `sink`
+    /// returns. The default implementation returns `DecompressError::SinkUnsupported`.
+    fn decompress_to_sink(
+        &self,
+        archive: &Path,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        let _ = (archive, sink, opts);
+        Err(DecompressError::SinkUnsupported(self.id()))
+    }
+
+    ///
+    /// Iterate over the archive's file entries, each with a reader of its contents.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive can't be opened. The default
+    /// implementation returns `DecompressError::EntriesUnsupported`.
+    fn entries(
+        &self,
+        archive: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        let _ = (archive, opts);
+        Err(DecompressError::EntriesUnsupported(self.id()))
+    }
+
+    ///
+    /// The archive-level comment, for formats that carry one (zip). The default
+    /// implementation returns `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive cannot be read.
+    fn archive_comment(&self, archive: &Path) -> Result<Option<String>, DecompressError> {
+        let _ = archive;
+        Ok(None)
+    }
+
+    ///
+    /// Test if an archive needs a password, for formats that support encryption (zip,
+    /// rar). The default implementation returns `false`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive cannot be read.
+    fn is_encrypted(&self, archive: &Path) -> Result<bool, DecompressError> {
+        let _ = archive;
+        Ok(false)
+    }
+
+    ///
+    /// Read the archive's metadata. The default implementation gathers it from `count`,
+    /// `archive_comment` and `is_encrypted`, and leaves `version_needed` out.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive cannot be read.
+    fn metadata(&self, archive: &Path) -> Result<ArchiveMetadata, DecompressError> {
+        let opts = ExtractOptsBuilder::default()
+            .build()
+            .map_err(|err| DecompressError::Error(err.to_string()))?;
+        Ok(ArchiveMetadata {
+            id: self.id(),
+            entry_count: self.count(archive, &opts)?,
+            comment: self.archive_comment(archive)?,
+            encrypted: self.is_encrypted(archive)?,
+            version_needed: None,
+        })
+    }
+}
+
+///
+/// Represent a stack of decompressors with a default stack preconfigured when calling `new`
+///
+pub struct Decompress {
+    decompressors: Vec<Box<dyn Decompressor>>,
+    /// File names of formats this build leaves out, with the feature each one needs
+    disabled: Vec<(&'static str, Regex)>,
+}
+
+/// The formats of the default stack whose features aren't enabled, for
+/// `DecompressError::FeatureDisabled`
+fn disabled_formats() -> Vec<(&'static str, Regex)> {
+    let formats: &[(&'static str, &str)] = &[
+        #[cfg(not(feature = "zip"))]
+        ("zip", r"(?i)\.zip$"),
+        #[cfg(not(feature = "targz"))]
+        ("targz", r"(?i)\.t(ar\.gz|gz)$"),
+        #[cfg(not(feature = "tarball"))]
+        ("tarball", r"(?i)\.tar$"),
+        #[cfg(not(feature = "tarxz"))]
+        ("tarxz", r"(?i)\.t(ar\.xz|xz)$"),
+        #[cfg(not(feature = "tarbz"))]
+        ("tarbz", r"(?i)\.t(ar\.bz2|bz)$"),
+        #[cfg(not(feature = "tarzst"))]
+        ("tarzst", r"(?i)\.t(ar\.zst|zst)$"),
+        #[cfg(not(feature = "warcgz"))]
+        ("warcgz", r"(?i)\.warc\.gz$"),
+        #[cfg(not(feature = "warc"))]
+        ("warc", r"(?i)\.warc$"),
+        #[cfg(not(feature = "lha"))]
+        ("lha", r"(?i)\.(lzh|lha)$"),
+        #[cfg(not(feature = "gz"))]
+        ("gz", r"(?i)\.gz$"),
+        #[cfg(not(feature = "ar"))]
+        ("ar", r"(?i)\.ar$"),
+        #[cfg(not(feature = "deb"))]
+        ("deb", r"(?i)\.deb$"),
+        #[cfg(not(feature = "bz2"))]
+        ("bz2", r"(?i)\.bz2$"),
+        #[cfg(not(feature = "xz"))]
+        ("xz", r"(?i)\.xz$"),
+        #[cfg(not(feature = "zstd"))]
+        ("zstd", r"(?i)\.zst$"),
+        #[cfg(not(feature = "rar"))]
+        ("rar", r"(?i)\.(rar|r\d{2,})$"),
+    ];
+    formats
+        .iter()
+        .map(|(feature, re)| (*feature, Regex::new(re).unwrap()))
+        .collect()
+}
+
+impl Default for Decompress {
+    fn default() -> Self {
+        Self {
+            decompressors: vec![
+                #[cfg(feature = "zip")]
+                Box::<decompressors::zip::Zip>::default(),
+                #[cfg(feature = "targz")]
+                Box::<decompressors::targz::Targz>::default(),
+                #[cfg(feature = "tarball")]
+                Box::<decompressors::tarball::Tarball>::default(),
+                #[cfg(feature = "tarxz")]
+                Box::<decompressors::tarxz::Tarxz>::default(),
+                #[cfg(feature = "tarbz")]
+                Box::<decompressors::tarbz::Tarbz>::default(),
+                #[cfg(feature = "tarzst")]
+                Box::<decompressors::tarzst::Tarzst>::default(),
+                #[cfg(feature = "warcgz")]
+                Box::<decompressors::warc::WarcGz>::default(),
+                #[cfg(feature = "warc")]
+                Box::<decompressors::warc::Warc>::default(),
+                #[cfg(feature = "lha")]
+                Box::<decompressors::lha::Lha>::default(),
+                // order is important, `gz` is placed only after the targz variant did not match
+                // if it's placed above targz, it will unpack and leave a tar archive.
+                #[cfg(feature = "gz")]
+                Box::<decompressors::gz::Gz>::default(),
+                #[cfg(feature = "ar")]
+                Box::<decompressors::ar::Ar>::default(),
+                #[cfg(feature = "deb")]
+                Box::<decompressors::deb::Deb>::default(),
+                #[cfg(feature = "bz2")]
+                Box::<decompressors::bz2::Bz2>::default(),
+                #[cfg(feature = "xz")]
+                Box::<decompressors::xz::Xz>::default(),
+                #[cfg(feature = "zstd")]
+                Box::<decompressors::zstd::Zstd>::default(),
+                #[cfg(feature = "rar")]
+                Box::<decompressors::unrar::Unrar>::default(),
+            ],
+            disabled: disabled_formats(),
+        }
+    }
+}
+
+impl Decompress {
+    /// Find a decompressor from the stack
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if IO fails
+    #[allow(clippy::borrowed_box)]
+    pub fn find_decompressor<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        detect_content: bool,
+    ) -> Result<&Box<dyn Decompressor>, DecompressError> {
+        if detect_content {
+            let res = infer::get_from_path(archive.as_ref())?;
+            let mt = res.map(|t| t.mime_type());
+            mt.and_then(|mt| {
+                self.decompressors
+                    .iter()
+                    .find(|dec| dec.test_mimetype(mt) && dec.test_content(archive.as_ref()))
+            })
+        } else {
+            self.decompressors
+                .iter()
+                .find(|dec| dec.test(archive.as_ref()))
+        }
+        .ok_or_else(|| self.missing(archive.as_ref()))
+    }
+
+    /// `DecompressError::FeatureDisabled` if the archive is named like a format that's
+    /// known but disabled, `DecompressError::MissingCompressor` otherwise
+    fn missing(&self, archive: &Path) -> DecompressError {
+        archive
+            .file_name()
+            .and_then(std::ffi::OsStr::to_str)
+            .and_then(|name| {
+                self.disabled
+                    .iter()
+                    .find(|(_, re)| re.is_match(name))
+                    .map(|(feature, _)| DecompressError::FeatureDisabled(feature))
+            })
+            .unwrap_or(DecompressError::MissingCompressor)
+    }
+
+    /// Build given a custom stack of decompressors
+    #[must_use]
+    pub fn build(decompressors: Vec<Box<dyn Decompressor>>) -> Self {
+        Self {
+            decompressors,
+            disabled: vec![],
+        }
+    }
+
+    /// Register a format that `feature` would handle, so archives whose name `re`
+    /// matches fail with `DecompressError::FeatureDisabled` rather than
+    /// `DecompressError::MissingCompressor` when nothing in the stack takes them.
+    /// `Decompress::default()` registers every format of the default stack whose
+    /// feature isn't enabled
+    #[must_use]
+    pub fn with_disabled(mut self, feature: &'static str, re: Regex) -> Self {
+        self.disabled.push((feature, re));
+        self
+    }
+
+    /// Add a decompressor at the bottom of the stack, so that it's only picked when none
+    /// of the others match
+    #[must_use]
+    pub fn with(mut self, decompressor: Box<dyn Decompressor>) -> Self {
+        self.decompressors.push(decompressor);
+        self
+    }
+
+    /// Build a stack of just the default decompressors with the given ids (such as `"zip"`
+    /// or `"targz"`), in their default order. Ids of decompressors that aren't compiled
+    /// in are ignored
+    #[must_use]
+    pub fn only(ids: &[&str]) -> Self {
+        let Self {
+            decompressors,
+            disabled,
+        } = Self::default();
+        Self {
+            decompressors: decompressors
+                .into_iter()
+                .filter(|dec| ids.contains(&dec.id()))
+                .collect(),
+            disabled: disabled
+                .into_iter()
+                .filter(|(feature, _)| ids.contains(feature))
+                .collect(),
+        }
+    }
+
+    /// Build a stack that only decompresses zip archives
+    #[must_use]
+    pub fn zip_only() -> Self {
+        Self::only(&["zip"])
+    }
+
+    /// Build a stack that only decompresses tar archives, plain or compressed
+    #[must_use]
+    pub fn tar_family_only() -> Self {
+        Self::only(&["tarball", "targz", "tarbz", "tarxz", "tarzst"])
+    }
+
+    /// List
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn list<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<Listing, DecompressError> {
+        self.find_decompressor(archive.as_ref(), opts.detect_content)
+            .and_then(|dec| dec.list(archive.as_ref(), opts))
+    }
+
+    /// List the names entries would be written under, relative to the output folder:
+    /// each listed name goes through `strip_common_prefix`, `strip`, `rename_rules`, `wrap_in` and `map`
+    /// (or `map_opt`) as `decompress` would apply them. Entries stripped away or skipped
+    /// by `map_opt` are left out. Only names are transformed, nothing is extracted or
+    /// checked against the disk, and `map` sees relative rather than output paths
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn list_mapped<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<Listing, DecompressError> {
+        let dec = self.find_decompressor(archive.as_ref(), opts.detect_content)?;
+        let mut strip = opts.strip;
+        if opts.strip_common_prefix {
+            strip += common_prefix_len(dec.list_iter(archive.as_ref(), opts)?)?;
+        }
+        let root = wrapped(Path::new(""), opts);
+        let mut entries = vec![];
+        for entry in dec.list_iter(archive.as_ref(), opts)? {
+            let entry = entry?;
+            let path = entry
+                .path
+                .as_path()
+                .components()
+                .skip(strip)
+                .collect::<PathBuf>();
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let path = root.join(decompressors::utils::rename(opts, &path, entry.kind));
+            let mapped = opts
+                .map_opt
+                .as_ref()
+                .map_or_else(|| Some((opts.map)(&path)), |map_opt| map_opt(&path));
+            if let Some(mapped) = mapped {
+                entries.push(mapped.to_string_lossy().to_string());
+            }
+        }
+        Ok(Listing {
+            id: dec.id(),
+            entries,
+        })
+    }
+
+    /// List lazily, one entry at a time, without building a `Vec` of names
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the archive cannot be opened
+    pub fn list_iter<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<ListingIter, DecompressError> {
+        self.find_decompressor(archive.as_ref(), opts.detect_content)
+            .and_then(|dec| dec.list_iter(archive.as_ref(), opts))
+    }
+
+    /// The names of the first `limit` entries, such as for a preview. Built on
+    /// `list_iter`, so tarballs stop reading after `limit` headers. Zip still reads its
+    /// whole central directory, but none of the entry data
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn peek<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        limit: usize,
+        opts: &ExtractOpts,
+    ) -> Result<Vec<String>, DecompressError> {
+        self.list_iter(archive, opts)?
+            .take(limit)
+            .map(|entry| entry.map(|entry| entry.path.as_path().to_string_lossy().to_string()))
+            .collect()
+    }
+
+    /// Count the entries of an archive, without building a `Vec` of names
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn count<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<usize, DecompressError> {
+        self.find_decompressor(archive.as_ref(), opts.detect_content)
+            .and_then(|dec| dec.count(archive.as_ref(), opts))
+    }
+
+    /// Count the entries of an archive and add up their sizes, from its listing
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn summary<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<ArchiveSummary, DecompressError> {
+        let mut summary = ArchiveSummary {
+            entry_count: 0,
+            total_uncompressed: 0,
+            total_compressed: 0,
+            largest_entry: None,
+        };
+        for entry in self.list_iter(archive, opts)? {
+            let entry = entry?;
+            summary.entry_count += 1;
+            summary.total_uncompressed += entry.size.unwrap_or_default();
+            summary.total_compressed += entry.compressed_size.unwrap_or_default();
+            if entry.size.is_some()
+                && entry.size > summary.largest_entry.as_ref().and_then(|e| e.size)
+            {
+                summary.largest_entry = Some(entry);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Group an archive's files by the top-level folder they're in, from its listing, as
+    /// `(folder, file count, total size)` sorted by folder. Files at the root of the
+    /// archive are under `""`, and top-level folders without files are there with 0
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn top_level_summary<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<Vec<(String, usize, u64)>, DecompressError> {
+        let mut groups = BTreeMap::<String, (usize, u64)>::new();
+        for entry in self.list_iter(archive, opts)? {
+            let entry = entry?;
+            let parts = entry.path.parts();
+            let Some(first) = parts.first() else {
+                continue;
+            };
+            if entry.kind == RelPathKind::Dir {
+                groups.entry(first.clone()).or_default();
+                continue;
+            }
+            let key = if parts.len() == 1 { "" } else { first };
+            let group = groups.entry(key.to_string()).or_default();
+            group.0 += 1;
+            group.1 += entry.size.unwrap_or_default();
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(folder, (count, size))| (folder, count, size))
+            .collect())
+    }
+
+    /// Build a folder tree of an archive's entries from its listing, without extracting
+    /// anything. Folders the archive only implies through its entry paths are part of the
+    /// tree, and entries keep the archive's order within their folder
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn tree<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<ArchiveNode, DecompressError> {
+        let mut root = ArchiveNode::Dir {
+            name: String::new(),
+            children: vec![],
+        };
+        for entry in self.list_iter(archive, opts)? {
+            let entry = entry?;
+            root.insert(&entry.path.parts(), entry.kind, entry.size);
+        }
+        Ok(root)
+    }
+
+    /// Check that every entry of an archive decodes (and, for zip, that CRCs match),
+    /// without writing anything to disk
+    ///
+    /// # Errors
+    ///
+    /// This function will return the first corruption error, or all of them as
+    /// `DecompressError::Multiple` under `continue_on_error`
+    pub fn verify<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        self.find_decompressor(archive.as_ref(), opts.detect_content)
+            .and_then(|dec| dec.verify(archive.as_ref(), opts))
+    }
+
+    /// Decompress with a decompressor that is selected based on file name (cheaper)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn decompress<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.open_with(archive.as_ref(), opts.detect_content)?
+            .extract(to, opts)
+    }
+
+    /// Decompress, and as long as that leaves exactly one file that `can_decompress`
+    /// recognizes (a zip holding just a `.tar.gz`), decompress that file into the same
+    /// folder too and remove it. `opts` apply at every level, except that `wrap_in` only
+    /// wraps the outermost. `format_chain` lists every format unwrapped, outermost first.
+    /// Unwrapping stops when a level writes back the very file it read
     ///
     /// # Errors
     ///
-    /// This function will return an error if unpacking fails.
-    fn list(&self, archive: &Path) -> Result<Listing, DecompressError>;
+    /// This function will return an error if an IO or parsing error happened at any level,
+    /// or `DecompressError::TooManyLevels` if the chain goes on for more than 16 archives
+    pub fn unwrap_single<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let mut res = self.decompress(archive, to, opts)?;
+        let mut chain = res.format_chain.clone();
+        let inner_opts = ExtractOpts {
+            wrap_in: None,
+            ..opts.clone()
+        };
+        while let [inner] = res.files.as_slice() {
+            let inner = PathBuf::from(inner);
+            // passthrough matches any file, and would copy it onto itself
+            if !matches!(self.matching_id(&inner), Some(id) if id != "passthrough") {
+                break;
+            }
+            if chain.len() >= MAX_UNWRAP_LEVELS {
+                return Err(DecompressError::TooManyLevels {
+                    limit: MAX_UNWRAP_LEVELS,
+                });
+            }
+            let to = res.to.clone();
+            let next = self.decompress(inner.as_path(), to.as_path(), &inner_opts)?;
+            let rewritten = next.files.contains(&inner.to_string_lossy().to_string());
+            if !rewritten {
+                std::fs::remove_file(&inner)?;
+            }
+            chain.extend(&next.format_chain);
+            res = next;
+            if rewritten {
+                break;
+            }
+        }
+        Ok(res.with_format_chain(chain))
+    }
 
-    ///
-    /// Decompress an archive
+    /// Extract just the entries named in `names`, in a single pass over the archive, and
+    /// return the paths of the files written for them in the order they were found.
+    /// Names are entry paths as `list` reports them (matched after `strip`, if any).
+    /// Picking entries relies on `filter`, so only tar, zip and warc archives can do it
     ///
     /// # Errors
     ///
-    /// This function will return an error if unpacking fails.
-    fn decompress(
+    /// This function will return an error if an IO or parsing error happened, or
+    /// `DecompressError::MissingEntries` with the names that aren't in the archive (the
+    /// ones that are have been written by then)
+    pub fn extract_entries<P: AsRef<Path>>(
         &self,
-        archive: &Path,
-        to: &Path,
+        archive: P,
+        names: &HashSet<String>,
+        to: P,
         opts: &ExtractOpts,
-    ) -> Result<Decompression, DecompressError>;
-}
+    ) -> Result<Vec<PathBuf>, DecompressError> {
+        self.open_with(archive.as_ref(), opts.detect_content)?
+            .extract_entries(names, to, opts)
+    }
 
-///
-/// Represent a stack of decompressors with a default stack preconfigured when calling `new`
-///
-pub struct Decompress {
-    decompressors: Vec<Box<dyn Decompressor>>,
-}
+    /// Pick the decompressor for an archive once, by file name or else by content, for
+    /// several operations on it that don't detect its format again
+    ///
+    /// # Errors
+    ///
+    /// Returns `DecompressError::MissingCompressor` if no decompressor matches, or an
+    /// error if the archive can't be read to check its content
+    pub fn open<P: AsRef<Path>>(&self, archive: P) -> Result<OpenArchive<'_>, DecompressError> {
+        match self.open_with(archive.as_ref(), false) {
+            Err(DecompressError::MissingCompressor | DecompressError::FeatureDisabled(_)) => {
+                self.open_with(archive.as_ref(), true)
+            }
+            res => res,
+        }
+    }
 
-impl Default for Decompress {
-    fn default() -> Self {
-        Self {
-            decompressors: vec![
-                #[cfg(feature = "zip")]
-                Box::<decompressors::zip::Zip>::default(),
-                #[cfg(feature = "targz")]
-                Box::<decompressors::targz::Targz>::default(),
-                #[cfg(feature = "tarball")]
-                Box::<decompressors::tarball::Tarball>::default(),
-                #[cfg(feature = "tarxz")]
-                Box::<decompressors::tarxz::Tarxz>::default(),
-                #[cfg(feature = "tarbz")]
-                Box::<decompressors::tarbz::Tarbz>::default(),
-                #[cfg(feature = "tarzst")]
-                Box::<decompressors::tarzst::Tarzst>::default(),
-                // order is important, `gz` is placed only after the targz variant did not match
-                // if it's placed above targz, it will unpack and leave a tar archive.
-                #[cfg(feature = "gz")]
-                Box::<decompressors::gz::Gz>::default(),
-                #[cfg(feature = "ar")]
-                Box::<decompressors::ar::Ar>::default(),
-                #[cfg(feature = "bz2")]
-                Box::<decompressors::bz2::Bz2>::default(),
-                #[cfg(feature = "xz")]
-                Box::<decompressors::xz::Xz>::default(),
-                #[cfg(feature = "zstd")]
-                Box::<decompressors::zstd::Zstd>::default(),
-                #[cfg(feature = "rar")]
-                Box::<decompressors::unrar::Unrar>::default(),
-            ],
+    fn open_with(
+        &self,
+        archive: &Path,
+        detect_content: bool,
+    ) -> Result<OpenArchive<'_>, DecompressError> {
+        Ok(OpenArchive {
+            dec: self.find_decompressor(archive, detect_content)?.as_ref(),
+            archive: archive.to_path_buf(),
+        })
+    }
+
+    /// Decompress into `sink` rather than the filesystem, with `strip`, `filter`, `map`
+    /// and the other options that decide names and which entries to keep applied as
+    /// `decompress` would. Only tar and zip archives can do it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened, the
+    /// format can't extract to a sink, or `sink` failed
+    pub fn decompress_to_sink<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        sink: &mut dyn EntrySink,
+        opts: &ExtractOpts,
+    ) -> Result<(), DecompressError> {
+        let dec = self.find_decompressor(archive.as_ref(), opts.detect_content)?;
+        if opts.strip_common_prefix {
+            let mut opts = opts.clone();
+            opts.strip += common_prefix_len(dec.list_iter(archive.as_ref(), &opts)?)?;
+            dec.decompress_to_sink(archive.as_ref(), sink, &opts)
+        } else {
+            dec.decompress_to_sink(archive.as_ref(), sink, opts)
         }
     }
-}
 
-impl Decompress {
-    /// Find a decompressor from the stack
+    /// Iterate over the file entries of a tar or zip archive, each with a reader of its
+    /// contents, to process them without writing them out. Names and the entries kept
+    /// follow the options as with `decompress_to_sink`.
+    ///
+    /// Entries come in archive order and a tar stream can't go back, so a reader only
+    /// works until the iterator advances: read each entry before asking for the next one,
+    /// a reader kept past that reads nothing more
     ///
     /// # Errors
     ///
-    /// This function will return an error if IO fails
-    #[allow(clippy::borrowed_box)]
-    pub fn find_decompressor<P: AsRef<Path>>(
+    /// This function will return an error if the archive can't be opened or the format
+    /// can't be read entry by entry. Errors found along the way come from the iterator
+    pub fn entries<P: AsRef<Path>>(
         &self,
         archive: P,
-        detect_content: bool,
-    ) -> Result<&Box<dyn Decompressor>, DecompressError> {
-        if detect_content {
-            let res = infer::get_from_path(archive.as_ref())?;
-            let mt = res.map(|t| t.mime_type());
-            mt.and_then(|mt| self.decompressors.iter().find(|dec| dec.test_mimetype(mt)))
+        opts: &ExtractOpts,
+    ) -> Result<EntryReaderIter, DecompressError> {
+        let dec = self.find_decompressor(archive.as_ref(), opts.detect_content)?;
+        if opts.strip_common_prefix {
+            let mut opts = opts.clone();
+            opts.strip += common_prefix_len(dec.list_iter(archive.as_ref(), &opts)?)?;
+            dec.entries(archive.as_ref(), &opts)
         } else {
-            println!("f: {:?} ", archive.as_ref());
-            self.decompressors
-                .iter()
-                .find(|dec| dec.test(archive.as_ref()))
+            dec.entries(archive.as_ref(), opts)
         }
-        .ok_or(DecompressError::MissingCompressor)
     }
 
-    /// Build given a custom stack of decompressors
-    #[must_use]
-    pub fn build(decompressors: Vec<Box<dyn Decompressor>>) -> Self {
-        Self { decompressors }
+    /// Decompress an archive from a stream, with a decompressor that is selected based on
+    /// `name`, which stands in for the archive's file name. Formats that need `Seek`
+    /// (zip, ar, rar) fail with `DecompressError::SeekRequired`, zip unless
+    /// `ExtractOpts::zip_streaming` is set
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn decompress_reader<R: Read + 'static, P: AsRef<Path>>(
+        &self,
+        reader: R,
+        name: P,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        self.find_decompressor(name.as_ref(), false)
+            .and_then(|dec| {
+                dec.decompress_reader(
+                    Box::new(reader),
+                    name.as_ref(),
+                    &wrapped(to.as_ref(), opts),
+                    opts,
+                )
+            })
     }
 
-    /// List
+    /// Decompress a single-file archive (gz, bz2, xz, zst) into `writer` rather than a
+    /// folder, returning the number of bytes written. Multi-file archives fail with
+    /// `DecompressError::NotSingleFile`
     ///
     /// # Errors
     ///
     /// This function will return an error if an IO or parsing error happened
-    pub fn list<P: AsRef<Path>>(
+    pub fn decompress_to_writer<P: AsRef<Path>, W: Write>(
         &self,
         archive: P,
+        writer: &mut W,
         opts: &ExtractOpts,
-    ) -> Result<Listing, DecompressError> {
+    ) -> Result<u64, DecompressError> {
         self.find_decompressor(archive.as_ref(), opts.detect_content)
-            .and_then(|dec| dec.list(archive.as_ref()))
+            .and_then(|dec| dec.decompress_to_writer(archive.as_ref(), writer, opts))
     }
 
-    /// Decompress with a decompressor that is selected based on file name (cheaper)
+    /// Decompress `len` bytes of a single-file archive, starting `start` bytes into its
+    /// uncompressed content, into `writer`, returning the number of bytes written.
+    /// Seekable zstd files (with a seek table) only decode the frames holding the range,
+    /// other formats decode from the start and skip ahead
     ///
     /// # Errors
     ///
     /// This function will return an error if an IO or parsing error happened
-    pub fn decompress<P: AsRef<Path>>(
+    pub fn decompress_range<P: AsRef<Path>, W: Write>(
         &self,
         archive: P,
-        to: P,
+        start: u64,
+        len: u64,
+        writer: &mut W,
         opts: &ExtractOpts,
-    ) -> Result<Decompression, DecompressError> {
+    ) -> Result<u64, DecompressError> {
         self.find_decompressor(archive.as_ref(), opts.detect_content)
-            .and_then(|dec| dec.decompress(archive.as_ref(), to.as_ref(), opts))
+            .and_then(|dec| dec.decompress_range(archive.as_ref(), start, len, writer, opts))
+    }
+
+    /// Download and decompress an archive, streaming the response body where the format
+    /// allows it. The decompressor is selected based on the URL's file name, or else on the
+    /// `Content-Type` header. Formats that need `Seek` are buffered to a temporary file first
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request, IO or parsing failed, or
+    /// `DecompressError::FeatureDisabled` if the URL names a format that's disabled
+    #[cfg(feature = "http")]
+    pub fn decompress_url(
+        &self,
+        url: &str,
+        to: &Path,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        use std::{cell::RefCell, rc::Rc};
+
+        struct Shared<R>(Rc<RefCell<R>>);
+        impl<R: Read> Read for Shared<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.borrow_mut().read(buf)
+            }
+        }
+
+        let http_err = |e: reqwest::Error| DecompressError::Error(e.to_string());
+        let resp = reqwest::blocking::get(url)
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .map_err(http_err)?;
+        let name = PathBuf::from(
+            resp.url()
+                .path_segments()
+                .and_then(Iterator::last)
+                .filter(|segment| !segment.is_empty())
+                .unwrap_or("download"),
+        );
+        let mimetype = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_string());
+
+        let dec = self
+            .decompressors
+            .iter()
+            .find(|dec| dec.test(&name))
+            .or_else(|| {
+                mimetype.and_then(|mt| self.decompressors.iter().find(|dec| dec.test_mimetype(&mt)))
+            })
+            .ok_or_else(|| self.missing(&name))?;
+
+        let to = wrapped(to, opts);
+        let body = Rc::new(RefCell::new(resp));
+        match dec.decompress_reader(Box::new(Shared(body.clone())), &name, &to, opts) {
+            Err(DecompressError::SeekRequired(_)) => {
+                let tmp = tempfile::tempdir()?;
+                let archive = tmp.path().join(&name);
+                io::copy(
+                    &mut *body.borrow_mut(),
+                    &mut std::fs::File::create(&archive)?,
+                )?;
+                dec.decompress(&archive, &to, opts)
+            }
+            res => res,
+        }
+    }
+
+    /// Decompress into a folder named after the archive under `out_root`, with all archive
+    /// extensions stripped (`out_root/foo` for `foo.tar.gz`).
+    /// The folder is reported in `Decompression.to`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn decompress_into_named<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        out_root: P,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let name = archive
+            .as_ref()
+            .file_name()
+            .ok_or_else(|| DecompressError::Error("cannot compose a file name".to_string()))?
+            .to_string_lossy();
+        let to = out_root.as_ref().join(strip_archive_extensions(&name));
+        self.decompress(archive.as_ref(), to.as_path(), opts)
+    }
+
+    /// Decompress into a new temporary folder, which is removed when the returned
+    /// `TempDir` is dropped. `Decompression.to` points into it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the folder can't be created, or if an IO
+    /// or parsing error happened
+    #[cfg(feature = "temp")]
+    pub fn decompress_temp<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        opts: &ExtractOpts,
+    ) -> Result<(tempfile::TempDir, Decompression), DecompressError> {
+        let tmp = tempfile::tempdir()?;
+        let res = self.decompress(archive.as_ref(), tmp.path(), opts)?;
+        Ok((tmp, res))
     }
 
     /// Returns `true` if any of the decompressors in the stack can decompress this
@@ -244,16 +2047,312 @@ impl Decompress {
     ) -> Result<bool, DecompressError> {
         match self.find_decompressor(archive.as_ref(), true) {
             Ok(_) => Ok(true),
-            Err(DecompressError::MissingCompressor) => Ok(false),
+            Err(DecompressError::MissingCompressor | DecompressError::FeatureDisabled(_)) => {
+                Ok(false)
+            }
             Err(e) => Err(e),
         }
     }
 
+    /// Strip the extensions of the formats in the stack from a file name, last one first,
+    /// for as long as the stack recognizes what's left. `foo.tar.gz` becomes `foo` with
+    /// the default stack, and `foo.tar` with a stack that only has gz. Unlike
+    /// `strip_archive_extensions`, names added with `build` or `build_extended` count
+    #[must_use]
+    pub fn strip_extensions(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        loop {
+            let path = Path::new(&name);
+            // passthrough takes any name, none of its extensions are an archive's
+            let recognized = self
+                .decompressors
+                .iter()
+                .any(|dec| dec.id() != "passthrough" && dec.test(path));
+            match path.file_stem().and_then(std::ffi::OsStr::to_str) {
+                Some(stem) if recognized && stem != name => name = stem.to_string(),
+                _ => return name,
+            }
+        }
+    }
+
     /// Returns `true` if any of the decompressors in the stack can decompress this
     /// specific archive based on its path (no file opening)
     pub fn can_decompress<P: AsRef<Path>>(&self, archive: P) -> bool {
         self.find_decompressor(archive.as_ref(), false).is_ok()
     }
+
+    /// The id of the decompressor in the stack that would decompress this archive, based
+    /// on its path (no file opening). Same as `detect` without content detection, which
+    /// can't fail then
+    #[must_use]
+    pub fn matching_id<P: AsRef<Path>>(&self, archive: P) -> Option<&'static str> {
+        self.detect(archive, false).ok().flatten()
+    }
+
+    /// The id of the decompressor in the stack that would decompress this archive, based
+    /// on its content (reads first 8kb). Same as `detect` with content detection
+    ///
+    /// # Errors
+    /// May fail if cannot read the file
+    pub fn matching_id_content<P: AsRef<Path>>(
+        &self,
+        archive: P,
+    ) -> Result<Option<&'static str>, DecompressError> {
+        self.detect(archive, true)
+    }
+
+    /// The archive-level comment (zip), `None` when there's none or the format has no
+    /// such thing. The decompressor is selected based on file name
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no decompressor matches, or the archive
+    /// cannot be read
+    pub fn archive_comment<P: AsRef<Path>>(
+        &self,
+        archive: P,
+    ) -> Result<Option<String>, DecompressError> {
+        self.find_decompressor(archive.as_ref(), false)
+            .and_then(|dec| dec.archive_comment(archive.as_ref()))
+    }
+
+    /// The archive's entry count, comment, encryption and the version needed to extract it,
+    /// in one go where the format allows. The decompressor is selected based on file name
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no decompressor matches, or the archive
+    /// cannot be read
+    pub fn metadata<P: AsRef<Path>>(&self, archive: P) -> Result<ArchiveMetadata, DecompressError> {
+        self.find_decompressor(archive.as_ref(), false)
+            .and_then(|dec| dec.metadata(archive.as_ref()))
+    }
+
+    /// Returns `true` if any entry of the archive (or, for rar, its headers) is encrypted,
+    /// so a password is needed to extract it. The decompressor is selected based on
+    /// file name
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no decompressor matches, or the archive
+    /// cannot be read
+    pub fn is_encrypted<P: AsRef<Path>>(&self, archive: P) -> Result<bool, DecompressError> {
+        self.find_decompressor(archive.as_ref(), false)
+            .and_then(|dec| dec.is_encrypted(archive.as_ref()))
+    }
+
+    /// Identify the decompressor in the stack for this archive: by content (reads first
+    /// 8kb), or else by file name. When both match the same kind of content, the
+    /// extension narrows it down (so a `.gz` isn't taken for a `.tar.gz`)
+    ///
+    /// # Errors
+    /// May fail if cannot read the file, or with `DecompressError::MissingCompressor`
+    /// if neither content nor file name match
+    pub fn identify<P: AsRef<Path>>(&self, archive: P) -> Result<Identification, DecompressError> {
+        let archive = archive.as_ref();
+        let by_name = self.decompressors.iter().find(|dec| dec.test(archive));
+        let mimetype = infer::get_from_path(archive)?.map(|t| t.mime_type());
+        if let Some(mt) = mimetype {
+            let by_content = by_name.filter(|dec| dec.test_mimetype(mt)).or_else(|| {
+                self.decompressors
+                    .iter()
+                    .find(|dec| dec.test_mimetype(mt) && dec.test_content(archive))
+            });
+            if let Some(dec) = by_content {
+                return Ok(Identification {
+                    id: dec.id(),
+                    via: DetectionMethod::Content,
+                });
+            }
+        }
+        by_name
+            .map(|dec| Identification {
+                id: dec.id(),
+                via: DetectionMethod::Extension,
+            })
+            .ok_or_else(|| self.missing(archive))
+    }
+
+    /// Returns the id of the decompressor in the stack that would handle this
+    /// specific archive, or `None` if unsupported. Picks it the way `decompress` does:
+    /// based on its content (reads first 8kb) with `detect_content`, and on its path
+    /// otherwise
+    ///
+    /// # Errors
+    /// May fail if cannot read the file
+    pub fn detect<P: AsRef<Path>>(
+        &self,
+        archive: P,
+        detect_content: bool,
+    ) -> Result<Option<&'static str>, DecompressError> {
+        match self.find_decompressor(archive.as_ref(), detect_content) {
+            Ok(dec) => Ok(Some(dec.id())),
+            Err(DecompressError::MissingCompressor | DecompressError::FeatureDisabled(_)) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// An archive with its decompressor already picked, from `Decompress::open`
+pub struct OpenArchive<'a> {
+    dec: &'a dyn Decompressor,
+    archive: PathBuf,
+}
+
+impl OpenArchive<'_> {
+    /// The id of the decompressor picked for the archive
+    #[must_use]
+    pub fn id(&self) -> &'static str {
+        self.dec.id()
+    }
+
+    /// The path the archive was opened from
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.archive
+    }
+
+    /// List the archive's entries
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn list(&self, opts: &ExtractOpts) -> Result<Listing, DecompressError> {
+        self.dec.list(&self.archive, opts)
+    }
+
+    /// Count the archive's entries, without building a `Vec` of names
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn count(&self, opts: &ExtractOpts) -> Result<usize, DecompressError> {
+        self.dec.count(&self.archive, opts)
+    }
+
+    /// Decompress the archive into `to`, as `Decompress::decompress` does
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened
+    pub fn extract<P: AsRef<Path>>(
+        &self,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Decompression, DecompressError> {
+        let root = to.as_ref();
+        let to = wrapped(root, opts);
+        let mut opts = Cow::Borrowed(opts);
+        if opts.strip_common_prefix {
+            let strip = common_prefix_len(self.dec.list_iter(&self.archive, &opts)?)?;
+            opts.to_mut().strip += strip;
+        }
+        if let Some(on_entry) = opts.on_entry.clone().filter(|_| opts.precompute_total) {
+            // a single unknown size leaves the total unknown, not short
+            let mut total = Some(0);
+            for entry in self.dec.list_iter(&self.archive, &opts)? {
+                total = total.zip(entry?.size).map(|(total, size)| total + size);
+                if total.is_none() {
+                    break;
+                }
+            }
+            opts.to_mut().on_entry = Some(Arc::new(move |info: &EntryInfo| {
+                on_entry(&EntryInfo {
+                    total_expected: total,
+                    ..info.clone()
+                });
+            }));
+        }
+        let mut res = self.dec.decompress(&self.archive, &to, &opts)?;
+        if let Some((manifest, _)) = &opts.write_manifest {
+            write_manifest(manifest, root, &res.file_infos)?;
+        }
+        if let Some((store, _)) = &opts.content_addressed {
+            store_content_addressed(store, root, &mut res)?;
+        }
+        Ok(res)
+    }
+
+    /// Extract a single entry, see `extract_entries`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened, or
+    /// `DecompressError::MissingEntries` if the archive has no such entry
+    pub fn extract_entry<P: AsRef<Path>>(
+        &self,
+        name: &str,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Vec<PathBuf>, DecompressError> {
+        self.extract_entries(&HashSet::from([name.to_string()]), to, opts)
+    }
+
+    /// Extract just the entries named in `names`, as `Decompress::extract_entries` does
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO or parsing error happened, or
+    /// `DecompressError::MissingEntries` with the names that aren't in the archive
+    pub fn extract_entries<P: AsRef<Path>>(
+        &self,
+        names: &HashSet<String>,
+        to: P,
+        opts: &ExtractOpts,
+    ) -> Result<Vec<PathBuf>, DecompressError> {
+        let normalize = |path: &Path| RelPath::new(path).parts().join("/");
+        let found = Arc::new(Mutex::new(HashSet::new()));
+
+        let mut opts = opts.clone();
+        {
+            let wanted = names
+                .iter()
+                .map(|name| normalize(Path::new(name)))
+                .collect::<HashSet<_>>();
+            let filter = Arc::clone(&opts.filter);
+            let root = wrapped(to.as_ref(), &opts).into_owned();
+            let found = Arc::clone(&found);
+            opts.filter = Arc::new(move |path| {
+                let name = normalize(path.strip_prefix(&root).unwrap_or(path));
+                if !wanted.contains(&name) || !filter(path) {
+                    return false;
+                }
+                found
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(name);
+                true
+            });
+        }
+        let res = self.extract(to, &opts)?;
+
+        let found = found.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut missing = names
+            .iter()
+            .map(|name| normalize(Path::new(name)))
+            .filter(|name| !found.contains(name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(DecompressError::MissingEntries(missing));
+        }
+        Ok(res
+            .file_infos
+            .into_iter()
+            .map(|file| PathBuf::from(file.path))
+            .collect())
+    }
+}
+
+/// Identify the decompressor for an archive by its content, falling back to its
+/// file name, and tell which of the two matched
+///
+/// # Errors
+/// May fail if cannot read the file, or if no decompressor matches
+pub fn identify<P: AsRef<Path>>(archive: P) -> Result<Identification, DecompressError> {
+    Decompress::default().identify(archive)
 }
 
 /// Decompress an archive with default decompressor set up
@@ -292,3 +2391,16 @@ pub fn can_decompress<P: AsRef<Path>>(archive: P) -> bool {
 pub fn can_decompress_content<P: AsRef<Path>>(archive: P) -> Result<bool, DecompressError> {
     Decompress::default().can_decompress_content(archive)
 }
+
+/// Returns the id of the decompressor that would handle this specific archive
+/// based on its content (reads first 8kb) with `detect_content`, and on its path
+/// otherwise, or `None` if unsupported
+///
+/// # Errors
+/// May fail if cannot read the file
+pub fn detect<P: AsRef<Path>>(
+    archive: P,
+    detect_content: bool,
+) -> Result<Option<&'static str>, DecompressError> {
+    Decompress::default().detect(archive, detect_content)
+}
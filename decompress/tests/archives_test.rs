@@ -1,6 +1,18 @@
-use std::{fs, path::Path};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
 
-use decompress::{decompressors, Decompress, DecompressError, Decompression, ExtractOptsBuilder};
+use decompress::{
+    decompressors, AbsoluteSymlinkPolicy, ArchiveNode, CaseCollisionPolicy, ConflictResolution,
+    Decompress, DecompressError, Decompression, DetectionMethod, EntrySink, ExtractOptsBuilder,
+    HashAlgo, RelPath, RelPathKind, RenameRule, SymlinkPolicy,
+};
 use dircmp::Comparison;
 use insta::assert_debug_snapshot;
 use regex::Regex;
@@ -26,14 +38,30 @@ use rstest::rstest;
 #[case("inner.tar.gz", "inner_tgz_1", 1, "targz")]
 #[case("inner.tar.xz", "inner_txz_0", 0, "tarxz")]
 #[case("inner.tar.xz", "inner_txz_1", 1, "tarxz")]
+#[case("multistream.tar.xz", "txz_multistream", 0, "tarxz")]
 #[case("inner.tar.zst", "inner_zst_1", 1, "tarzst")]
 #[case("inner.tar.bz2", "inner_bz2_1", 1, "tarbz")]
 #[case("bare.ar", "bare_ar", 0, "ar")]
+#[case("hello.deb", "deb_0", 0, "deb")]
+#[case("hello.deb", "deb_2", 2, "deb")]
+#[case("site.warc", "warc_0", 0, "warc")]
+#[case("site.warc", "warc_1", 1, "warc")]
+#[case("site.warc.gz", "warcgz_0", 0, "warcgz")]
 #[case("sub.txt.gz", "gz_1", 0, "gz")]
+#[case("multimember.txt.gz", "gz_multimember", 0, "gz")]
 #[case("sub.txt.bz2", "bz_2", 0, "bz2")]
+#[case("multistream.txt.bz2", "bz_multistream", 0, "bz2")]
 #[case("sub.txt.xz", "xz_1", 0, "xz")]
+#[case("multistream.txt.xz", "xz_multistream", 0, "xz")]
 #[case("sub.txt.zst", "zstd_1", 0, "zst")]
 #[case("version.rar", "rar_1", 0, "rar")]
+#[case("sparse-gnu.tar", "sparse_gnu", 0, "tarball")]
+#[case("sparse-pax.tar", "sparse_pax", 0, "tarball")]
+#[case("pax-global.tar", "pax_global", 0, "tarball")]
+#[case("longname-gnu.tar", "longname_gnu", 0, "tarball")]
+#[case("longname-pax.tar", "longname_pax", 0, "tarball")]
+#[case("folders.lzh", "lha_folders", 0, "lha")]
+#[case("level0.lzh", "lha_level0", 0, "lha")]
 #[trace]
 fn test_archives(
     #[case] archive: &str,
@@ -60,6 +88,21 @@ fn test_archives(
     assert_eq!(res.id, id);
 }
 
+#[rstest]
+#[case("longname-gnu.tar")]
+#[case("longname-pax.tar")]
+fn test_list_long_names(#[case] archive: &str) {
+    let listing = decompress::list(
+        format!("tests/fixtures/{archive}"),
+        &ExtractOptsBuilder::default().build().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        listing.entries,
+        vec!["long-names/nested-folder-with-a-long-name/a-file-name-that-keeps-going-well-past-the-hundred-characters-of-a-tar-header.txt"]
+    );
+}
+
 #[rstest]
 #[case("bare_ar", "content_bare_ar", "ar")]
 #[case("bare_tar_gz", "content_bare_tar_gz", "targz")]
@@ -73,92 +116,3211 @@ fn test_archives_content(#[case] archive: &str, #[case] outdir: &str, #[case] id
         .build()
         .unwrap();
 
-    let res = assertion(archive, outdir, |from, to| {
-        Decompress::default().decompress(from, to, &extract_opts)
-    })
-    .unwrap();
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+}
+
+#[rstest]
+#[case("dict.txt.zst", "zstd_dict", "zst")]
+#[case("dict.tar.zst", "tarzst_dict", "tarzst")]
+fn test_zstd_dictionary(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let dictionary = fs::read("tests/fixtures/dict.zstdict").unwrap();
+    let extract_opts = ExtractOptsBuilder::default()
+        .zstd_dictionary(Some(dictionary))
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+    assert_eq!(res.id, id);
+
+    let res = Decompress::default().decompress(
+        format!("tests/fixtures/{archive}"),
+        format!("tests/out/{outdir}_missing"),
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    match res {
+        Err(DecompressError::CorruptArchive(msg)) => assert!(msg.contains("dictionary")),
+        _ => panic!("should have failed without a dictionary"),
+    }
+}
+
+#[rstest]
+#[case("bundle.tar.zst", "bundle")]
+#[case("data.gz", "data")]
+#[case("site.warc.gz", "site")]
+#[case("Backup.TGZ", "Backup")]
+#[case("notes.txt", "notes.txt")]
+#[case("v1.2.zip", "v1.2")]
+#[case(".zip", ".zip")]
+fn test_strip_archive_extensions(#[case] name: &str, #[case] stripped: &str) {
+    assert_eq!(decompress::strip_archive_extensions(name), stripped);
+}
+
+#[test]
+fn test_strip_extensions() {
+    let dec = Decompress::default();
+    assert_eq!(dec.strip_extensions("bundle.tar.zst"), "bundle");
+    assert_eq!(dec.strip_extensions("data.json.gz"), "data.json");
+    assert_eq!(dec.strip_extensions("v1.2"), "v1.2");
+
+    assert_eq!(
+        Decompress::only(&["gz"]).strip_extensions("bundle.tar.gz"),
+        "bundle.tar"
+    );
+    let dec = Decompress::build(vec![decompressors::targz::Targz::build_extended(
+        Regex::new(r"(?i)\.tzz$").unwrap(),
+    )]);
+    assert_eq!(dec.strip_extensions("bundle.tzz"), "bundle");
+    let dec = Decompress::default().with(decompressors::passthrough::Passthrough::build());
+    assert_eq!(dec.strip_extensions("notes.txt"), "notes.txt");
+}
+
+#[rstest]
+#[case("bare.tar.gz", "named_tgz", "bare")]
+#[case("bare.zip", "named_zip", "bare")]
+#[case("inner.tar.zst", "named_tzst", "inner")]
+#[case("sub.txt.gz", "named_gz", "sub.txt")]
+fn test_decompress_into_named(#[case] archive: &str, #[case] outdir: &str, #[case] name: &str) {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress_into_named(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.to, Path::new(&format!("tests/out/{outdir}")).join(name));
+}
+
+#[cfg(feature = "temp")]
+#[test]
+fn test_decompress_temp() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let (tmp, res) = Decompress::default()
+        .decompress_temp("tests/fixtures/inner.tar.gz", &extract_opts)
+        .unwrap();
+    assert_eq!(res.id, "targz");
+    assert_eq!(res.to, tmp.path());
+    let result = Comparison::default()
+        .compare(tmp.path(), Path::new("tests/expected/inner_tgz_0"))
+        .unwrap();
+    assert!(result.is_empty());
+
+    let dir = tmp.path().to_path_buf();
+    drop(tmp);
+    assert!(!dir.exists());
+}
+
+#[rstest]
+#[case("folders.tar.gz", "folders_tgz_0")]
+#[case("folders.zip", "folders_zip_0")]
+#[case("folders.lzh", "lha_folders")]
+#[case("bare.ar", "bare_ar")]
+fn test_buffer_threshold(
+    #[case] archive: &str,
+    #[case] expected: &str,
+    #[values(0, 20, 1 << 20)] threshold: u64,
+) {
+    let out = format!("tests/out/buffered_{threshold}_{expected}");
+    let extract_opts = ExtractOptsBuilder::default()
+        .buffer_threshold(Some(threshold))
+        .build()
+        .unwrap();
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &extract_opts,
+        )
+        .unwrap();
+    let result = Comparison::default()
+        .compare(
+            Path::new(&out),
+            Path::new(&format!("tests/expected/{expected}")),
+        )
+        .unwrap();
+    assert!(result.is_empty());
+    for file in res.file_infos {
+        assert_eq!(fs::metadata(&file.path).unwrap().len(), file.size);
+    }
+}
+
+#[rstest]
+#[case("bare.tar.gz", "reader_tgz", "targz")]
+#[case("sub.txt.gz", "reader_gz", "gz")]
+fn test_decompress_reader(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress_reader(
+            fs::File::open(from).unwrap(),
+            from,
+            to,
+            &extract_opts,
+        )
+    })
+    .unwrap();
+    assert_eq!(res.id, id);
+
+    let res = Decompress::default().decompress_reader(
+        fs::File::open("tests/fixtures/bare.zip").unwrap(),
+        "bare.zip",
+        "tests/out/reader_zip",
+        &extract_opts,
+    );
+    assert!(matches!(res, Err(DecompressError::SeekRequired("zip"))));
+}
+
+#[cfg(feature = "http")]
+#[rstest]
+#[case(
+    "bare.tar.gz",
+    "bare.tar.gz",
+    "application/octet-stream",
+    "url_tgz",
+    "targz"
+)]
+#[case("bare.zip", "download", "application/zip", "url_zip", "zip")]
+fn test_decompress_url(
+    #[case] archive: &str,
+    #[case] url_name: &str,
+    #[case] content_type: &'static str,
+    #[case] outdir: &str,
+    #[case] id: &str,
+) {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/{url_name}", listener.local_addr().unwrap());
+    let body = fs::read(format!("tests/fixtures/{archive}")).unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    });
+
+    let res = assertion(archive, outdir, |_, to| {
+        Decompress::default().decompress_url(
+            &url,
+            Path::new(to),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+    })
+    .unwrap();
+    assert_eq!(res.id, id);
+    server.join().unwrap();
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_decompress_url_feature_disabled() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/version.rar", listener.local_addr().unwrap());
+    let body = fs::read("tests/fixtures/version.rar").unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    });
+
+    let res = Decompress::build(vec![decompressors::zip::Zip::build(None)])
+        .with_disabled("rar", Regex::new(r"(?i)\.rar$").unwrap())
+        .decompress_url(
+            &url,
+            Path::new("tests/out/url_disabled_rar"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        );
+    assert!(matches!(res, Err(DecompressError::FeatureDisabled("rar"))));
+    server.join().unwrap();
+}
+
+#[rstest]
+#[case("common.tar.gz", "common_tgz", 0)]
+#[case("common.tar.gz", "common_strip_tgz", 1)]
+#[case("inner.zip", "common_zip", 0)]
+#[case("folders.tar.gz", "common_none_tgz", 0)]
+fn test_strip_common_prefix(#[case] archive: &str, #[case] outdir: &str, #[case] strip: usize) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .strip_common_prefix(true)
+        .strip(strip)
+        .build()
+        .unwrap();
+
+    assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_only() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+
+    let dec = Decompress::zip_only();
+    assert!(dec.can_decompress("tests/fixtures/bare.zip"));
+    assert!(!dec.can_decompress("tests/fixtures/bare.tar.gz"));
+
+    let dec = Decompress::tar_family_only();
+    assert!(dec.can_decompress("tests/fixtures/bare.tar.gz"));
+    assert!(dec.can_decompress("tests/fixtures/inner.tar.zst"));
+    assert!(!dec.can_decompress("tests/fixtures/bare.zip"));
+    assert!(!dec.can_decompress("tests/fixtures/sub.txt.gz"));
+
+    let res = assertion("bare.zip", "only_zip", |from, to| {
+        Decompress::only(&["zip", "gz"]).decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+    assert_eq!(res.id, "zip");
+}
+
+#[test]
+fn test_filename_encoding() {
+    let extract_opts = ExtractOptsBuilder::default()
+        .filename_encoding(Some(decompress::encoding_rs::SHIFT_JIS))
+        .build()
+        .unwrap();
+
+    let listing = Decompress::default()
+        .list("tests/fixtures/sjis.zip", &extract_opts)
+        .unwrap();
+    assert_eq!(listing.entries, vec!["日本語.txt"]);
+
+    assertion("sjis.zip", "sjis_zip", |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    // without an encoding, legacy names are read as CP437
+    let listing = Decompress::default()
+        .list(
+            "tests/fixtures/sjis.zip",
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_ne!(listing.entries, vec!["日本語.txt"]);
+}
+
+#[test]
+fn test_lossy_names() {
+    let lossy = |archive: &str, opts| {
+        Decompress::default()
+            .list_iter(format!("tests/fixtures/{archive}"), &opts)
+            .unwrap()
+            .map(|entry| entry.unwrap().is_name_lossy)
+            .collect::<Vec<_>>()
+    };
+    let sjis = ExtractOptsBuilder::default()
+        .filename_encoding(Some(decompress::encoding_rs::SHIFT_JIS))
+        .build()
+        .unwrap();
+    let plain = ExtractOptsBuilder::default().build().unwrap();
+
+    assert_eq!(lossy("sjis.zip", plain.clone()), vec![true]);
+    assert_eq!(lossy("sjis.zip", sjis.clone()), vec![false]);
+    // ustar paths are bytes, which unix keeps as they are
+    #[cfg(unix)]
+    assert_eq!(lossy("latin1.tar", plain.clone()), vec![true, false]);
+
+    let res = Decompress::default()
+        .decompress(
+            "tests/fixtures/sjis.zip",
+            "tests/out/lossy_sjis_zip",
+            &plain,
+        )
+        .unwrap();
+    assert_eq!(res.warnings.len(), 1);
+    let res = Decompress::default()
+        .decompress("tests/fixtures/sjis.zip", "tests/out/lossy_sjis_zip", &sjis)
+        .unwrap();
+    assert!(res.warnings.is_empty());
+}
+
+#[test]
+fn test_lha() {
+    let extract_opts = ExtractOptsBuilder::default()
+        .filename_encoding(Some(decompress::encoding_rs::SHIFT_JIS))
+        .build()
+        .unwrap();
+    let listing = Decompress::default()
+        .list("tests/fixtures/sjis.lzh", &extract_opts)
+        .unwrap();
+    assert_eq!(listing.id, "lha");
+    assert_eq!(listing.entries, vec!["フォルダ/日本語.txt"]);
+
+    // level 1 and 2 headers carry a unix time, level 0 a DOS time for the same moment
+    for (archive, outdir) in [("sjis.lzh", "lha_sjis"), ("level0.lzh", "lha_level0")] {
+        let res = assertion(archive, outdir, |from, to| {
+            Decompress::default().decompress(from, to, &extract_opts)
+        })
+        .unwrap();
+        assert!(res
+            .file_infos
+            .iter()
+            .all(|file| file.mtime
+                == Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_642_249_800))));
+    }
+
+    Decompress::default()
+        .verify("tests/fixtures/folders.lzh", &extract_opts)
+        .unwrap();
+    let mut corrupted = fs::read("tests/fixtures/level0.lzh").unwrap();
+    let last = corrupted.len() - 2;
+    corrupted[last] ^= 0xff;
+    fs::create_dir_all("tests/out").unwrap();
+    fs::write("tests/out/corrupted.lzh", corrupted).unwrap();
+    let res = Decompress::default().verify("tests/out/corrupted.lzh", &extract_opts);
+    assert!(matches!(res, Err(DecompressError::CorruptArchive(_))));
+}
+
+#[rstest]
+#[case("x.tar")]
+#[case("x.tar.gz")]
+#[case("x.tar.bz2")]
+#[case("x.tar.xz")]
+#[case("x.tar.zst")]
+#[case("x.zip")]
+#[case("x.ar")]
+#[case("x.deb")]
+#[case("x.gz")]
+#[case("x.bz2")]
+#[case("x.xz")]
+#[case("x.zst")]
+#[case("x.rar")]
+#[case("x.warc")]
+#[case("x.warc.gz")]
+#[case("x.lzh")]
+fn test_truncated_input(#[case] archive: &str, #[values(0, 1)] len: usize) {
+    // a legitimately empty tar, warc or lha is zero bytes long, everything else is corrupt
+    let out = Path::new("tests/out/truncated").join(format!("{len}"));
+    fs::create_dir_all(&out).unwrap();
+    let from = out.join(archive);
+    fs::write(&from, &b"\x1f"[..len]).unwrap();
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let dec = Decompress::default();
+    let to = out.join(format!("{archive}.out"));
+    for res in [
+        dec.decompress(&from, &to, &opts).map(|_| ()),
+        dec.list(&from, &opts).map(|_| ()),
+        dec.verify(&from, &opts),
+    ] {
+        // classified once, where the input was read
+        let classified = match &res {
+            Ok(()) => true,
+            Err(DecompressError::CorruptArchive(msg)) => !msg.contains("corrupt archive"),
+            Err(_) => false,
+        };
+        assert!(classified, "{archive} ({len} bytes): {res:?}");
+    }
+}
+
+#[test]
+fn test_writer_errors_are_io() {
+    struct Failing;
+    impl Write for Failing {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "writer is full",
+            ))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let res = Decompress::default().decompress_to_writer(
+        "tests/fixtures/sub.txt.gz",
+        &mut Failing,
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    match res {
+        Err(DecompressError::IO(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        res => panic!("expected an IO error, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_normalize_separators() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let listing = Decompress::default()
+        .list("tests/fixtures/backslash.zip", &extract_opts)
+        .unwrap();
+    assert_eq!(listing.entries, vec!["win/", "win/sub/a.txt", "win/b.txt"]);
+
+    assertion("backslash.zip", "backslash_zip", |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    let listing = Decompress::default()
+        .list(
+            "tests/fixtures/backslash.zip",
+            &ExtractOptsBuilder::default()
+                .normalize_separators(false)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(listing.entries[1], "win\\sub\\a.txt");
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_list_mapped(#[case] archive: &str) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .strip(1)
+        .wrap_in(Some("pkg".to_string()))
+        .map_opt(|path| (!path.ends_with("ex.sh")).then(|| path.with_extension("md").into()))
+        .build()
+        .unwrap();
+    let listing = Decompress::default()
+        .list_mapped(format!("tests/fixtures/{archive}"), &extract_opts)
+        .unwrap();
+    assert_eq!(listing.entries, vec!["pkg/sub.md"]);
+
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/list_mapped_{archive}"),
+            &extract_opts,
+        )
+        .unwrap();
+    assert_eq!(
+        res.files,
+        vec![format!("tests/out/list_mapped_{archive}/pkg/sub.md")]
+    );
+}
+
+#[test]
+fn test_rename_rules_long_names() {
+    // every `**` could start at any of the 200 characters, matching mustn't try them all
+    let listing = Decompress::default()
+        .list_mapped(
+            "tests/fixtures/long-name.tar",
+            &ExtractOptsBuilder::default()
+                .rename_rules(vec![RenameRule {
+                    matches: Some("**a**a**a**a**a**a**a**ab".to_string()),
+                    suffix: Some(".bak".to_string()),
+                    ..RenameRule::default()
+                }])
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+        .entries;
+    assert_eq!(
+        listing,
+        vec!["a".repeat(200), format!("{}b.bak", "a".repeat(199))]
+    );
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_rename_rules(#[case] archive: &str) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .rename_rules(vec![
+            RenameRule {
+                replace_prefix: Some(("folder-1".to_string(), "app".to_string())),
+                ..RenameRule::default()
+            },
+            RenameRule {
+                matches: Some("**/*.sh".to_string()),
+                suffix: Some(".bak".to_string()),
+                ..RenameRule::default()
+            },
+            RenameRule {
+                matches: Some("*.txt".to_string()),
+                suffix: Some(".old".to_string()),
+                ..RenameRule::default()
+            },
+        ])
+        .build()
+        .unwrap();
+    let mut listing = Decompress::default()
+        .list_mapped(format!("tests/fixtures/{archive}"), &extract_opts)
+        .unwrap()
+        .entries;
+    listing.retain(|name| !name.ends_with('/'));
+    listing.sort();
+    assert_eq!(
+        listing,
+        vec!["app/ex.sh.bak", "app/sub.txt", "root.txt.old"]
+    );
+
+    let out = format!("tests/out/rename_rules_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &extract_opts,
+        )
+        .unwrap();
+    assert!(Path::new(&out).join("app/ex.sh.bak").is_file());
+    assert!(Path::new(&out).join("app/sub.txt").is_file());
+    assert!(Path::new(&out).join("root.txt.old").is_file());
+    assert!(!Path::new(&out).join("folder-1").exists());
+
+    let err = ExtractOptsBuilder::default()
+        .rename_rules(vec![RenameRule {
+            replace_prefix: Some(("folder-1".to_string(), "../app".to_string())),
+            ..RenameRule::default()
+        }])
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("`rename_rules`"));
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_write_manifest(#[case] archive: &str) {
+    let out = format!("tests/out/manifest_{archive}");
+    let manifest = format!("tests/out/manifest_{archive}.sha256");
+    let _ = fs::remove_dir_all(&out);
+    let decompress = |algo| {
+        Decompress::default()
+            .decompress(
+                format!("tests/fixtures/{archive}"),
+                out.clone(),
+                &ExtractOptsBuilder::default()
+                    .wrap_in(Some("pkg".to_string()))
+                    .write_manifest(Some((manifest.clone().into(), algo)))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        fs::read_to_string(&manifest).unwrap()
+    };
+
+    assert_eq!(
+        decompress(HashAlgo::Sha256),
+        "446f2cc01bfd7369511694d055938e071a0ef704264ec097d328b72f63631a72  pkg/folder-1/ex.sh\n\
+         f6874e4cf6186ea34e4799f4308a6d646c23bff625350491152d6e6d19274dbf  pkg/folder-1/sub.txt\n\
+         0ea35aee04e6a8a3fb08c7d3ccbe4616d7c69260c5ec6b781a32afba69ef0bcb  pkg/root.txt\n"
+    );
+    for (algo, len) in [(HashAlgo::Sha1, 40), (HashAlgo::Blake3, 64)] {
+        let lines = decompress(algo);
+        let hashes = lines
+            .lines()
+            .map(|line| line.split_once("  ").unwrap().0.len())
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, vec![len; 3]);
+    }
+}
+
+#[test]
+fn test_content_addressed() {
+    let store = Path::new("tests/out/content_addressed_store");
+    let _ = fs::remove_dir_all(store);
+    let decompress = |archive: &str, out: &str| {
+        let out = format!("tests/out/{out}");
+        let _ = fs::remove_dir_all(&out);
+        let res = Decompress::default()
+            .decompress(
+                format!("tests/fixtures/{archive}"),
+                out.clone(),
+                &ExtractOptsBuilder::default()
+                    .content_addressed(Some((store.into(), HashAlgo::Sha256)))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        (out, res)
+    };
+
+    let sub = "f6874e4cf6186ea34e4799f4308a6d646c23bff625350491152d6e6d19274dbf";
+    let (out, res) = decompress("folders.zip", "content_addressed_zip");
+    assert_eq!(res.content_index.len(), 3);
+    assert!(res
+        .content_index
+        .contains(&("folder-1/sub.txt".to_string(), sub.to_string())));
+    assert!(!Path::new(&out).join("folder-1/sub.txt").exists());
+    assert_eq!(
+        fs::read(store.join(&sub[..2]).join(sub)).unwrap(),
+        fs::read("tests/expected/folders_zip_0/folder-1/sub.txt").unwrap()
+    );
+    assert!(res
+        .files
+        .iter()
+        .all(|file| file.starts_with("tests/out/content_addressed_store/")));
+
+    // the same files again are only indexed
+    let (_, again) = decompress("folders.tar.gz", "content_addressed_tgz");
+    let mut indexed = again.content_index.clone();
+    indexed.sort();
+    let mut first = res.content_index.clone();
+    first.sort();
+    assert_eq!(indexed, first);
+    assert_eq!(
+        fs::read_dir(store)
+            .unwrap()
+            .map(|dir| fs::read_dir(dir.unwrap().path()).unwrap().count())
+            .sum::<usize>(),
+        3
+    );
+
+    let err = ExtractOptsBuilder::default()
+        .write_manifest(Some(("manifest".into(), HashAlgo::Sha1)))
+        .content_addressed(Some((store.into(), HashAlgo::Sha256)))
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("`content_addressed`"));
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_io_retries(#[case] archive: &str) {
+    let out = format!("tests/out/io_retries_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    let decompress = || {
+        Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default().io_retries(3).build().unwrap(),
+        )
+    };
+    assert_eq!(decompress().unwrap().files.len(), 3);
+
+    // a folder where a file goes isn't going away by retrying
+    fs::remove_file(format!("{out}/root.txt")).unwrap();
+    fs::create_dir(format!("{out}/root.txt")).unwrap();
+    match decompress() {
+        Err(DecompressError::IO(err)) => assert!(!matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )),
+        res => panic!("expected an IO error, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_unwrap_single() {
+    let out = "tests/out/unwrap_single";
+    let _ = fs::remove_dir_all(out);
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let res = Decompress::default()
+        .unwrap_single("tests/fixtures/wrapped.zip", out, &opts)
+        .unwrap();
+    assert_eq!(res.format_chain, vec!["zip", "targz"]);
+    assert_eq!(res.id, "targz");
+    assert!(!Path::new(out).join("inner.tar.gz").exists());
+    assert!(Comparison::default()
+        .compare(Path::new(out), Path::new("tests/expected/inner_tgz_0"))
+        .unwrap()
+        .is_empty());
+
+    // nothing more to unwrap in a folder of several files
+    let res = Decompress::default()
+        .unwrap_single(
+            "tests/fixtures/folders.zip",
+            "tests/out/unwrap_single_folders",
+            &opts,
+        )
+        .unwrap();
+    assert_eq!(res.format_chain, vec!["zip"]);
+
+    // a level written back onto the file it came from ends the chain
+    let out = "tests/out/unwrap_single_same";
+    let _ = fs::remove_dir_all(out);
+    let named = ExtractOptsBuilder::default()
+        .use_gzip_original_name(true)
+        .build()
+        .unwrap();
+    let res = Decompress::default()
+        .unwrap_single("tests/fixtures/same.gz", out, &named)
+        .unwrap();
+    assert_eq!(res.format_chain, vec!["gz", "gz"]);
+    assert!(Path::new(out).join("same.gz").exists());
+
+    // a chain that goes on and on is cut off
+    let res = Decompress::default().unwrap_single(
+        "tests/fixtures/nested-deep.gz",
+        "tests/out/unwrap_single_deep",
+        &named,
+    );
+    assert!(matches!(
+        res,
+        Err(DecompressError::TooManyLevels { limit: 16 })
+    ));
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_structure_only(#[case] archive: &str) {
+    let out = format!("tests/out/structure_only_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .structure_only(true)
+                .preserve_mtime(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files.len(), 3);
+    assert!(res.file_infos.iter().all(|file| file.size == 0));
+    for file in ["folder-1/ex.sh", "folder-1/sub.txt", "root.txt"] {
+        let meta = fs::metadata(Path::new(&out).join(file)).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.len(), 0);
+        assert!(
+            meta.modified().unwrap() < SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let meta = fs::metadata(Path::new(&out).join("folder-1/ex.sh")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o755);
+    }
+}
+
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn test_preserve_hardlinks(#[case] preserve: bool) {
+    let out = format!("tests/out/hardlinks_{preserve}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            "tests/fixtures/hardlinks.tar".to_string(),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .preserve_hardlinks(preserve)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let data = fs::read(Path::new(&out).join("layer/data.txt")).unwrap();
+    assert_eq!(data.len(), 124);
+    assert_eq!(
+        fs::read(Path::new(&out).join("layer/copy.txt")).unwrap(),
+        data
+    );
+    assert!(res.file_infos.iter().all(|file| file.size == 124));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let data = fs::metadata(Path::new(&out).join("layer/data.txt")).unwrap();
+        let copy = fs::metadata(Path::new(&out).join("layer/copy.txt")).unwrap();
+        assert_eq!(data.ino() == copy.ino(), preserve);
+    }
+}
+
+struct CapturingLogger(std::sync::Mutex<Vec<(String, String)>>);
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.target().to_string(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(std::sync::Mutex::new(vec![]));
+
+#[rstest]
+#[case("folders.tar.gz", "decompress::targz")]
+#[case("folders.zip", "decompress::zip")]
+fn test_log_decisions(#[case] archive: &str, #[case] target: &str) {
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+    let out = format!("tests/out/log_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out,
+            &ExtractOptsBuilder::default()
+                .filter(|path| !path.ends_with("root.txt"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let messages = LOGGER
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(t, _)| t == target)
+        .map(|(_, msg)| msg.clone())
+        .collect::<Vec<_>>();
+    assert!(messages.iter().any(|msg| msg == "filtered out root.txt"));
+    assert!(messages
+        .iter()
+        .any(|msg| msg.starts_with("extracted folder-1/ex.sh to ")));
+    assert!(messages
+        .iter()
+        .any(|msg| msg == "considering folder-1/sub.txt (File)"));
+}
+
+#[rstest]
+#[case("inner.tar.gz", "resume_tgz")]
+#[case("inner.zip", "resume_zip")]
+fn test_resume_manifest(#[case] archive: &str, #[case] outdir: &str) {
+    let out = format!("tests/out/{outdir}");
+    let manifest = format!("tests/out/{outdir}.manifest");
+    let _ = fs::remove_dir_all(&out);
+    let _ = fs::remove_file(&manifest);
+
+    // a first run that only got as far as one file
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .resume_manifest(Some(manifest.clone().into()))
+                .filter(|path| path.ends_with("ex.sh"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files.len(), 1);
+    assert_eq!(fs::read_to_string(&manifest).unwrap(), "folder-1/ex.sh\n");
+
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .resume_manifest(Some(manifest.clone().into()))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files, vec![format!("{out}/folder-1/sub.txt")]);
+    assert!(fs::read_to_string(&manifest)
+        .unwrap()
+        .lines()
+        .any(|line| line == "folder-1/sub.txt"));
+}
+
+#[test]
+fn test_resume_manifest_rar() {
+    let out = "tests/out/resume_rar";
+    let manifest = "tests/out/resume_rar.manifest";
+    let _ = fs::remove_file(manifest);
+    let extract_opts = ExtractOptsBuilder::default()
+        .resume_manifest(Some(manifest.into()))
+        .build()
+        .unwrap();
+
+    let res = Decompress::default()
+        .decompress("tests/fixtures/version.rar", out, &extract_opts)
+        .unwrap();
+    assert!(!res.files.is_empty());
+    let res = Decompress::default()
+        .decompress("tests/fixtures/version.rar", out, &extract_opts)
+        .unwrap();
+    assert!(res.files.is_empty());
+}
+
+#[rstest]
+#[case("inner.tar.gz", "max_entries_tgz")]
+#[case("inner.zip", "max_entries_zip")]
+fn test_max_entries(#[case] archive: &str, #[case] outdir: &str) {
+    let decompress = |max_entries, only_sh| {
+        let mut builder = ExtractOptsBuilder::default().max_entries(Some(max_entries));
+        if only_sh {
+            builder = builder.filter(|path| path.extension().map_or(false, |ext| ext == "sh"));
+        }
+        Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &builder.build().unwrap(),
+        )
+    };
+
+    assert!(matches!(
+        decompress(2, false),
+        Err(DecompressError::TooManyEntries { limit: 2 })
+    ));
+    assert!(decompress(3, false).is_ok());
+    // skipped entries don't count
+    assert!(decompress(1, true).is_ok());
+}
+
+#[test]
+fn test_max_entries_rar() {
+    let res = Decompress::default().decompress(
+        "tests/fixtures/version.rar",
+        "tests/out/max_entries_rar",
+        &ExtractOptsBuilder::default()
+            .max_entries(Some(0))
+            .build()
+            .unwrap(),
+    );
+    assert!(matches!(
+        res,
+        Err(DecompressError::TooManyEntries { limit: 0 })
+    ));
+}
+
+#[rstest]
+#[case("zeros.zip", "ratio_zip", "zeros.bin", 1000.0)]
+#[case("folders.lzh", "ratio_lha", "repeat.txt", 60.0)]
+fn test_max_compression_ratio(
+    #[case] archive: &str,
+    #[case] outdir: &str,
+    #[case] entry: &str,
+    #[case] ratio: f64,
+) {
+    let out = format!("tests/out/{outdir}");
+    let _ = fs::remove_dir_all(&out);
+    let decompress = |limit| {
+        Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .max_compression_ratio(Some(limit))
+                .build()
+                .unwrap(),
+        )
+    };
+
+    match decompress(ratio) {
+        Err(DecompressError::SuspiciousRatio {
+            entry: name,
+            ratio: found,
+        }) => {
+            assert_eq!(name, entry);
+            assert!(found > ratio);
+        }
+        res => panic!("expected SuspiciousRatio, got {res:?}"),
+    }
+    assert!(!Path::new(&out).join(entry).exists());
+    assert!(decompress(ratio * 2.0).is_ok());
+
+    assert!(ExtractOptsBuilder::default()
+        .max_compression_ratio(Some(0.0))
+        .build()
+        .is_err());
+}
+
+#[rstest]
+#[case("inner.tar.gz")]
+#[case("inner.zip")]
+#[case("bare.ar")]
+fn test_peek(#[case] archive: &str) {
+    let target = format!("tests/fixtures/{archive}");
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let decompress = Decompress::default();
+    let entries = decompress.list(&target, &opts).unwrap().entries;
+    assert_eq!(decompress.peek(&target, 1, &opts).unwrap(), entries[..1]);
+    assert_eq!(decompress.peek(&target, 100, &opts).unwrap(), entries);
+    assert!(decompress.peek(&target, 0, &opts).unwrap().is_empty());
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_top_level_summary(#[case] archive: &str) {
+    let summary = Decompress::default()
+        .top_level_summary(
+            format!("tests/fixtures/{archive}"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    let size = |file: &str| {
+        fs::metadata(format!("tests/expected/folders_zip_0/{file}"))
+            .unwrap()
+            .len()
+    };
+    assert_eq!(
+        summary,
+        vec![
+            ("".to_string(), 1, size("root.txt")),
+            (
+                "folder-1".to_string(),
+                2,
+                size("folder-1/ex.sh") + size("folder-1/sub.txt")
+            ),
+        ]
+    );
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_tree(#[case] archive: &str) {
+    let file = |name: &str, size| ArchiveNode::File {
+        name: name.to_string(),
+        size: Some(size),
+    };
+    let dir = |name: &str, children| ArchiveNode::Dir {
+        name: name.to_string(),
+        children,
+    };
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    assert_eq!(
+        Decompress::default()
+            .tree(format!("tests/fixtures/{archive}"), &opts)
+            .unwrap(),
+        dir(
+            "",
+            vec![
+                dir("folder-1", vec![file("ex.sh", 23), file("sub.txt", 19)]),
+                file("root.txt", 21),
+            ]
+        )
+    );
+
+    // no folder entries at all, they're all implied by the file's path
+    let tree = Decompress::default()
+        .tree("tests/fixtures/longname-pax.tar", &opts)
+        .unwrap();
+    let ArchiveNode::Dir { children, .. } = &tree else {
+        panic!("expected the root folder, got {tree:?}");
+    };
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].name(), "long-names");
+}
+
+#[rstest]
+#[case("inner.tar.gz", "max_path_depth_tgz", 2)]
+#[case("inner.zip", "max_path_depth_zip", 2)]
+#[case("version.rar", "max_path_depth_rar", 1)]
+fn test_max_path_depth(#[case] archive: &str, #[case] outdir: &str, #[case] depth: usize) {
+    let decompress = |max_path_depth| {
+        Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &ExtractOptsBuilder::default()
+                .max_path_depth(Some(max_path_depth))
+                .build()
+                .unwrap(),
+        )
+    };
+
+    let limit = depth - 1;
+    match decompress(limit) {
+        Err(DecompressError::PathTooDeep {
+            limit: got_limit,
+            depth: got_depth,
+        }) => assert_eq!((got_limit, got_depth), (limit, depth)),
+        res => panic!("should have rejected a deep path, got {res:?}"),
+    }
+    assert!(decompress(depth).is_ok());
+}
+
+#[rstest]
+#[case("set.r00", "set.rar", "rar_volumes_old")]
+#[case("set.part02.rar", "set.part01.rar", "rar_volumes_part")]
+fn test_rar_volumes(#[case] volume: &str, #[case] first: &str, #[case] outdir: &str) {
+    // only the first volume is a real archive: reading has to start from it
+    let dir = Path::new("tests/out").join(outdir);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy("tests/fixtures/version.rar", dir.join(first)).unwrap();
+    fs::write(dir.join(volume), b"not a rar volume").unwrap();
+
+    let decompress = Decompress::default();
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    assert!(decompress.can_decompress(dir.join(volume)));
+    let listing = decompress.list(dir.join(volume), &extract_opts).unwrap();
+    assert_eq!(listing.id, "rar");
+    assert_eq!(
+        listing.entries,
+        decompress
+            .list("tests/fixtures/version.rar", &extract_opts)
+            .unwrap()
+            .entries
+    );
+    let res = decompress
+        .decompress(dir.join(volume), dir.join("out"), &extract_opts)
+        .unwrap();
+    assert!(!res.files.is_empty());
+}
+
+#[test]
+fn test_predicate() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let dec = Decompress::build(vec![decompressors::predicate::Predicate::build(
+        decompressors::targz::Targz::build(None),
+        |path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("tar-") && name.ends_with(".tzz")
+                })
+        },
+    )]);
+
+    assert!(!dec.can_decompress("tests/fixtures/bare.tar.gz"));
+    assert!(dec.can_decompress("tests/fixtures/tar-gz.tzz"));
+    let res = dec
+        .decompress(
+            Path::new("tests/fixtures/tar-gz.tzz"),
+            Path::new("tests/out/predicate_tzz"),
+            &extract_opts,
+        )
+        .unwrap();
+    assert_eq!(res.id, "targz");
+    assert!(Comparison::default()
+        .compare(
+            Path::new("tests/out/predicate_tzz"),
+            Path::new("tests/expected/custom_tar_gz_tzz")
+        )
+        .unwrap()
+        .is_empty());
+
+    let mut files = BTreeMap::new();
+    dec.decompress_to_sink("tests/fixtures/tar-gz.tzz", &mut files, &extract_opts)
+        .unwrap();
+    assert!(!files.is_empty());
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_decompress_to_sink(#[case] archive: &str) {
+    struct Kinds(Vec<(String, RelPathKind)>);
+    impl EntrySink for Kinds {
+        fn write_entry(
+            &mut self,
+            path: &RelPath,
+            kind: RelPathKind,
+            _reader: &mut dyn io::Read,
+        ) -> io::Result<()> {
+            self.0.push((path.parts().join("/"), kind));
+            Ok(())
+        }
+    }
+
+    let archive = format!("tests/fixtures/{archive}");
+    let mut files = BTreeMap::new();
+    Decompress::default()
+        .decompress_to_sink(
+            &archive,
+            &mut files,
+            &ExtractOptsBuilder::default()
+                .filter(|path| !path.ends_with("ex.sh"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["folder-1/sub.txt", "root.txt"]
+    );
+    for (name, contents) in &files {
+        assert_eq!(
+            contents,
+            &fs::read(format!("tests/expected/folders_zip_0/{name}")).unwrap()
+        );
+    }
+
+    let mut kinds = Kinds(vec![]);
+    Decompress::default()
+        .decompress_to_sink(
+            &archive,
+            &mut kinds,
+            &ExtractOptsBuilder::default().strip(1).build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        kinds.0,
+        vec![
+            ("ex.sh".to_string(), RelPathKind::File),
+            ("sub.txt".to_string(), RelPathKind::File),
+        ]
+    );
+
+    let res = Decompress::default().decompress_to_sink(
+        "tests/fixtures/bare.ar",
+        &mut files,
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    assert!(matches!(res, Err(DecompressError::SinkUnsupported("ar"))));
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.tar.xz")]
+#[case("folders.zip")]
+fn test_entries(#[case] archive: &str) {
+    let archive = format!("tests/fixtures/{archive}");
+    let mut files = vec![];
+    for entry in Decompress::default()
+        .entries(&archive, &ExtractOptsBuilder::default().build().unwrap())
+        .unwrap()
+    {
+        let (path, mut reader) = entry.unwrap();
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents).unwrap();
+        files.push((path.parts().join("/"), contents));
+    }
+    assert_eq!(
+        files.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        vec!["folder-1/ex.sh", "folder-1/sub.txt", "root.txt"]
+    );
+    for (name, contents) in &files {
+        assert_eq!(
+            contents,
+            &fs::read(format!("tests/expected/folders_zip_0/{name}")).unwrap()
+        );
+    }
+
+    // options apply, and a reader kept past its entry reads nothing more
+    let mut entries = Decompress::default()
+        .entries(
+            &archive,
+            &ExtractOptsBuilder::default()
+                .strip(1)
+                .filter(|path| !path.ends_with("ex.sh"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let (path, mut stale) = entries.next().unwrap().unwrap();
+    assert_eq!(path.parts(), vec!["sub.txt"]);
+    assert!(entries.next().is_none());
+    let mut contents = vec![];
+    stale.read_to_end(&mut contents).unwrap();
+    assert!(contents.is_empty());
+
+    let res = Decompress::default().entries(
+        "tests/fixtures/sub.txt.gz",
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    assert!(matches!(
+        res,
+        Err(DecompressError::EntriesUnsupported("gz"))
+    ));
+}
+
+#[test]
+fn test_build_extended() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let dec = Decompress::build(vec![decompressors::targz::Targz::build_extended(
+        Regex::new(r"(?i)\.tzz$").unwrap(),
+    )]);
+
+    assert!(dec.can_decompress("tests/fixtures/bare.tar.gz"));
+    assert!(dec.can_decompress("tests/fixtures/tar-gz.tzz"));
+    assert!(!dec.can_decompress("tests/fixtures/bare.zip"));
+    let res = dec
+        .decompress(
+            Path::new("tests/fixtures/tar-gz.tzz"),
+            Path::new("tests/out/extended_tzz"),
+            &extract_opts,
+        )
+        .unwrap();
+    assert_eq!(res.id, "targz");
+    assert!(Comparison::default()
+        .compare(
+            Path::new("tests/out/extended_tzz"),
+            Path::new("tests/expected/custom_tar_gz_tzz")
+        )
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_build_multi() {
+    let dec = Decompress::build(vec![decompressors::targz::Targz::build_multi(&[
+        Regex::new(r"(?i)\.tgz$").unwrap(),
+        Regex::new(r"\.tzz$").unwrap(),
+    ])]);
+    assert!(dec.can_decompress("tests/fixtures/bare.TGZ"));
+    assert!(dec.can_decompress("tests/fixtures/tar-gz.tzz"));
+    // the first pattern's (?i) stays with it
+    assert!(!dec.can_decompress("tests/fixtures/tar-gz.TZZ"));
+    // in place of the defaults
+    assert!(!dec.can_decompress("tests/fixtures/bare.tar.gz"));
+    let res = dec
+        .decompress(
+            Path::new("tests/fixtures/tar-gz.tzz"),
+            Path::new("tests/out/multi_tzz"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.id, "targz");
+
+    let dec = Decompress::build(vec![decompressors::targz::Targz::build_multi(&[])]);
+    assert!(!dec.can_decompress("tests/fixtures/tar-gz.tzz"));
+}
+
+#[test]
+fn test_feature_disabled() {
+    let dec = Decompress::build(vec![decompressors::zip::Zip::build(None)])
+        .with_disabled("rar", Regex::new(r"(?i)\.rar$").unwrap());
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let res = dec.decompress(
+        "tests/fixtures/version.rar",
+        "tests/out/disabled_rar",
+        &opts,
+    );
+    match res {
+        Err(err @ DecompressError::FeatureDisabled("rar")) => {
+            assert!(err.to_string().contains("`rar` feature"));
+        }
+        res => panic!("should have needed the rar feature, got {res:?}"),
+    }
+    assert!(!dec.can_decompress("tests/fixtures/version.rar"));
+    assert_eq!(
+        dec.detect("tests/fixtures/version.rar", true).unwrap(),
+        None
+    );
+    assert!(matches!(
+        dec.decompress("tests/fixtures/bare.tar", "tests/out/disabled_tar", &opts),
+        Err(DecompressError::MissingCompressor)
+    ));
+}
+
+#[test]
+fn test_passthrough() {
+    let out = Path::new("tests/out/passthrough");
+    let _ = fs::remove_dir_all(out);
+    let decompress = Decompress::default().with(decompressors::passthrough::Passthrough::build());
+    let extract_opts = ExtractOptsBuilder::default()
+        .map(|path| path.with_file_name("copy.bin").into())
+        .build()
+        .unwrap();
+
+    assert!(!Decompress::default().can_decompress("tests/fixtures/dict.zstdict"));
+    let res = decompress
+        .decompress(Path::new("tests/fixtures/dict.zstdict"), out, &extract_opts)
+        .unwrap();
+    assert_eq!(res.id, "passthrough");
+    assert_eq!(
+        fs::read(out.join("copy.bin")).unwrap(),
+        fs::read("tests/fixtures/dict.zstdict").unwrap()
+    );
+
+    // real formats still come first
+    let res = decompress
+        .list("tests/fixtures/bare.zip", &extract_opts)
+        .unwrap();
+    assert_eq!(res.id, "zip");
+}
+
+#[test]
+fn test_custom() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let dec = Decompress::build(vec![decompressors::targz::Targz::build(Some(
+        Regex::new(r"(?i)\.tzz$").unwrap(),
+    ))]);
+
+    let res = assertion("tar-gz.tzz", "custom_tar_gz_tzz", |from, to| {
+        dec.decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+    assert_eq!(res.id, "targz");
+
+    // we swapped our decompressor stack, so now tar.gz should not work
+    let res = assertion("bare.tar.gz", "bar_no_go", |from, to| {
+        dec.decompress(from, to, &extract_opts)
+    });
+
+    match res {
+        Err(DecompressError::MissingCompressor) => {}
+        _ => panic!("should have not decompressed"),
+    }
+}
+
+#[rstest]
+#[case("data.gz", "untar_gz", &["gz", "tarball"])]
+#[case("sub.txt.gz", "untar_gz_plain", &["gz"])]
+fn test_auto_untar_gz(#[case] archive: &str, #[case] outdir: &str, #[case] chain: &[&str]) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .auto_untar_gz(true)
+        .build()
+        .unwrap();
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+    assert_eq!(res.id, "gz");
+    assert_eq!(res.format_chain, chain);
+}
+
+#[rstest]
+#[case("hello.deb", "format_chain_deb", &["ar", "tarxz"])]
+#[case("inner.tar.gz", "format_chain_tgz", &["targz"])]
+#[case("inner.zip", "format_chain_zip", &["zip"])]
+fn test_format_chain(#[case] archive: &str, #[case] outdir: &str, #[case] chain: &[&str]) {
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.format_chain, chain);
+}
+
+#[test]
+fn test_auto_untar_gz_off() {
+    // without the option, the tarball is written out as is
+    let res = Decompress::default()
+        .decompress(
+            "tests/fixtures/data.gz",
+            "tests/out/untar_gz_off",
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files, vec!["tests/out/untar_gz_off/data"]);
+}
+
+#[rstest]
+#[case("bare.tar.gz", "bare_ext_tgz_0", "targz")]
+#[case("bare.zip", "bare_ext_zip_0", "zip")]
+fn test_allowed_extensions(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let skipped = Rc::new(RefCell::new(vec![]));
+    let seen = skipped.clone();
+    let extract_opts = ExtractOptsBuilder::default()
+        .allowed_extensions(Some(["SH".to_string()].into()))
+        .on_entry(move |info| {
+            if !info.extracted {
+                seen.borrow_mut().push(info.path.parts().join("/"));
+            }
+        })
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+    assert_eq!(*skipped.borrow(), vec!["root.txt"]);
+}
+
+#[rstest]
+#[case("folders.tar.gz", true, true)]
+#[case("folders.zip", true, true)]
+#[case("folders.zip", false, true)]
+#[case("folders.lzh", true, false)]
+fn test_precompute_total(#[case] archive: &str, #[case] precompute: bool, #[case] sized: bool) {
+    let totals = Rc::new(RefCell::new(vec![]));
+    let seen = totals.clone();
+    let out = format!("tests/out/precompute_total_{archive}_{precompute}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out,
+            &ExtractOptsBuilder::default()
+                .precompute_total(precompute)
+                .on_entry(move |info| seen.borrow_mut().push(info.total_expected))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let expected = res.file_infos.iter().map(|file| file.size).sum::<u64>();
+    assert!(!totals.borrow().is_empty());
+    assert!(totals
+        .borrow()
+        .iter()
+        .all(|total| *total == (precompute && sized).then_some(expected)));
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_content_filter(#[case] archive: &str) {
+    let seen = Rc::new(RefCell::new(vec![]));
+    let heads = seen.clone();
+    let out = format!("tests/out/content_filter_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .filter(|path| !path.ends_with("root.txt"))
+                .content_filter(move |path, head| {
+                    heads
+                        .borrow_mut()
+                        .push((path.parts().join("/"), head.len()));
+                    head.starts_with(b"#!")
+                })
+                .content_filter_prefix(4)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files, vec![format!("{out}/folder-1/ex.sh")]);
+    // the peeked bytes are written along with the rest
+    assert_eq!(
+        fs::read(format!("{out}/folder-1/ex.sh")).unwrap(),
+        fs::read("tests/expected/folders_zip_0/folder-1/ex.sh").unwrap()
+    );
+    assert!(!Path::new(&out).join("folder-1/sub.txt").exists());
+    let mut seen = seen.borrow().clone();
+    seen.sort();
+    assert_eq!(
+        seen,
+        vec![
+            ("folder-1/ex.sh".to_string(), 4),
+            ("folder-1/sub.txt".to_string(), 4)
+        ]
+    );
+}
+
+#[rstest]
+#[case("folders.tar.gz", "folders_tgz_0")]
+#[case("folders.zip", "folders_zip_0")]
+#[case("folders.lzh", "lha_folders")]
+#[case("version.rar", "rar_1")]
+fn test_atomic_writes(#[case] archive: &str, #[case] expected: &str) {
+    let out = format!("tests/out/atomic_{expected}");
+    let _ = fs::remove_dir_all(&out);
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .atomic_writes(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    // no temporary files or folders are left behind
+    assert!(Comparison::default()
+        .compare(
+            Path::new(&out),
+            Path::new(&format!("tests/expected/{expected}"))
+        )
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_atomic_writes_failed() {
+    let out = "tests/out/atomic_badcrc";
+    let _ = fs::remove_dir_all(out);
+    let res = Decompress::default().decompress(
+        "tests/fixtures/badcrc.zip",
+        out,
+        &ExtractOptsBuilder::default()
+            .atomic_writes(true)
+            .build()
+            .unwrap(),
+    );
+    assert!(res.is_err());
+    let mut pending = vec![Path::new(out).to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            assert!(path.is_dir(), "left behind {}", path.display());
+            pending.push(path);
+        }
+    }
+}
+
+#[rstest]
+#[case("case.tar")]
+#[case("case.zip")]
+fn test_case_collision(#[case] archive: &str) {
+    let decompress = |policy, assume_insensitive| {
+        let out = format!("tests/out/case_{archive}_{policy:?}_{assume_insensitive}");
+        let _ = fs::remove_dir_all(&out);
+        let res = Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .case_collision(policy)
+                .assume_case_insensitive(assume_insensitive)
+                .build()
+                .unwrap(),
+        );
+        (res, out)
+    };
+
+    // the probe finds the tests' output folder case-sensitive
+    #[cfg(target_os = "linux")]
+    {
+        let (res, _) = decompress(CaseCollisionPolicy::Error, false);
+        assert_eq!(res.unwrap().files.len(), 2);
+    }
+
+    let (res, _) = decompress(CaseCollisionPolicy::Error, true);
+    match res {
+        Err(DecompressError::CaseCollision { a, b }) => {
+            assert!(a.ends_with("docs/README"));
+            assert!(b.ends_with("docs/readme"));
+        }
+        res => panic!("should have collided, got {res:?}"),
+    }
+
+    let (res, out) = decompress(CaseCollisionPolicy::Rename, true);
+    assert_eq!(res.unwrap().files.len(), 2);
+    assert_eq!(
+        fs::read_to_string(format!("{out}/docs/README")).unwrap(),
+        "upper\n"
+    );
+    assert_eq!(
+        fs::read_to_string(format!("{out}/docs/readme (1)")).unwrap(),
+        "lower\n"
+    );
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_only_if_newer(#[case] archive: &str) {
+    let out = format!("tests/out/only_if_newer_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    let decompress = || {
+        Decompress::default()
+            .decompress(
+                format!("tests/fixtures/{archive}"),
+                out.clone(),
+                &ExtractOptsBuilder::default()
+                    .only_if_newer(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+    };
+    assert_eq!(decompress().files.len(), 3);
+
+    // written just now, so newer than the archive's files
+    let res = decompress();
+    assert!(res.files.is_empty());
+    assert_eq!(res.skipped.len(), 3);
+
+    // older than its entry, and missing
+    filetime::set_file_mtime(
+        format!("{out}/root.txt"),
+        filetime::FileTime::from_unix_time(0, 0),
+    )
+    .unwrap();
+    fs::remove_file(format!("{out}/folder-1/sub.txt")).unwrap();
+    let res = decompress();
+    let mut files = res.files.clone();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![format!("{out}/folder-1/sub.txt"), format!("{out}/root.txt")]
+    );
+    assert_eq!(res.skipped, vec![format!("{out}/folder-1/ex.sh")]);
+}
+
+#[rstest]
+#[case("folders.tar.gz")]
+#[case("folders.zip")]
+fn test_max_extract_depth(#[case] archive: &str) {
+    let out = format!("tests/out/max_extract_depth_{archive}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .max_extract_depth(Some(1))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files, vec![format!("{out}/root.txt")]);
+    let mut skipped = res.skipped;
+    skipped.sort();
+    assert_eq!(
+        skipped,
+        vec![
+            format!("{out}/folder-1/ex.sh"),
+            format!("{out}/folder-1/sub.txt")
+        ]
+    );
+    assert!(Path::new(&out).join("folder-1").is_dir());
+    assert_eq!(fs::read_dir(format!("{out}/folder-1")).unwrap().count(), 0);
+
+    // with strip, depth counts from what's left
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out,
+            &ExtractOptsBuilder::default()
+                .strip(1)
+                .max_extract_depth(Some(1))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.files.len(), 2);
+    assert!(res.skipped.is_empty());
+}
+
+#[rstest]
+#[case("macos.tar", "macos_tar", "tarball")]
+#[case("macos.zip", "macos_zip", "zip")]
+fn test_skip_apple_metadata(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let skipped = Rc::new(RefCell::new(vec![]));
+    let seen = skipped.clone();
+    let extract_opts = ExtractOptsBuilder::default()
+        .skip_apple_metadata(true)
+        .on_entry(move |info| {
+            if !info.extracted {
+                seen.borrow_mut().push(info.path.parts().join("/"));
+            }
+        })
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+    assert_eq!(
+        *skipped.borrow(),
+        vec![
+            "photos/._a.txt",
+            "__MACOSX",
+            "__MACOSX/photos",
+            "__MACOSX/photos/._a.txt"
+        ]
+    );
+}
+
+#[rstest]
+#[case("folders.tar.gz", "numbered_tgz")]
+#[case("folders.zip", "numbered_zip")]
+fn test_number_entries(#[case] archive: &str, #[case] outdir: &str) {
+    let out = format!("tests/out/{outdir}");
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .number_entries(true)
+                .filter(|path| !path.ends_with("ex.sh"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.files,
+        vec![
+            format!("{out}/folder-1/00002_sub.txt"),
+            format!("{out}/00003_root.txt"),
+        ]
+    );
+    assert!(Path::new(&out).join("folder-1").is_dir());
+}
+
+#[test]
+fn test_sanitize_names() {
+    let out = "tests/out/sanitized_tar";
+    let res = Decompress::default()
+        .decompress(
+            "tests/fixtures/illegal.tar",
+            out,
+            &ExtractOptsBuilder::default()
+                .sanitize_names(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let mut renamed = vec![("bad\0nul.txt".to_string(), "bad_nul.txt".to_string())];
+    if cfg!(windows) {
+        renamed.push(("what?.txt".to_string(), "what_.txt".to_string()));
+    }
+    assert_eq!(res.renamed, renamed);
+    assert_eq!(
+        fs::read_to_string(Path::new(out).join("bad_nul.txt")).unwrap(),
+        "nul\n"
+    );
+
+    // without it, the name can't be written
+    let res = Decompress::default().decompress(
+        "tests/fixtures/illegal.tar",
+        "tests/out/unsanitized_tar",
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    assert!(res.is_err());
+}
+
+#[rstest]
+#[case("bare.tar.gz", "bare_filter_tgz_0", "targz")]
+#[case("bare.zip", "bare_filter_zip_0", "zip")]
+#[trace]
+fn test_filter(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .strip(0)
+        .filter(|path| {
+            if let Some(path) = path.to_str() {
+                return path.ends_with("ex.sh");
+            }
+            false
+        })
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+}
+
+#[rstest]
+#[case("bare.tar.gz", "bare_map_tgz_0", "targz")]
+#[case("bare.zip", "bare_map_zip_0", "zip")]
+#[trace]
+fn test_map(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .strip(0)
+        .map(|path| {
+            let mut path = path.to_path_buf();
+            path.set_file_name(format!(
+                "abc-{}",
+                path.file_name().unwrap().to_str().unwrap()
+            ));
+            path.into()
+        })
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+}
+
+#[rstest]
+#[case("bare.tar.gz", "bare_map_opt_tgz_0", "targz")]
+#[case("bare.zip", "bare_map_opt_zip_0", "zip")]
+#[trace]
+fn test_map_opt(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+    let extract_opts = ExtractOptsBuilder::default()
+        .strip(0)
+        .map_opt(|path| {
+            let name = path.file_name()?.to_str()?;
+            if name.ends_with(".txt") {
+                return None;
+            }
+            Some(path.with_file_name(format!("abc-{name}")).into())
+        })
+        .build()
+        .unwrap();
+
+    let res = assertion(archive, outdir, |from, to| {
+        Decompress::default().decompress(from, to, &extract_opts)
+    })
+    .unwrap();
+
+    assert_eq!(res.id, id);
+    assert_eq!(res.files.len(), 1);
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case("sparse-gnu.tar", "sparse_gnu_holes")]
+#[case("sparse-pax.tar", "sparse_pax_holes")]
+fn test_sparse_holes(#[case] archive: &str, #[case] outdir: &str) {
+    use std::os::unix::fs::MetadataExt;
+
+    let out = format!("tests/out/{outdir}");
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+
+    let meta = fs::metadata(format!("{out}/disk.img")).unwrap();
+    assert_eq!(meta.len(), 65540);
+    assert!(meta.blocks() * 512 < meta.len());
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case("folders.tar.gz", "dir_mode_tgz")]
+#[case("folders.zip", "dir_mode_zip")]
+#[case("version.rar", "dir_mode_rar")]
+fn test_dir_mode(#[case] archive: &str, #[case] outdir: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    fn assert_dir_modes(dir: &Path) {
+        assert_eq!(
+            fs::metadata(dir).unwrap().permissions().mode() & 0o7777,
+            0o750,
+            "{}",
+            dir.display()
+        );
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                assert_dir_modes(&path);
+            }
+        }
+    }
+
+    let out = Path::new("tests/out").join(outdir);
+    let _ = fs::remove_dir_all(&out);
+    Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures").join(archive),
+            out.join("nested"),
+            &ExtractOptsBuilder::default()
+                .dir_mode(Some(0o750))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_dir_modes(&out);
+}
+
+#[rstest]
+#[case("folders.tar.gz", "preserve_mtime_tgz", 1_669_538_222, 1_669_538_202)]
+#[case("folders.zip", "preserve_mtime_zip", 1_669_545_422, 1_669_545_402)]
+fn test_preserve_mtime(
+    #[case] archive: &str,
+    #[case] outdir: &str,
+    #[case] dir_mtime: i64,
+    #[case] file_mtime: i64,
+) {
+    let out = format!("tests/out/{outdir}");
+    if Path::new(&out).exists() {
+        fs::remove_dir_all(&out).unwrap();
+    }
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .preserve_mtime(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+    let mtime = |p: &str| {
+        filetime::FileTime::from_last_modification_time(&fs::metadata(p).unwrap()).unix_seconds()
+    };
+    assert_eq!(mtime(&format!("{out}/folder-1")), dir_mtime);
+    assert_eq!(mtime(&format!("{out}/folder-1/ex.sh")), file_mtime);
+}
+
+#[test]
+fn test_malformed_mtime() {
+    let out = Path::new("tests/out/malformed_mtime");
+    let _ = fs::remove_dir_all(out);
+    let res = Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures/bad-mtime.tar"),
+            out,
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.file_infos[0].mtime, None);
+    assert_eq!(fs::read(out.join("bad-mtime.txt")).unwrap(), b"hello\n");
+
+    let res = Decompress::default().decompress(
+        Path::new("tests/fixtures/bad-mtime.tar"),
+        &out.join("preserved"),
+        &ExtractOptsBuilder::default()
+            .preserve_mtime(true)
+            .build()
+            .unwrap(),
+    );
+    assert!(res.is_err());
+}
+
+#[rstest]
+#[case("folders.tar.gz", "set_all_mtimes_tgz")]
+#[case("folders.zip", "set_all_mtimes_zip")]
+#[case("version.rar", "set_all_mtimes_rar")]
+fn test_set_all_mtimes(#[case] archive: &str, #[case] outdir: &str) {
+    fn assert_mtimes(dir: &Path) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let meta = fs::symlink_metadata(&path).unwrap();
+            assert_eq!(
+                filetime::FileTime::from_last_modification_time(&meta).unix_seconds(),
+                1_600_000_000,
+                "{}",
+                path.display()
+            );
+            if meta.is_dir() {
+                assert_mtimes(&path);
+            }
+        }
+    }
+
+    let out = Path::new("tests/out").join(outdir);
+    let _ = fs::remove_dir_all(&out);
+    Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures").join(archive),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .preserve_mtime(true)
+                .set_all_mtimes(Some(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+                ))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_mtimes(&out);
+}
+
+#[rstest]
+#[case("unsafe.tar", "../escape.txt")]
+#[case("absolute.tar", "/tmp/absolute.txt")]
+#[case("unsafe.zip", "../escape.txt")]
+fn test_reject_unsafe_paths(#[case] archive: &str, #[case] unsafe_path: &str) {
+    let from = format!("tests/fixtures/{archive}");
+    let extract_opts = ExtractOptsBuilder::default()
+        .reject_unsafe_paths(true)
+        .build()
+        .unwrap();
+
+    match Decompress::default().list(&from, &extract_opts) {
+        Err(DecompressError::PathEscape(path)) => assert_eq!(path, unsafe_path),
+        res => panic!("should have rejected listing, got {res:?}"),
+    }
+    match Decompress::default().decompress(
+        from.as_str(),
+        &format!("tests/out/reject_{archive}"),
+        &extract_opts,
+    ) {
+        Err(DecompressError::PathEscape(path)) => assert_eq!(path, unsafe_path),
+        res => panic!("should have rejected decompressing, got {res:?}"),
+    }
+}
+
+#[rstest]
+#[case("inner.tar", "transform_tar")]
+#[case("inner.zip", "transform_zip")]
+fn test_transform(#[case] archive: &str, #[case] outdir: &str) {
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &ExtractOptsBuilder::default()
+                .transform(|path, contents| {
+                    if path.as_path().ends_with("sub.txt") {
+                        *contents = String::from_utf8_lossy(contents)
+                            .replace("subfolder", "templated folder")
+                            .into_bytes();
+                    }
+                })
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(format!("tests/out/{outdir}/folder-1/sub.txt")).unwrap(),
+        "I'm in a templated folder\n"
+    );
+    assert_eq!(
+        fs::read(format!("tests/out/{outdir}/folder-1/ex.sh")).unwrap(),
+        fs::read("tests/fixtures/inner/folder-1/ex.sh").unwrap()
+    );
+    let sub = res
+        .file_infos
+        .iter()
+        .find(|f| f.path.ends_with("sub.txt"))
+        .unwrap();
+    assert_eq!(sub.size, 26);
+}
+
+#[rstest]
+#[case("folders.tar.gz", "on_entry_tgz")]
+#[case("folders.zip", "on_entry_zip")]
+fn test_on_entry(#[case] archive: &str, #[case] outdir: &str) {
+    let seen = Rc::new(RefCell::new(vec![]));
+    let seen_ref = seen.clone();
+    let extract_opts = ExtractOptsBuilder::default()
+        .filter(|path| !path.ends_with("root.txt"))
+        .on_entry(move |info| {
+            seen_ref.borrow_mut().push((
+                info.path.as_path().to_string_lossy().to_string(),
+                info.kind,
+                info.size,
+                info.extracted,
+            ));
+        })
+        .build()
+        .unwrap();
+
+    Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &extract_opts,
+        )
+        .unwrap();
+
+    let seen = seen.borrow();
+    assert_eq!(
+        *seen,
+        vec![
+            ("folder-1/".to_string(), RelPathKind::Dir, Some(0), true),
+            (
+                "folder-1/ex.sh".to_string(),
+                RelPathKind::File,
+                Some(23),
+                true
+            ),
+            (
+                "folder-1/sub.txt".to_string(),
+                RelPathKind::File,
+                Some(19),
+                true
+            ),
+            ("root.txt".to_string(), RelPathKind::File, Some(21), false),
+        ]
+    );
+}
+
+#[test]
+fn test_reuse_cloned_opts() {
+    let extract_opts = ExtractOptsBuilder::default()
+        .filter(|path| path.ends_with("ex.sh"))
+        .build()
+        .unwrap();
+
+    for (archive, outdir) in [
+        ("bare.tar.gz", "clone_filter_tgz"),
+        ("bare.zip", "clone_filter_zip"),
+    ] {
+        let opts = extract_opts.clone();
+        assertion(archive, outdir, |from, to| {
+            Decompress::default().decompress(from, to, &opts)
+        })
+        .unwrap();
+    }
+}
+
+#[cfg(all(unix, feature = "xattr"))]
+#[test]
+fn test_preserve_xattrs() {
+    let out = "tests/out/preserve_xattrs";
+    Decompress::default()
+        .decompress(
+            "tests/fixtures/xattrs.tar",
+            out,
+            &ExtractOptsBuilder::default()
+                .preserve_xattrs(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        xattr::get(format!("{out}/root.txt"), "user.comment").unwrap(),
+        Some(b"hello xattr".to_vec())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preserve_ownership() {
+    use std::os::unix::fs::MetadataExt;
+
+    let out = "tests/out/preserve_ownership";
+    fs::create_dir_all(out).unwrap();
+    // without privilege the chown is skipped and files stay ours
+    let uid = fs::metadata(out).unwrap().uid();
+    Decompress::default()
+        .decompress(
+            "tests/fixtures/inner.tar",
+            out,
+            &ExtractOptsBuilder::default()
+                .preserve_ownership(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+    let meta = fs::metadata(format!("{out}/folder-1/sub.txt")).unwrap();
+    if uid == 0 {
+        assert_eq!((meta.uid(), meta.gid()), (501, 20));
+    } else {
+        assert_eq!(meta.uid(), uid);
+    }
+}
+
+#[test]
+fn test_symlinks_dereference() {
+    assertion("symlinks.tar", "symlinks_deref", |from, to| {
+        Decompress::default().decompress(
+            from,
+            to,
+            &ExtractOptsBuilder::default()
+                .symlinks(SymlinkPolicy::Dereference)
+                .build()
+                .unwrap(),
+        )
+    })
+    .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks_create() {
+    let out = "tests/out/symlinks_create";
+    Decompress::default()
+        .decompress(
+            "tests/fixtures/symlinks.tar",
+            out,
+            &ExtractOptsBuilder::default()
+                .symlinks(SymlinkPolicy::Create)
+                // it links out of the output folder
+                .filter(|path| !path.ends_with("escape.txt"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        fs::read_link(format!("{out}/links/sub/up.txt")).unwrap(),
+        Path::new("../a.txt")
+    );
+    assert_eq!(
+        fs::read_to_string(format!("{out}/links/later.txt")).unwrap(),
+        "z\n"
+    );
+
+    // a relative link out of the output folder fails right away, without
+    // `validate_symlinks_after`
+    let res = Decompress::default().decompress(
+        "tests/fixtures/symlinks.tar",
+        "tests/out/symlinks_create_escape",
+        &ExtractOptsBuilder::default()
+            .symlinks(SymlinkPolicy::Create)
+            .build()
+            .unwrap(),
+    );
+    match res {
+        Err(DecompressError::PathEscape(entry)) => assert_eq!(entry, "links/escape.txt"),
+        res => panic!("expected PathEscape, got {res:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case("symlink-traversal.tar", "symlinks_traversal", "link")]
+#[case("symlink-through.tar", "symlinks_through", "link/pwned.txt")]
+fn test_symlink_traversal(#[case] archive: &str, #[case] out: &str, #[case] escaping: &str) {
+    let res = Decompress::default().decompress(
+        format!("tests/fixtures/{archive}"),
+        format!("tests/out/{out}"),
+        &ExtractOptsBuilder::default()
+            .symlinks(SymlinkPolicy::Create)
+            .absolute_symlinks(AbsoluteSymlinkPolicy::Create)
+            .reject_unsafe_paths(true)
+            .build()
+            .unwrap(),
+    );
+    match res {
+        Err(DecompressError::PathEscape(entry)) => assert_eq!(entry, escaping),
+        res => panic!("expected PathEscape, got {res:?}"),
+    }
+    assert!(!Path::new("tests/out/escaped/pwned.txt").exists());
+    assert!(!Path::new("/tmp/decompress-symlink-through/pwned.txt").exists());
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case("symlink-overwrite.tar", "symlinks_overwrite", false)]
+#[case("symlink-overwrite-tmp.tar", "symlinks_overwrite_tmp", true)]
+fn test_symlink_overwrite(#[case] archive: &str, #[case] out: &str, #[case] atomic: bool) {
+    // `t` (or the file `atomic_writes` writes it aside to) is first a link that only
+    // escapes through `d/s -> ..`, then a file written through it
+    let out = format!("tests/out/{out}");
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default().decompress(
+        format!("tests/fixtures/{archive}"),
+        format!("{out}/root"),
+        &ExtractOptsBuilder::default()
+            .symlinks(SymlinkPolicy::Create)
+            .atomic_writes(atomic)
+            .build()
+            .unwrap(),
+    );
+    assert!(!Path::new(&out).join("victim.txt").exists());
+    if atomic {
+        res.unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{out}/root/t")).unwrap(),
+            "pwned\n"
+        );
+    } else {
+        match res {
+            Err(DecompressError::PathEscape(entry)) => assert_eq!(entry, "t"),
+            res => panic!("expected PathEscape, got {res:?}"),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case("symlink-chain.tar", "symlinks_validate_chain", true)]
+#[case("symlink-escape.tar", "symlinks_validate_escape", false)]
+#[case("symlinks.tar", "symlinks_validate_parent", false)]
+fn test_validate_symlinks_after(#[case] archive: &str, #[case] out: &str, #[case] ok: bool) {
+    let res = Decompress::default().decompress(
+        format!("tests/fixtures/{archive}"),
+        format!("tests/out/{out}"),
+        &ExtractOptsBuilder::default()
+            .symlinks(SymlinkPolicy::Create)
+            .validate_symlinks_after(true)
+            .build()
+            .unwrap(),
+    );
+    if ok {
+        res.unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("tests/out/{out}/a")).unwrap(),
+            fs::read_to_string(format!("tests/out/{out}/c.txt")).unwrap()
+        );
+    } else {
+        assert!(matches!(res, Err(DecompressError::PathEscape(_))));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_absolute_symlinks() {
+    let decompress = |out: &str, policy| {
+        let out = format!("tests/out/{out}");
+        let _ = fs::remove_dir_all(&out);
+        Decompress::default()
+            .decompress(
+                "tests/fixtures/absolute-link.tar".to_string(),
+                out.clone(),
+                &ExtractOptsBuilder::default()
+                    .symlinks(SymlinkPolicy::Create)
+                    .validate_symlinks_after(true)
+                    .absolute_symlinks(policy)
+                    .build()
+                    .unwrap(),
+            )
+            .map(|_| out)
+    };
+
+    let out = decompress("absolute_symlinks_skip", AbsoluteSymlinkPolicy::Skip).unwrap();
+    assert!(Path::new(&out).join("libc.so").symlink_metadata().is_err());
+    assert_eq!(
+        fs::read_link(format!("{out}/to-a.txt")).unwrap(),
+        Path::new("a.txt")
+    );
+
+    let out = decompress("absolute_symlinks_create", AbsoluteSymlinkPolicy::Create).unwrap();
+    assert_eq!(
+        fs::read_link(format!("{out}/libc.so")).unwrap(),
+        Path::new("/usr/lib/libc.so.6")
+    );
+
+    match decompress("absolute_symlinks_error", AbsoluteSymlinkPolicy::Error) {
+        Err(DecompressError::PathEscape(entry)) => assert_eq!(entry, "libc.so"),
+        res => panic!("expected PathEscape, got {res:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rewrite_symlink_target() {
+    let decompress = |out: &str, to: &'static str| {
+        let out = format!("tests/out/{out}");
+        let _ = fs::remove_dir_all(&out);
+        Decompress::default()
+            .decompress(
+                "tests/fixtures/absolute-link.tar".to_string(),
+                out.clone(),
+                &ExtractOptsBuilder::default()
+                    .symlinks(SymlinkPolicy::Create)
+                    .absolute_symlinks(AbsoluteSymlinkPolicy::Create)
+                    .rewrite_symlink_target(move |path, target| {
+                        if path.as_path() == Path::new("libc.so") {
+                            to.into()
+                        } else {
+                            target.to_path_buf()
+                        }
+                    })
+                    .build()
+                    .unwrap(),
+            )
+            .map(|_| out)
+    };
+
+    let out = decompress("rewrite_symlink_target", "a.txt").unwrap();
+    assert_eq!(
+        fs::read_link(format!("{out}/libc.so")).unwrap(),
+        Path::new("a.txt")
+    );
+    assert_eq!(
+        fs::read_link(format!("{out}/to-a.txt")).unwrap(),
+        Path::new("a.txt")
+    );
+
+    match decompress("rewrite_symlink_target_escape", "../a.txt") {
+        Err(DecompressError::PathEscape(entry)) => assert_eq!(entry, "libc.so"),
+        res => panic!("expected PathEscape, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_unsupported_entry_type() {
+    let res = assertion("fifo.tar", "fifo", |from, to| {
+        Decompress::default().decompress(from, to, &ExtractOptsBuilder::default().build().unwrap())
+    })
+    .unwrap();
+    assert_eq!(res.skipped.len(), 1);
+    assert_eq!(res.warnings.len(), 1);
+    assert!(res.warnings[0].contains("Fifo"));
+    assert!(!Path::new(&res.skipped[0]).exists());
+
+    let res = assertion("fifo.tar", "fifo_strict", |from, to| {
+        Decompress::default().decompress(
+            from,
+            to,
+            &ExtractOptsBuilder::default()
+                .strict_entry_types(true)
+                .build()
+                .unwrap(),
+        )
+    });
+    match res {
+        Err(DecompressError::UnsupportedEntryType { id, entry_type }) => {
+            assert_eq!(id, "tarball");
+            assert_eq!(entry_type, "Fifo");
+        }
+        _ => panic!("should have failed on a fifo entry"),
+    }
+}
+
+#[test]
+fn test_can_decompress() {
+    assert!(Decompress::default().can_decompress("foo/bar/baz.tar.gz"));
+    assert!(!Decompress::default().can_decompress("foo/bar/baz.tar.foo"));
+}
+
+#[rstest]
+#[case("bare_zip", Some("zip"))]
+#[case("bare_tar_gz", Some("targz"))]
+#[case("bare_ar", Some("ar"))]
+#[case("sub_txt_zst", Some("zst"))]
+#[case("bare/root.txt", None)]
+fn test_detect(#[case] archive: &str, #[case] id: Option<&str>) {
+    let res = Decompress::default()
+        .detect(format!("tests/fixtures/{archive}"), true)
+        .unwrap();
+    assert_eq!(res, id);
+}
+
+#[test]
+fn test_detect_by_name() {
+    let dec = Decompress::default();
+    assert_eq!(dec.detect("tests/fixtures/bare_zip", false).unwrap(), None);
+    assert_eq!(
+        dec.detect("tests/fixtures/inner.tar.gz", false).unwrap(),
+        Some("targz")
+    );
+    // the name is enough, the file isn't opened
+    assert_eq!(dec.detect("missing/foo.zip", false).unwrap(), Some("zip"));
+}
+
+#[rstest]
+#[case("inner.tar.gz", Some("targz"), Some("targz"))]
+#[case("sub.txt.gz", Some("gz"), Some("gz"))]
+#[case("bare_zip", None, Some("zip"))]
+#[case("dict.zstdict", None, None)]
+fn test_matching_id(
+    #[case] archive: &str,
+    #[case] by_name: Option<&str>,
+    #[case] by_content: Option<&str>,
+) {
+    let archive = format!("tests/fixtures/{archive}");
+    assert_eq!(Decompress::default().matching_id(&archive), by_name);
+    assert_eq!(
+        Decompress::default().matching_id_content(&archive).unwrap(),
+        by_content
+    );
+}
+
+#[rstest]
+#[case("inner.tar.gz", "inner.gz", "targz")]
+#[case("inner.tar.bz2", "inner.bz2", "tarbz")]
+#[case("inner.tar.xz", "inner.xz", "tarxz")]
+#[case("inner.tar.zst", "inner.zst", "tarzst")]
+#[case("site.warc.gz", "site.gz", "warcgz")]
+fn test_detect_misnamed(#[case] archive: &str, #[case] renamed: &str, #[case] id: &str) {
+    let out = Path::new("tests/out/detect_misnamed");
+    fs::create_dir_all(out).unwrap();
+    let renamed = out.join(renamed);
+    fs::copy(format!("tests/fixtures/{archive}"), &renamed).unwrap();
+
+    let decompress = Decompress::default();
+    assert_ne!(decompress.matching_id(&renamed), Some(id));
+    assert_eq!(decompress.detect(&renamed, true).unwrap(), Some(id));
+    assert_eq!(
+        decompress
+            .list(
+                &renamed,
+                &ExtractOptsBuilder::default()
+                    .detect_content(true)
+                    .build()
+                    .unwrap()
+            )
+            .unwrap()
+            .id,
+        id
+    );
+}
+
+#[rstest]
+#[case("bare_zip", "zip", DetectionMethod::Content)]
+#[case("tar-gz.tzz", "targz", DetectionMethod::Content)]
+#[case("sub.txt.gz", "gz", DetectionMethod::Content)]
+#[case("inner.tar.gz", "targz", DetectionMethod::Content)]
+#[case("site.warc", "warc", DetectionMethod::Extension)]
+fn test_identify(#[case] archive: &str, #[case] id: &str, #[case] via: DetectionMethod) {
+    let res = Decompress::default()
+        .identify(format!("tests/fixtures/{archive}"))
+        .unwrap();
+    assert_eq!((res.id, res.via), (id, via));
+}
+
+#[test]
+fn test_identify_unsupported() {
+    let res = Decompress::default().identify("tests/fixtures/bare/root.txt");
+    assert!(matches!(res, Err(DecompressError::MissingCompressor)));
+}
+
+#[rstest]
+#[case("inner.tar", 3)]
+#[case("inner.zip", 3)]
+#[case("inner.tar.gz", 3)]
+#[case("inner.tar.zst", 3)]
+#[case("bare.ar", 1)]
+#[case("sub.txt.gz", 1)]
+fn test_count(#[case] archive: &str, #[case] count: usize) {
+    let target = format!("tests/fixtures/{archive}");
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    assert_eq!(
+        Decompress::default().count(&target, &extract_opts).unwrap(),
+        count
+    );
+    assert_eq!(
+        Decompress::default()
+            .list(&target, &extract_opts)
+            .unwrap()
+            .entries
+            .len(),
+        count
+    );
+}
+
+#[rstest]
+#[case("inner.zip", 46)]
+#[case("inner.tar.gz", 0)]
+fn test_summary(#[case] archive: &str, #[case] total_compressed: u64) {
+    let summary = Decompress::default()
+        .summary(
+            format!("tests/fixtures/{archive}"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(summary.entry_count, 3);
+    assert_eq!(summary.total_uncompressed, 42);
+    assert_eq!(summary.total_compressed, total_compressed);
+    let largest = summary.largest_entry.unwrap();
+    assert_eq!(largest.path.parts().join("/"), "folder-1/ex.sh");
+    assert_eq!(largest.size, Some(23));
+}
+
+#[test]
+fn test_open() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let decompress = Decompress::default();
+    let archive = decompress.open("tests/fixtures/inner.zip").unwrap();
+    assert_eq!(archive.id(), "zip");
+    assert_eq!(archive.path(), Path::new("tests/fixtures/inner.zip"));
 
-    assert_eq!(res.id, id);
+    let listing = archive.list(&extract_opts).unwrap();
+    assert_eq!(
+        listing.entries,
+        decompress
+            .list("tests/fixtures/inner.zip", &extract_opts)
+            .unwrap()
+            .entries
+    );
+    assert_eq!(archive.count(&extract_opts).unwrap(), listing.entries.len());
+
+    let out = "tests/out/open_zip";
+    let _ = fs::remove_dir_all(out);
+    assert_eq!(
+        archive
+            .extract_entry("folder-1/sub.txt", out, &extract_opts)
+            .unwrap(),
+        vec![Path::new(out).join("folder-1/sub.txt")]
+    );
+    let res = archive.extract(out, &extract_opts).unwrap();
+    assert_eq!(res.id, "zip");
+    assert!(Comparison::default()
+        .compare(Path::new(out), Path::new("tests/expected/inner_zip_0"))
+        .unwrap()
+        .is_empty());
+
+    // no extension to go by, the content decides
+    assert_eq!(
+        decompress.open("tests/fixtures/bare_tar_gz").unwrap().id(),
+        "targz"
+    );
 }
 
-#[test]
-fn test_custom() {
+#[rstest]
+#[case("inner.tar.gz", "extract_entries_tgz")]
+#[case("inner.zip", "extract_entries_zip")]
+fn test_extract_entries(#[case] archive: &str, #[case] outdir: &str) {
+    let archive = format!("tests/fixtures/{archive}");
+    let out = format!("tests/out/{outdir}");
+    let _ = fs::remove_dir_all(&out);
     let extract_opts = ExtractOptsBuilder::default().build().unwrap();
-    let dec = Decompress::build(vec![decompressors::targz::Targz::build(Some(
-        Regex::new(r"(?i)\.tzz$").unwrap(),
-    ))]);
+    let names = ["folder-1/sub.txt", "folder-1/ex.sh"]
+        .into_iter()
+        .map(String::from)
+        .collect::<HashSet<_>>();
 
-    let res = assertion("tar-gz.tzz", "custom_tar_gz_tzz", |from, to| {
-        dec.decompress(from, to, &extract_opts)
-    })
-    .unwrap();
-    assert_eq!(res.id, "targz");
+    let paths = Decompress::default()
+        .extract_entries(archive.clone(), &names, out.clone(), &extract_opts)
+        .unwrap();
+    assert_eq!(
+        paths,
+        vec![
+            Path::new(&out).join("folder-1/ex.sh"),
+            Path::new(&out).join("folder-1/sub.txt")
+        ]
+    );
 
-    // we swapped our decompressor stack, so now tar.gz should not work
-    let res = assertion("bare.tar.gz", "bar_no_go", |from, to| {
-        dec.decompress(from, to, &extract_opts)
-    });
+    let names = ["folder-1/sub.txt", "nope.txt"]
+        .into_iter()
+        .map(String::from)
+        .collect::<HashSet<_>>();
+    let res = Decompress::default().extract_entries(archive, &names, out, &extract_opts);
+    assert!(
+        matches!(res, Err(DecompressError::MissingEntries(missing)) if missing == vec!["nope.txt"])
+    );
+}
+
+#[rstest]
+#[case("encrypted.zip", true)]
+#[case("inner.zip", false)]
+#[case("version.rar", false)]
+#[case("inner.tar.gz", false)]
+fn test_is_encrypted(#[case] archive: &str, #[case] encrypted: bool) {
+    assert_eq!(
+        Decompress::default()
+            .is_encrypted(format!("tests/fixtures/{archive}"))
+            .unwrap(),
+        encrypted
+    );
+}
+
+#[rstest]
+#[case("encrypted.zip", "zip", 2, None, true, Some(10))]
+#[case("comments.zip", "zip", 2, Some("provenance bundle"), false, Some(20))]
+#[case("inner.tar.gz", "targz", 3, None, false, None)]
+#[case("version.rar", "rar", 1, None, false, None)]
+fn test_metadata(
+    #[case] archive: &str,
+    #[case] id: &str,
+    #[case] entry_count: usize,
+    #[case] comment: Option<&str>,
+    #[case] encrypted: bool,
+    #[case] version_needed: Option<u16>,
+) {
+    let metadata = Decompress::default()
+        .metadata(format!("tests/fixtures/{archive}"))
+        .unwrap();
+    assert_eq!(metadata.id, id);
+    assert_eq!(metadata.entry_count, entry_count);
+    assert_eq!(metadata.comment.as_deref(), comment);
+    assert_eq!(metadata.encrypted, encrypted);
+    assert_eq!(metadata.version_needed, version_needed);
+}
+
+#[test]
+fn test_predicate_metadata() {
+    let dec = Decompress::build(vec![decompressors::predicate::Predicate::build(
+        decompressors::zip::Zip::build(None),
+        |path| path.extension().map_or(false, |ext| ext == "zip"),
+    )]);
+    let metadata = dec.metadata("tests/fixtures/comments.zip").unwrap();
+    assert_eq!(metadata.comment.as_deref(), Some("provenance bundle"));
+    assert_eq!(metadata.version_needed, Some(20));
+}
 
+#[test]
+fn test_zip_unsupported_feature() {
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let res = Decompress::default().decompress(
+        "tests/fixtures/multidisk.zip",
+        "tests/out/zip_multidisk",
+        &opts,
+    );
     match res {
-        Err(DecompressError::MissingCompressor) => {}
-        _ => panic!("should have not decompressed"),
+        Err(DecompressError::UnsupportedFeature(msg)) => assert!(msg.contains("multi-disk")),
+        res => panic!("should have been unsupported, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_tar_multivolume() {
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    let res = Decompress::default().decompress(
+        "tests/fixtures/multivol.tar",
+        "tests/out/tar_multivol",
+        &opts,
+    );
+    match res {
+        Err(DecompressError::UnsupportedFeature(msg)) => {
+            assert_eq!(msg, "GNU multi-volume tar");
+        }
+        res => panic!("should have been unsupported, got {res:?}"),
     }
 }
 
 #[rstest]
-#[case("bare.tar.gz", "bare_filter_tgz_0", "targz")]
-#[case("bare.zip", "bare_filter_zip_0", "zip")]
-#[trace]
-fn test_filter(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
-    let extract_opts = ExtractOptsBuilder::default()
-        .strip(0)
-        .filter(|path| {
-            if let Some(path) = path.to_str() {
-                return path.ends_with("ex.sh");
-            }
-            false
-        })
+#[case("streamable.zip", "zip_streaming")]
+#[case("empty-dirs.zip", "zip_streaming_empty_dirs")]
+fn test_zip_streaming(#[case] archive: &str, #[case] outdir: &str) {
+    let from = Path::new("tests/fixtures").join(archive);
+    let out = Path::new("tests/out").join(outdir);
+    if out.exists() {
+        fs::remove_dir_all(&out).unwrap();
+    }
+    let opts = ExtractOptsBuilder::default().build().unwrap();
+    Decompress::default()
+        .decompress(&from, &out.join("central"), &opts)
+        .unwrap();
+
+    let opts = ExtractOptsBuilder::default()
+        .zip_streaming(true)
         .build()
         .unwrap();
+    Decompress::default()
+        .decompress(&from, &out.join("streamed"), &opts)
+        .unwrap();
+    let res = Decompress::default()
+        .decompress_reader(
+            fs::File::open(&from).unwrap(),
+            &from,
+            &out.join("reader"),
+            &opts,
+        )
+        .unwrap();
+    assert_eq!(res.id, "zip");
 
-    let res = assertion(archive, outdir, |from, to| {
-        Decompress::default().decompress(from, to, &extract_opts)
-    })
-    .unwrap();
+    for dir in ["streamed", "reader"] {
+        let diff = Comparison::default()
+            .compare(&out.join("central"), &out.join(dir))
+            .unwrap();
+        assert!(diff.is_empty(), "{dir}: {diff:?}");
+    }
+}
 
-    assert_eq!(res.id, id);
+#[test]
+fn test_zip_streaming_data_descriptor() {
+    // sizes of entries written with a data descriptor are only known past their data
+    let opts = ExtractOptsBuilder::default()
+        .zip_streaming(true)
+        .build()
+        .unwrap();
+    let res = Decompress::default().decompress(
+        "tests/fixtures/bare.zip",
+        "tests/out/zip_streaming_bare",
+        &opts,
+    );
+    assert!(matches!(res, Err(DecompressError::UnsupportedFeature(_))));
+}
+
+/// Writes a zip64 archive with a member over 4 GiB, and about as much again when
+/// extracting it, so it only runs on request
+#[test]
+#[ignore = "writes over 8 GiB"]
+fn test_zip64_large_file() {
+    use std::io::Write;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    const SIZE: u64 = (1 << 32) + 1024;
+    let out = Path::new("tests/out/zip64_large");
+    fs::create_dir_all(out).unwrap();
+    let archive = out.join("large.zip");
+    let mut writer = ZipWriter::new(fs::File::create(&archive).unwrap());
+    writer
+        .start_file(
+            "large.bin",
+            FileOptions::default()
+                .compression_method(CompressionMethod::Stored)
+                .large_file(true),
+        )
+        .unwrap();
+    let chunk = vec![0u8; 1 << 20];
+    let mut left = SIZE;
+    while left > 0 {
+        let n = usize::try_from(left).map_or(chunk.len(), |left| left.min(chunk.len()));
+        writer.write_all(&chunk[..n]).unwrap();
+        left -= n as u64;
+    }
+    writer.finish().unwrap();
+
+    let res = Decompress::default()
+        .decompress(
+            archive.as_path(),
+            out.join("extracted").as_path(),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.file_infos[0].size, SIZE);
+    assert_eq!(
+        fs::metadata(out.join("extracted/large.bin")).unwrap().len(),
+        SIZE
+    );
+    fs::remove_dir_all(out).unwrap();
 }
 
 #[rstest]
-#[case("bare.tar.gz", "bare_map_tgz_0", "targz")]
-#[case("bare.zip", "bare_map_zip_0", "zip")]
-#[trace]
-fn test_map(#[case] archive: &str, #[case] outdir: &str, #[case] id: &str) {
+#[case("bare.tar.gz", "on_conflict_tgz")]
+#[case("bare.zip", "on_conflict_zip")]
+fn test_on_conflict(#[case] archive: &str, #[case] outdir: &str) {
+    let out = format!("tests/out/{outdir}");
+    if Path::new(&out).exists() {
+        fs::remove_dir_all(&out).unwrap();
+    }
+    let decompress = |resolution| {
+        let conflicts = Rc::new(RefCell::new(vec![]));
+        let conflicts_ref = conflicts.clone();
+        let res = Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .on_conflict(move |path| {
+                    conflicts_ref
+                        .borrow_mut()
+                        .push(path.file_name().unwrap().to_string_lossy().to_string());
+                    resolution
+                })
+                .build()
+                .unwrap(),
+        );
+        let conflicts = conflicts.borrow().clone();
+        (res, conflicts)
+    };
+
+    // nothing to conflict with yet
+    let (res, conflicts) = decompress(ConflictResolution::Abort);
+    assert_eq!(res.unwrap().files.len(), 2);
+    assert!(conflicts.is_empty());
+
+    let (res, conflicts) = decompress(ConflictResolution::Skip);
+    assert!(res.unwrap().files.is_empty());
+    assert_eq!(conflicts, vec!["ex.sh", "root.txt"]);
+
+    let (res, _) = decompress(ConflictResolution::Rename);
+    assert_eq!(res.unwrap().files.len(), 2);
+    assert_eq!(
+        fs::read(format!("{out}/ex (1).sh")).unwrap(),
+        fs::read(format!("{out}/ex.sh")).unwrap()
+    );
+    assert!(Path::new(&format!("{out}/root (1).txt")).is_file());
+
+    let (res, _) = decompress(ConflictResolution::Rename);
+    res.unwrap();
+    assert!(Path::new(&format!("{out}/ex (2).sh")).is_file());
+
+    let (res, _) = decompress(ConflictResolution::Abort);
+    assert!(matches!(res, Err(DecompressError::Aborted(_))));
+
+    let (res, _) = decompress(ConflictResolution::Overwrite);
+    assert_eq!(res.unwrap().files.len(), 2);
+}
+
+#[rstest]
+#[case("bare.tar.gz", "wrap_in_bare_tgz", 0)]
+#[case("inner.tar.gz", "wrap_in_inner_tgz", 1)]
+#[case("bare.zip", "wrap_in_bare_zip", 0)]
+#[case("inner.zip", "wrap_in_inner_zip", 1)]
+fn test_wrap_in(#[case] archive: &str, #[case] outdir: &str, #[case] strip: usize) {
+    let out = format!("tests/out/{outdir}");
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            out.clone(),
+            &ExtractOptsBuilder::default()
+                .strip(strip)
+                .wrap_in(Some("pkg".to_string()))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert!(Path::new(&format!("{out}/pkg/ex.sh")).is_file());
+    assert!(!res.files.is_empty());
+    assert!(res
+        .files
+        .iter()
+        .all(|file| Path::new(file).starts_with(format!("{out}/pkg"))));
+}
+
+#[test]
+fn test_conflicting_opts() {
+    let err = ExtractOptsBuilder::default()
+        .map(|path| path.into())
+        .map_opt(|path| Some(path.into()))
+        .strict_ownership(true)
+        .build()
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("`map` and `map_opt`"), "{err}");
+    assert!(
+        err.contains("`strict_ownership` without `preserve_ownership`"),
+        "{err}"
+    );
+
+    assert!(ExtractOptsBuilder::default()
+        .dir_mode(Some(0o100_755))
+        .build()
+        .is_err());
+    for wrap_in in ["", "../up", "/abs"] {
+        assert!(ExtractOptsBuilder::default()
+            .wrap_in(Some(wrap_in.to_string()))
+            .build()
+            .is_err());
+    }
+    assert!(ExtractOptsBuilder::default()
+        .strict_ownership(true)
+        .preserve_ownership(true)
+        .dir_mode(Some(0o755))
+        .build()
+        .is_ok());
+}
+
+#[rstest]
+#[case("long.txt.zst", "zstd_window_zst", "long.txt")]
+#[case("long.tar.zst", "zstd_window_tzst", "folder-1/sub.txt")]
+fn test_zstd_window_log_max(#[case] archive: &str, #[case] outdir: &str, #[case] file: &str) {
+    let decompress = |window_log_max| {
+        Decompress::default().decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &ExtractOptsBuilder::default()
+                .zstd_window_log_max(window_log_max)
+                .build()
+                .unwrap(),
+        )
+    };
+
+    assert!(decompress(None).is_err());
+    decompress(Some(28)).unwrap();
+    assert!(Path::new(&format!("tests/out/{outdir}/{file}")).exists());
+}
+
+#[rstest]
+#[case("badsum.txt.zst", "checksum_zst")]
+#[case("badsum.tar.zst", "checksum_tzst")]
+fn test_zstd_checksum_mismatch(#[case] archive: &str, #[case] outdir: &str) {
     let extract_opts = ExtractOptsBuilder::default()
-        .strip(0)
-        .map(|path| {
-            let mut path = path.to_path_buf();
-            path.set_file_name(format!(
-                "abc-{}",
-                path.file_name().unwrap().to_str().unwrap()
-            ));
-            path.into()
-        })
+        .zstd_window_log_max(Some(28))
         .build()
         .unwrap();
+    let archive = format!("tests/fixtures/{archive}");
+    let res = Decompress::default().decompress(
+        archive.clone(),
+        format!("tests/out/{outdir}"),
+        &extract_opts,
+    );
+    assert!(matches!(res, Err(DecompressError::ChecksumMismatch(_))));
+    let res = Decompress::default().verify(archive, &extract_opts);
+    assert!(matches!(res, Err(DecompressError::ChecksumMismatch(_))));
+}
 
-    let res = assertion(archive, outdir, |from, to| {
-        Decompress::default().decompress(from, to, &extract_opts)
-    })
-    .unwrap();
+#[rstest]
+#[case(true, 0, "gzip_name_0", "a/b/sub.txt")]
+#[case(true, 1, "gzip_name_1", "b/sub.txt")]
+#[case(true, 5, "gzip_name_5", "original-name.txt")]
+#[case(false, 0, "gzip_name_off", "original-name.txt")]
+fn test_use_gzip_original_name(
+    #[case] enabled: bool,
+    #[case] strip: usize,
+    #[case] outdir: &str,
+    #[case] file: &str,
+) {
+    let out = Path::new("tests/out").join(outdir);
+    let _ = fs::remove_dir_all(&out);
+    let res = Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures/original-name.txt.gz"),
+            &out,
+            &ExtractOptsBuilder::default()
+                .use_gzip_original_name(enabled)
+                .strip(strip)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        res.files,
+        vec![out.join(file).to_string_lossy().to_string()]
+    );
+    assert_eq!(
+        fs::read_to_string(out.join(file)).unwrap(),
+        "I'm in a subfolder\n"
+    );
+}
 
-    assert_eq!(res.id, id);
+#[test]
+fn test_zip_comments() {
+    let decompress = Decompress::default();
+    let extract_opts = ExtractOptsBuilder::default()
+        .zip_comments_to_sidecar(true)
+        .build()
+        .unwrap();
+
+    let comments = decompress
+        .list_iter("tests/fixtures/comments.zip", &extract_opts)
+        .unwrap()
+        .map(|entry| entry.unwrap().comment)
+        .collect::<Vec<_>>();
+    assert_eq!(comments, vec![Some(r#"{"source":"a"}"#.to_string()), None]);
+    assert_eq!(
+        decompress
+            .archive_comment("tests/fixtures/comments.zip")
+            .unwrap()
+            .as_deref(),
+        Some("provenance bundle")
+    );
+    assert_eq!(
+        decompress
+            .archive_comment("tests/fixtures/inner.zip")
+            .unwrap(),
+        None
+    );
+
+    let out = Path::new("tests/out/zip_comments");
+    let _ = fs::remove_dir_all(out);
+    decompress
+        .decompress(Path::new("tests/fixtures/comments.zip"), out, &extract_opts)
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("a.txt.comment")).unwrap(),
+        r#"{"source":"a"}"#
+    );
+    assert!(!out.join("b.txt.comment").exists());
+}
+
+#[rstest]
+#[case("inner.tar.gz", "file_infos_tgz", Some(1_669_538_253))]
+#[case("inner.zip", "file_infos_zip", None)]
+#[case("sub.txt.xz", "file_infos_xz", None)]
+fn test_file_infos(#[case] archive: &str, #[case] outdir: &str, #[case] mtime: Option<u64>) {
+    let res = Decompress::default()
+        .decompress(
+            format!("tests/fixtures/{archive}"),
+            format!("tests/out/{outdir}"),
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    assert_eq!(res.file_infos.len(), res.files.len());
+    for (info, path) in res.file_infos.iter().zip(&res.files) {
+        assert_eq!(&info.path, path);
+        assert_eq!(info.size, fs::metadata(path).unwrap().len());
+        if let Some(mtime) = mtime {
+            assert_eq!(
+                info.mtime,
+                Some(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime))
+            );
+        }
+    }
+    // zip stores a DOS time, which depends on the timezone it was made in
+    assert_eq!(
+        res.file_infos.iter().all(|info| info.mtime.is_some()),
+        archive != "sub.txt.xz"
+    );
 }
 
 #[test]
-fn test_can_decompress() {
-    assert!(Decompress::default().can_decompress("foo/bar/baz.tar.gz"));
-    assert!(!Decompress::default().can_decompress("foo/bar/baz.tar.foo"));
+fn test_list_iter() {
+    let extract_opts = ExtractOptsBuilder::default().build().unwrap();
+    let entries = Decompress::default()
+        .list_iter("tests/fixtures/inner.tar.gz", &extract_opts)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.path.parts().join("/"), entry.kind, entry.size)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        vec![
+            ("folder-1".to_string(), RelPathKind::Dir, Some(0)),
+            ("folder-1/ex.sh".to_string(), RelPathKind::File, Some(23)),
+            ("folder-1/sub.txt".to_string(), RelPathKind::File, Some(19)),
+        ]
+    );
+
+    // stopping early is fine, the rest of the archive isn't read
+    let first = Decompress::default()
+        .list_iter("tests/fixtures/inner.tar.gz", &extract_opts)
+        .unwrap()
+        .next();
+    assert!(matches!(first, Some(Ok(_))));
+
+    assert!(matches!(
+        Decompress::default().count("tests/fixtures/inner.foo", &extract_opts),
+        Err(DecompressError::MissingCompressor)
+    ));
 }
 
 #[rstest]
@@ -169,7 +3331,9 @@ fn test_can_decompress() {
 #[case("inner.tar.bz2")]
 #[case("inner.tar.zst")]
 #[case("inner.tar.zst")]
-#[case("bare.ar")]
+#[case("hello.deb")]
+#[case("site.warc")]
+#[case("site.warc.gz")]
 #[case("sub.txt.gz")]
 #[case("sub.txt.bz2")]
 #[case("sub.txt.xz")]
@@ -191,6 +3355,27 @@ fn test_can_list(#[case] archive: &str) {
     );
 }
 
+#[test]
+fn test_can_list_ar() {
+    let target = "tests/fixtures/bare.ar";
+    let opts = ExtractOptsBuilder::default()
+        .detect_content(false)
+        .build()
+        .unwrap();
+    let decompress = Decompress::default();
+    assert_debug_snapshot!(
+        "can_list_bare.ar",
+        (
+            "bare.ar",
+            decompress.list(target, &opts),
+            decompress
+                .list_iter(target, &opts)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+        )
+    );
+}
+
 fn assertion(
     from: &str,
     to: &str,
@@ -213,3 +3398,187 @@ fn assertion(
 
     Ok(extraction)
 }
+
+#[rstest]
+#[case("inner.tar.gz")]
+#[case("inner.zip")]
+#[case("bare.ar")]
+#[case("hello.deb")]
+#[case("sub.txt.gz")]
+#[case("sub.txt.zst")]
+#[case("version.rar")]
+#[case("site.warc")]
+fn test_verify(#[case] archive: &str) {
+    let target = format!("tests/fixtures/{archive}");
+    Decompress::default()
+        .verify(&target, &ExtractOptsBuilder::default().build().unwrap())
+        .unwrap();
+}
+
+#[rstest]
+#[case("badcrc.tar.gz")]
+#[case("badcrc.txt.gz")]
+#[case("badcrc.zip")]
+fn test_verify_corrupt(#[case] archive: &str) {
+    let target = format!("tests/fixtures/{archive}");
+    let res =
+        Decompress::default().verify(&target, &ExtractOptsBuilder::default().build().unwrap());
+    assert!(matches!(res, Err(DecompressError::CorruptArchive(_))));
+}
+
+#[test]
+fn test_verify_continue_on_error() {
+    let res = Decompress::default().verify(
+        "tests/fixtures/badcrc.zip",
+        &ExtractOptsBuilder::default()
+            .continue_on_error(true)
+            .build()
+            .unwrap(),
+    );
+    match res {
+        Err(DecompressError::Multiple(errors)) => assert_eq!(errors.len(), 2),
+        res => panic!("expected two errors, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_tar_empty_dirs() {
+    let out = Path::new("tests/out/empty_dirs_tar");
+    if out.exists() {
+        fs::remove_dir_all(out).unwrap();
+    }
+    Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures/empty-dirs.tar"),
+            out,
+            &ExtractOptsBuilder::default()
+                .strip(1)
+                .filter(|path| !path.ends_with("skipped"))
+                .dir_mode(Some(0o750))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert!(out.join("empty").is_dir());
+    assert!(out.join("nested/deeper").is_dir());
+    assert!(!out.join("skipped").exists());
+    assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "a\n");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(out.join("empty"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o750);
+    }
+}
+
+#[test]
+fn test_zip_empty_dirs() {
+    let out = Path::new("tests/out/empty_dirs_zip");
+    if out.exists() {
+        fs::remove_dir_all(out).unwrap();
+    }
+    Decompress::default()
+        .decompress(
+            Path::new("tests/fixtures/empty-dirs.zip"),
+            out,
+            &ExtractOptsBuilder::default()
+                .strip(1)
+                .filter(|path| !path.ends_with("skipped"))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    assert!(out.join("empty").is_dir());
+    assert!(out.join("nested/deeper").is_dir());
+    assert!(!out.join("skipped").exists());
+    assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "a\n");
+
+    // the entries are stored with mode 0o600, a folder still needs to be enterable
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(out.join("empty"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
+
+#[rstest]
+#[case("sub.txt.gz", "gz_1")]
+#[case("sub.txt.bz2", "bz_2")]
+#[case("sub.txt.xz", "xz_1")]
+#[case("sub.txt.zst", "zstd_1")]
+fn test_decompress_to_writer(#[case] archive: &str, #[case] expected: &str) {
+    let mut out = vec![];
+    let written = Decompress::default()
+        .decompress_to_writer(
+            format!("tests/fixtures/{archive}"),
+            &mut out,
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    let expected = fs::read(format!("tests/expected/{expected}/sub.txt")).unwrap();
+    assert_eq!(written, expected.len() as u64);
+    assert_eq!(out, expected);
+}
+
+#[rstest]
+#[case(0, 10)]
+#[case(1540, 20)] // crosses into the second frame
+#[case(3100, 1550)] // exactly the third frame
+#[case(6190, 100)] // runs past the end
+#[case(7000, 10)]
+fn test_decompress_range_seekable(#[case] start: u64, #[case] len: u64) {
+    let text: String = (0..200)
+        .map(|i| format!("line {i:04} of the seekable file\n"))
+        .collect();
+    let mut out = vec![];
+    let written = Decompress::default()
+        .decompress_range(
+            "tests/fixtures/seekable.txt.zst",
+            start,
+            len,
+            &mut out,
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    let from = (start as usize).min(text.len());
+    let to = ((start + len) as usize).min(text.len());
+    assert_eq!(out, &text.as_bytes()[from..to]);
+    assert_eq!(written, out.len() as u64);
+}
+
+#[rstest]
+#[case("sub.txt.gz", "gz_1")]
+#[case("sub.txt.zst", "zstd_1")]
+fn test_decompress_range(#[case] archive: &str, #[case] expected: &str) {
+    let mut out = vec![];
+    let written = Decompress::default()
+        .decompress_range(
+            format!("tests/fixtures/{archive}"),
+            3,
+            5,
+            &mut out,
+            &ExtractOptsBuilder::default().build().unwrap(),
+        )
+        .unwrap();
+    let expected = fs::read(format!("tests/expected/{expected}/sub.txt")).unwrap();
+    assert_eq!(written, 5);
+    assert_eq!(out, &expected[3..8]);
+}
+
+#[test]
+fn test_decompress_to_writer_multi_file() {
+    let res = Decompress::default().decompress_to_writer(
+        "tests/fixtures/inner.tar.gz",
+        &mut io::sink(),
+        &ExtractOptsBuilder::default().build().unwrap(),
+    );
+    assert!(matches!(res, Err(DecompressError::NotSingleFile("targz"))));
+}